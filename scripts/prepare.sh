@@ -4,4 +4,5 @@ mkdir -p data/screenshots
 mkdir -p data/avatars
 mkdir -p data/beatmaps
 mkdir -p data/replays
+mkdir -p data/captures
 echo "done"
\ No newline at end of file
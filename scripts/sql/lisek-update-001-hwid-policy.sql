@@ -0,0 +1,53 @@
+--
+-- HWID policy engine: rules per hardware component, whitelist of shared machines
+-- and review flags raised at login.
+--
+-- component: mac | unique_id | disk_id
+-- action: log | flag | block | restrict
+--
+
+CREATE TABLE public."HwidPolicyRule" (
+    id serial PRIMARY KEY,
+    component text NOT NULL,
+    action text NOT NULL,
+    "minAccounts" integer DEFAULT 1 NOT NULL,
+    enabled boolean DEFAULT true NOT NULL,
+    note text DEFAULT ''::text NOT NULL
+);
+
+CREATE TABLE public."HwidWhitelist" (
+    id serial PRIMARY KEY,
+    component text NOT NULL,
+    value text NOT NULL,
+    note text DEFAULT ''::text NOT NULL,
+    "createdBy" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE RESTRICT,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE UNIQUE INDEX "HwidWhitelist_component_value_key" ON public."HwidWhitelist" USING btree (component, value);
+
+CREATE TABLE public."HwidFlag" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE RESTRICT,
+    "linkedUserIds" integer[] NOT NULL,
+    component text NOT NULL,
+    value text NOT NULL,
+    action text NOT NULL,
+    resolved boolean DEFAULT false NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "HwidFlag_userId_idx" ON public."HwidFlag" USING btree ("userId");
+
+-- Only one open flag per account and matched value, repeated logins don't add more
+CREATE UNIQUE INDEX "HwidFlag_open_key" ON public."HwidFlag" USING btree ("userId", component, value, action) WHERE NOT resolved;
+
+CREATE INDEX "Hwid_mac_idx" ON public."Hwid" USING btree (mac);
+CREATE INDEX "Hwid_uniqueId_idx" ON public."Hwid" USING btree ("uniqueId");
+CREATE INDEX "Hwid_diskId_idx" ON public."Hwid" USING btree ("diskId");
+
+-- MAC addresses collide on virtual adapters, so they are only logged by default
+INSERT INTO public."HwidPolicyRule" (component, action, "minAccounts", note) VALUES
+    ('mac', 'log', 1, 'Shared network adapter'),
+    ('unique_id', 'flag', 1, 'Shared machine id'),
+    ('disk_id', 'flag', 1, 'Shared disk serial');
@@ -0,0 +1,16 @@
+--
+-- Every successful login with the client address resolved behind trusted proxies.
+--
+-- source: bancho | oauth
+--
+
+CREATE TABLE public."LoginHistory" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    ip text,
+    source text NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "LoginHistory_userId_createdAt_idx" ON public."LoginHistory" USING btree ("userId", "createdAt" DESC);
+CREATE INDEX "LoginHistory_ip_idx" ON public."LoginHistory" USING btree (ip);
@@ -0,0 +1,16 @@
+--
+-- Login history keeps client, hardware and token details of every session.
+--
+-- grantType: password | refresh_token for oauth logins
+--
+
+ALTER TABLE public."LoginHistory"
+    ADD COLUMN country text,
+    ADD COLUMN "clientVersion" text,
+    ADD COLUMN mac text,
+    ADD COLUMN "uniqueId" text,
+    ADD COLUMN "diskId" text,
+    ADD COLUMN "clientId" integer,
+    ADD COLUMN "grantType" text;
+
+CREATE INDEX "LoginHistory_uniqueId_idx" ON public."LoginHistory" USING btree ("uniqueId");
@@ -0,0 +1,20 @@
+--
+-- Audit trail of account state changes.
+--
+-- actorId: null when the change was made by the server itself
+-- action: verification.completed | verification.held | ...
+--
+
+CREATE TABLE public."AuditLog" (
+    id serial PRIMARY KEY,
+    "actorId" integer REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    action text NOT NULL,
+    "targetId" integer REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    before jsonb,
+    after jsonb,
+    note text DEFAULT ''::text NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "AuditLog_targetId_idx" ON public."AuditLog" USING btree ("targetId");
+CREATE INDEX "AuditLog_action_createdAt_idx" ON public."AuditLog" USING btree (action, "createdAt" DESC);
@@ -0,0 +1,9 @@
+--
+-- Notifications are listed newest first, most lookups only need unseen ones.
+--
+
+ALTER TABLE public."Notifications"
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL;
+
+CREATE INDEX "Notifications_userId_createdAt_idx" ON public."Notifications" USING btree ("userId", "createdAt" DESC);
+CREATE INDEX "Notifications_userId_seen_idx" ON public."Notifications" USING btree ("userId") WHERE NOT seen;
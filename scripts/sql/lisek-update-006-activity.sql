@@ -0,0 +1,22 @@
+--
+-- Per user activity feed and notification preferences.
+--
+-- type: first_place_lost
+--
+
+CREATE TABLE public."UserActivity" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    type text NOT NULL,
+    "beatmapChecksum" text,
+    "playMode" integer,
+    "relatedUserId" integer REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "UserActivity_userId_createdAt_idx" ON public."UserActivity" USING btree ("userId", "createdAt" DESC);
+
+CREATE TABLE public."UserPreference" (
+    "userId" integer PRIMARY KEY REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    "notifyFirstPlaceLost" boolean DEFAULT true NOT NULL
+);
@@ -0,0 +1,17 @@
+--
+-- Moderation queue state of player reports.
+--
+-- status: open | claimed | resolved | dismissed
+-- reasonId: 0 other, 1 cheating, 2 multiaccounting, 3 harassment, 4 inappropriate name, 5 spam
+--
+
+ALTER TABLE public."Report"
+    ADD COLUMN status text DEFAULT 'open'::text NOT NULL,
+    ADD COLUMN "claimedBy" integer REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    ADD COLUMN resolution text,
+    ADD COLUMN "punishmentId" text REFERENCES public."Punishment"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    ADD COLUMN "resolvedAt" timestamp(3) without time zone;
+
+CREATE INDEX "Report_status_createdAt_idx" ON public."Report" USING btree (status, "createdAt");
+CREATE INDEX "Report_reportedUser_idx" ON public."Report" USING btree ("reportedUser");
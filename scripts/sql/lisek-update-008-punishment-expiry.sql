@@ -0,0 +1,8 @@
+--
+-- Time limited punishments are lifted by the worker component once, liftedAt marks them as processed.
+--
+
+ALTER TABLE public."Punishment"
+    ADD COLUMN "liftedAt" timestamp(3) without time zone;
+
+CREATE INDEX "Punishment_pending_expiry_idx" ON public."Punishment" USING btree ("expiresAt") WHERE expires AND "liftedAt" IS NULL;
@@ -0,0 +1,22 @@
+--
+-- Staff actions are written to AuditLog as well, rows are never changed afterwards.
+--
+-- action: beatmap.status | punishment.restrict | punishment.unrestrict | punishment.auto_restrict
+--         | punishment.expired | report.claimed | report.resolved | hwid.rule_updated
+--         | hwid.whitelist_added | hwid.whitelist_removed | hwid.flag_resolved | capture.toggled
+--         | verification.completed | verification.held
+--
+
+CREATE INDEX "AuditLog_actorId_createdAt_idx" ON public."AuditLog" USING btree ("actorId", "createdAt" DESC);
+CREATE INDEX "AuditLog_createdAt_idx" ON public."AuditLog" USING btree ("createdAt" DESC);
+
+CREATE FUNCTION public.audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'AuditLog is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+-- Foreign keys still null out actor and target of deleted users
+CREATE TRIGGER "AuditLog_append_only"
+    BEFORE UPDATE OF action, before, after, note, "createdAt" OR DELETE ON public."AuditLog"
+    FOR EACH ROW EXECUTE FUNCTION public.audit_log_append_only();
@@ -0,0 +1,56 @@
+--
+-- Role based permissions. Users get capabilities of every group they are member of,
+-- "Group".permissions holds capability bits:
+--
+-- 1: nominate beatmaps | 2: moderate users | 4: manage groups | 8: developer tools | 16: supporter perks
+--
+-- Old "User".permissions bits 1 (manager) and 4 (nominator) are moved to groups,
+-- bit 8 and flag 32 still mark restricted and pending verification accounts.
+-- Membership changes are audited as group.member_added | group.member_removed.
+-- Groups existing before this update have their permissions reset, old values don't map
+-- onto capability bits. Capabilities for custom groups have to be granted again.
+-- Old integer "groupId" values are kept only where a group with the same text id exists,
+-- memberships of missing groups or users and duplicate memberships are deleted.
+--
+
+ALTER TABLE public."UserGroup"
+    ALTER COLUMN "groupId" TYPE text USING "groupId"::text;
+
+DELETE FROM public."UserGroup" ug
+WHERE NOT EXISTS (SELECT 1 FROM public."Group" g WHERE g.id = ug."groupId")
+    OR NOT EXISTS (SELECT 1 FROM public."User" u WHERE u.id = ug."userId");
+
+DELETE FROM public."UserGroup" a
+USING public."UserGroup" b
+WHERE a."userId" = b."userId" AND a."groupId" = b."groupId" AND a.id < b.id;
+
+ALTER TABLE public."UserGroup"
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL;
+
+ALTER TABLE ONLY public."UserGroup"
+    ADD CONSTRAINT "UserGroup_userId_fkey" FOREIGN KEY ("userId") REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE ONLY public."UserGroup"
+    ADD CONSTRAINT "UserGroup_groupId_fkey" FOREIGN KEY ("groupId") REFERENCES public."Group"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX "UserGroup_userId_groupId_key" ON public."UserGroup" USING btree ("userId", "groupId");
+
+UPDATE public."Group" SET permissions = 0;
+
+INSERT INTO public."Group" (id, name, permissions) VALUES
+    ('nominator', 'Beatmap Nominators', 1),
+    ('moderator', 'Moderators', 2),
+    ('admin', 'Administrators', 31),
+    ('developer', 'Developers', 8),
+    ('supporter', 'Supporters', 16)
+ON CONFLICT (id) DO UPDATE SET permissions = EXCLUDED.permissions;
+
+INSERT INTO public."UserGroup" ("userId", "groupId")
+SELECT id, 'admin' FROM public."User" WHERE permissions & 1 > 0
+ON CONFLICT DO NOTHING;
+
+INSERT INTO public."UserGroup" ("userId", "groupId")
+SELECT id, 'nominator' FROM public."User" WHERE permissions & 4 > 0
+ON CONFLICT DO NOTHING;
+
+UPDATE public."User" SET permissions = permissions & ~5;
@@ -0,0 +1,32 @@
+--
+-- Badge definitions, UserBadge rows become grants of a definition. Name, icon and colour
+-- are still copied to UserBadge so profiles keep reading a single table.
+--
+-- key: set on badges assigned automatically (donor | tournament_winner)
+-- Grants are audited as badge.created | badge.granted | badge.revoked
+--
+
+CREATE TABLE public."Badge" (
+    id serial PRIMARY KEY,
+    key text,
+    name text NOT NULL,
+    icon text NOT NULL,
+    color text NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE UNIQUE INDEX "Badge_key_key" ON public."Badge" USING btree (key);
+
+ALTER TABLE public."UserBadge"
+    ADD COLUMN "badgeId" integer REFERENCES public."Badge"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    ADD COLUMN "expiresAt" timestamp(3) without time zone,
+    ADD COLUMN "grantedBy" integer REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE SET NULL,
+    ADD COLUMN note text DEFAULT ''::text NOT NULL,
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL;
+
+CREATE UNIQUE INDEX "UserBadge_userId_badgeId_key" ON public."UserBadge" USING btree ("userId", "badgeId");
+CREATE INDEX "UserBadge_badgeId_idx" ON public."UserBadge" USING btree ("badgeId");
+
+INSERT INTO public."Badge" (key, name, icon, color) VALUES
+    ('donor', 'Supporter', 'heart', '#ff66aa'),
+    ('tournament_winner', 'Tournament Winner', 'trophy', '#f5c542');
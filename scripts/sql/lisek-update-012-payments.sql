@@ -0,0 +1,14 @@
+--
+-- Donor payments. Providers may retry webhooks, a payment is stored once per provider.
+--
+-- paymentSystem: name of provider which reported the payment (dummy | ...)
+-- until: donorUntil of the user after this payment was applied
+-- expiredAt: set by the worker once donor perks of the user ran out
+--
+
+ALTER TABLE public."PaymentEntry"
+    ADD COLUMN "expiredAt" timestamp(3) without time zone;
+
+CREATE UNIQUE INDEX "PaymentEntry_paymentSystem_paymentId_key" ON public."PaymentEntry" USING btree ("paymentSystem", "paymentId");
+CREATE INDEX "PaymentEntry_userId_idx" ON public."PaymentEntry" USING btree ("userId");
+CREATE INDEX "PaymentEntry_pending_expiry_idx" ON public."PaymentEntry" USING btree ("userId") WHERE "expiredAt" IS NULL;
@@ -0,0 +1,31 @@
+--
+-- Coin shop: purchases made with coins earned from listing votes.
+--
+-- item: username_change | donor_days | background_slot
+-- price: coins deducted at the time of purchase
+-- ListingVote: one rewarded vote callback per user per day
+--
+
+ALTER TABLE public."User"
+    ADD COLUMN "backgroundSlots" integer DEFAULT 0 NOT NULL;
+
+CREATE TABLE public."ShopPurchase" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    item text NOT NULL,
+    price integer NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "ShopPurchase_userId_idx" ON public."ShopPurchase" USING btree ("userId");
+
+CREATE TABLE public."ListingVote" (
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    "votedOn" date DEFAULT CURRENT_DATE NOT NULL,
+    coins integer NOT NULL,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY ("userId", "votedOn")
+);
+
+ALTER TABLE public."User"
+    ADD CONSTRAINT "User_coins_non_negative" CHECK (coins >= 0) NOT VALID;
@@ -0,0 +1,15 @@
+--
+-- Username changes. "oldUsernames" on User is kept for profiles, this table remembers
+-- when a name was released so it can't be taken over by someone else right away.
+--
+
+CREATE TABLE public."UsernameHistory" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    username text NOT NULL,
+    "usernameSafe" text NOT NULL,
+    "changedAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "UsernameHistory_usernameSafe_idx" ON public."UsernameHistory" USING btree ("usernameSafe", "changedAt");
+CREATE INDEX "UsernameHistory_userId_idx" ON public."UsernameHistory" USING btree ("userId");
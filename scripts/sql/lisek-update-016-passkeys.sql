@@ -0,0 +1,20 @@
+--
+-- WebAuthn passkeys. A user may register several passkeys, all of them share
+-- "userIdHexed" (WebAuthn user handle). "credential" holds the public key and
+-- signature counter as serialized by the server.
+--
+-- challange: challenge of the registration ceremony, base64url
+-- credId, rawId: credential id, base64url
+--
+
+DROP INDEX IF EXISTS public."PassKeys_userIdHexed_key";
+
+ALTER TABLE public."PassKeys"
+    ADD COLUMN credential jsonb,
+    ADD COLUMN name text DEFAULT ''::text NOT NULL,
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    ADD COLUMN "lastUsedAt" timestamp(3) without time zone;
+
+CREATE INDEX "PassKeys_userIdHexed_idx" ON public."PassKeys" USING btree ("userIdHexed");
+CREATE INDEX "PassKeys_userId_idx" ON public."PassKeys" USING btree ("userId");
+CREATE UNIQUE INDEX "PassKeys_credId_key" ON public."PassKeys" USING btree ("credId");
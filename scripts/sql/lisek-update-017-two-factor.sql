@@ -0,0 +1,35 @@
+--
+-- TOTP two-factor authentication for API logins, in-game login doesn't use it.
+-- "secret" is base32, row with NULL "enabledAt" is an enrollment waiting for first code.
+-- "lastUsedStep" is the last accepted 30 second step, so a code can't be replayed.
+-- Recovery codes are stored as sha256 and work once.
+--
+-- API tokens carry "User"."tokenGeneration", enabling two-factor bumps it so tokens
+-- issued with password alone stop working. Tokens also list sign-in methods (amr).
+--
+-- Members of a group with "requireTwoFactor" lose access to staff API routes
+-- until they enable it and sign in with it. Changes are audited as group.updated.
+--
+
+CREATE TABLE public."UserTwoFactor" (
+    "userId" integer PRIMARY KEY REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    secret text NOT NULL,
+    "lastUsedStep" bigint,
+    "enabledAt" timestamp(3) without time zone,
+    "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE TABLE public."TwoFactorRecoveryCode" (
+    id serial PRIMARY KEY,
+    "userId" integer NOT NULL REFERENCES public."User"(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    "codeHash" text NOT NULL,
+    "usedAt" timestamp(3) without time zone
+);
+
+CREATE INDEX "TwoFactorRecoveryCode_userId_idx" ON public."TwoFactorRecoveryCode" USING btree ("userId");
+
+ALTER TABLE public."Group"
+    ADD COLUMN "requireTwoFactor" boolean DEFAULT false NOT NULL;
+
+ALTER TABLE public."User"
+    ADD COLUMN "tokenGeneration" integer DEFAULT 0 NOT NULL;
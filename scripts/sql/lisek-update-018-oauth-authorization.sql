@@ -0,0 +1,16 @@
+--
+-- Consent given to third-party applications by authorization_code grant.
+-- One row per user and application, "scopes" holds scopes the user approved.
+-- Authorization codes live only in redis, "code" is kept for old rows.
+--
+
+DELETE FROM public."AuthorizedUserApplication" a
+USING public."AuthorizedUserApplication" b
+WHERE a."userId" = b."userId" AND a."oauthApplicationId" = b."oauthApplicationId" AND a.id < b.id;
+
+ALTER TABLE public."AuthorizedUserApplication"
+    ALTER COLUMN code DROP NOT NULL,
+    ADD COLUMN "createdAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    ADD COLUMN "updatedAt" timestamp(3) without time zone DEFAULT CURRENT_TIMESTAMP NOT NULL;
+
+CREATE UNIQUE INDEX "AuthorizedUserApplication_userId_oauthApplicationId_key" ON public."AuthorizedUserApplication" USING btree ("userId", "oauthApplicationId");
@@ -0,0 +1,24 @@
+use axum::{routing::get, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use self::routes::get_audit_entries;
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    //User id or username
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub action: Option<String>,
+    //RFC 3339 timestamps
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub fn router() -> Router {
+    Router::new().route("/", get(get_audit_entries))
+}
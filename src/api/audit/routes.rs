@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, Extension};
+use tracing::error;
+
+use crate::{
+    api::{auth::middleware::TokenAuth, failed, require_manager, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        audit_utils::{get_audit_log, AuditEntry, AuditFilter},
+        user_utils::find_user_by_id_or_username,
+    },
+};
+
+use super::AuditQuery;
+
+async fn resolve_user<T>(
+    ctx: &Context,
+    term: Option<String>,
+) -> Result<Option<i32>, ApiResponse<T>> {
+    if term.is_none() {
+        return Ok(None);
+    }
+
+    let user = find_user_by_id_or_username(&ctx.pool, term.unwrap()).await;
+
+    if let Err(error) = user {
+        error!("Error getting user: {:?}", error);
+        return Err(failed(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error",
+        ));
+    }
+
+    match user.unwrap() {
+        Some(user) => Ok(Some(user.id)),
+        None => Err(failed(StatusCode::NOT_FOUND, "Not found")),
+    }
+}
+
+pub async fn get_audit_entries(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResponse<Vec<AuditEntry>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let actor_id = match resolve_user(&ctx, query.actor).await {
+        Ok(actor_id) => actor_id,
+        Err(response) => return response,
+    };
+
+    let target_id = match resolve_user(&ctx, query.target).await {
+        Ok(target_id) => target_id,
+        Err(response) => return response,
+    };
+
+    let filter = AuditFilter {
+        actor_id,
+        target_id,
+        action: query.action.filter(|action| !action.is_empty()),
+        since: query.since.map(|since| since.naive_utc()),
+        until: query.until.map(|until| until.naive_utc()),
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_audit_log(&ctx.pool, &filter, offset, limit).await)
+}
@@ -11,11 +11,19 @@ use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
 use hmac::{Hmac, Mac};
 use jwt::{Error, Header, SignWithKey, Token, VerifyWithKey};
 use sha2::Sha256;
+use tracing::error;
 
 use crate::{
     context::Context,
+    db::user::User,
     utils::{
-        oauth_utils::get_app_by_id,
+        ip_utils::get_client_ip,
+        lockout_utils::{
+            check_credentials, register_failed_login, reset_failed_logins, LoginCheck,
+        },
+        oauth_utils::{get_app_by_id, parse_scopes, redeem_authorization_code},
+        session_utils::{insert_login_history, LoginRecord},
+        two_factor_utils::{is_two_factor_enabled, verify_two_factor},
         user_utils::{find_user_by_id_or_username, get_user_by_id},
     },
 };
@@ -62,71 +70,18 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
     }
 
     let body = body.unwrap();
-
-    let app = get_app_by_id(&context.pool, body.client_id.parse::<i32>().unwrap_or(0)).await;
-
-    match app {
-        Ok(app) => {
-            if app.is_none() {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(AccessTokenResponse {
-                        access_token: None,
-                        refresh_token: None,
-                        expires_in: None,
-                        token_type: None,
-                        error: Some("client_id".to_string()),
-                        hint: Some("client_id".to_string()),
-                        message: Some("client_id is incorrect".to_string()),
-                    }),
-                );
-            }
-            let app = app.unwrap();
-
-            if !app.allowed_grant_type.contains(&body.grant_type) {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(AccessTokenResponse {
-                        access_token: None,
-                        refresh_token: None,
-                        expires_in: None,
-                        token_type: None,
-                        error: Some("grant_type".to_string()),
-                        hint: Some("grant_type".to_string()),
-                        message: Some("grant_type is incorrect".to_string()),
-                    }),
-                );
-            }
-
-            if app.secret != body.client_secret {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(AccessTokenResponse {
-                        access_token: None,
-                        refresh_token: None,
-                        expires_in: None,
-                        token_type: None,
-                        error: Some("client_secret".to_string()),
-                        hint: Some("client_secret".to_string()),
-                        message: Some("client_secret is incorrect".to_string()),
-                    }),
-                );
-            }
-        }
-        Err(_) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(AccessTokenResponse {
-                    access_token: None,
-                    refresh_token: None,
-                    expires_in: None,
-                    token_type: None,
-                    error: Some("client_id".to_string()),
-                    hint: Some("client_id".to_string()),
-                    message: Some("client_id is incorrect".to_string()),
-                }),
-            );
-        }
+    let ip = get_client_ip(context, &parts);
+    let client_id = body.client_id.parse::<i32>().ok();
+
+    if let Err(response) = check_client(
+        context,
+        &body.client_id,
+        &body.client_secret,
+        &body.grant_type,
+    )
+    .await
+    {
+        return response;
     }
 
     if body.grant_type == "refresh_token" {
@@ -218,6 +173,8 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
             != STANDARD_NO_PAD
                 .decode(claims.hash.clone())
                 .unwrap_or_default()
+            || claims.gen != user.token_generation
+            || claims.client_id != client_id
         {
             return (
                 StatusCode::BAD_REQUEST,
@@ -233,102 +190,89 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
             );
         }
 
-        let mut hmac: hmac::digest::core_api::CoreWrapper<hmac::HmacCore<Sha256>> =
-            Hmac::new_from_slice(context.config.token_hmac_secret.as_bytes()).unwrap();
-
-        hmac.update(user.password.as_bytes());
-
-        let result = hmac.finalize();
-        let result = result.into_bytes();
-        let result = result.to_vec();
-
-        let result = STANDARD_NO_PAD.encode(result);
-        let hmac: hmac::digest::core_api::CoreWrapper<hmac::HmacCore<Sha256>> =
-            Hmac::new_from_slice(context.config.token_hmac_secret.as_bytes()).unwrap();
-        let exp = chrono::Utc::now().timestamp() + 3600;
-
-        let access_token_claims = TokenClaim {
-            exp,
-            sub: user.id,
-            iat: chrono::Utc::now().timestamp(),
-            hash: result.clone(),
-        };
-
-        let access_token_claims = Token::new(
-            Header {
-                algorithm: jwt::AlgorithmType::Hs256,
-                ..Default::default()
+        //Refreshed token may only narrow scopes, RFC 6749 section 6
+        let scope =
+            match body.scope.trim() {
+                "" => claims.scope.clone(),
+                requested => {
+                    let requested = parse_scopes(Some(requested));
+
+                    if claims.scope.as_ref().is_some_and(|granted| {
+                        !requested.iter().all(|scope| granted.contains(scope))
+                    }) {
+                        return token_error(
+                            StatusCode::BAD_REQUEST,
+                            "invalid_scope",
+                            "Requested scope exceeds the granted one.".to_string(),
+                        );
+                    }
+
+                    Some(requested)
+                }
+            };
+
+        return issue_tokens(
+            context,
+            &user,
+            ip.as_deref(),
+            TokenGrant {
+                client_id,
+                grant_type: &body.grant_type,
+                amr: claims.amr.clone(),
+                scope,
             },
-            access_token_claims,
         )
-        .sign_with_key(&hmac);
-
-        let access_token_claims = match access_token_claims {
-            Err(error) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AccessTokenResponse {
-                        access_token: None,
-                        refresh_token: None,
-                        expires_in: None,
-                        token_type: None,
-                        error: Some("internal_error".to_string()),
-                        hint: Some("internal_error".to_string()),
-                        message: Some(error.to_string()),
-                    }),
-                );
-            }
-            Ok(claims) => claims,
-        };
-
-        let refresh_token_exp = chrono::Utc::now().add(chrono::Days::new(14)).timestamp();
+        .await;
+    }
 
-        let refresh_token_claims = TokenClaim {
-            exp: refresh_token_exp,
-            sub: user.id,
-            iat: chrono::Utc::now().timestamp(),
-            hash: result,
-        };
+    if body.grant_type == "authorization_code" {
+        if body.code.is_none() {
+            return token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "code is required for this grant_type".to_string(),
+            );
+        }
 
-        let refresh_token_claims = Token::new(
-            Header {
-                algorithm: jwt::AlgorithmType::Hs256,
-                ..Default::default()
-            },
-            refresh_token_claims,
+        let user = redeem_authorization_code(
+            context,
+            &body.code.unwrap(),
+            client_id.unwrap_or(0),
+            body.redirect_uri.as_deref(),
+            body.code_verifier.as_deref(),
         )
-        .sign_with_key(&hmac);
-
-        let refresh_token_claims = match refresh_token_claims {
+        .await;
+
+        //Consent was given in a website session, app itself never saw the user sign in
+        return match user {
+            Ok(Some((user, scopes))) => {
+                issue_tokens(
+                    context,
+                    &user,
+                    ip.as_deref(),
+                    TokenGrant {
+                        client_id,
+                        grant_type: &body.grant_type,
+                        amr: Vec::new(),
+                        scope: Some(scopes),
+                    },
+                )
+                .await
+            }
+            Ok(None) => token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_grant",
+                "Authorization code is invalid or has expired.".to_string(),
+            ),
             Err(error) => {
-                return (
+                error!("Failed to redeem authorization code: {:?}", error);
+                token_error(
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AccessTokenResponse {
-                        access_token: None,
-                        refresh_token: None,
-                        expires_in: None,
-                        token_type: None,
-                        error: Some("internal_error".to_string()),
-                        hint: Some("internal_error".to_string()),
-                        message: Some(error.to_string()),
-                    }),
-                );
+                    "internal_error",
+                    "Internal server error.".to_string(),
+                )
             }
-            Ok(claims) => claims,
         };
-
-        return (
-            StatusCode::OK,
-            Json(AccessTokenResponse {
-                access_token: Some(access_token_claims.as_str().to_string()),
-                refresh_token: Some(refresh_token_claims.as_str().to_string()),
-                expires_in: Some(3600),
-                token_type: Some("Bearer".to_string()),
-                error: None,
-                hint: None,
-                message: None,
-            }),
-        );
     }
 
     if body.grant_type != "password" {
@@ -361,7 +305,9 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
         );
     }
 
-    let user = find_user_by_id_or_username(&context.pool, body.username.unwrap()).await;
+    let username = body.username.unwrap();
+
+    let user = find_user_by_id_or_username(&context.pool, username.clone()).await;
 
     if user.is_err() {
         return (
@@ -380,72 +326,209 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
 
     let user = user.unwrap();
 
+    //Attempts are counted by resolved username, so logging in by id shares the same counter
+    let lockout_name = user
+        .as_ref()
+        .map(|user| user.username.clone())
+        .unwrap_or(username);
+
+    //Osu sends passwords in md5, so they are stored as bcrypt of md5
+    let password = body.password.unwrap();
+    let password_md5 = format!("{:x}", md5::compute(password));
+
+    match check_credentials(
+        context,
+        &lockout_name,
+        &password_md5,
+        ip.as_deref(),
+        "oauth",
+    )
+    .await
+    {
+        LoginCheck::Ok => (),
+        LoginCheck::LockedOut(remaining) => {
+            return token_error(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_attempts",
+                format!(
+                    "Too many failed login attempts, try again in {} seconds.",
+                    remaining
+                ),
+            )
+        }
+        LoginCheck::InvalidCredentials => {
+            return token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_credentials",
+                "Invalid credentials.".to_string(),
+            )
+        }
+    }
+
+    //Credentials are only skipped for unknown users in debug builds
     if user.is_none() {
-        return (
+        return token_error(
             StatusCode::BAD_REQUEST,
-            Json(AccessTokenResponse {
-                access_token: None,
-                refresh_token: None,
-                expires_in: None,
-                token_type: None,
-                error: Some("invalid_credentials".to_string()),
-                hint: Some("username".to_string()),
-                message: Some("Invalid credentials.".to_string()),
-            }),
+            "invalid_credentials",
+            "Invalid credentials.".to_string(),
         );
     }
 
     let user = user.unwrap();
 
-    //Verifying password
-    //Because osu sends it in md5, we need to hash it to md5 first and then handle it by bcrypt
-    //Skipped in debug mode because we don't need to check it on local instance
-    let password = body.password.unwrap();
-    let password_md5 = md5::compute(password);
-    let password_md5_display = format!("{:x}", password_md5);
-    match bcrypt::verify(password_md5_display.as_bytes(), &user.password) {
-        Err(_) | Ok(false) => {
-            #[cfg(not(debug_assertions))]
-            return (
+    let mut amr = vec!["pwd".to_string()];
+
+    //Password was correct at this point, client asks for the code and repeats the request
+    let two_factor = match is_two_factor_enabled(&context.pool, user.id).await {
+        Ok(false) => Ok(true),
+        Ok(true) => match body.otp.as_deref() {
+            None => {
+                return token_error(
+                    StatusCode::UNAUTHORIZED,
+                    "otp_required",
+                    "Two-factor authentication code is required.".to_string(),
+                )
+            }
+            Some(otp) => {
+                amr.push("otp".to_string());
+                verify_two_factor(context, &user, otp).await
+            }
+        },
+        Err(error) => Err(error),
+    };
+
+    match two_factor {
+        Ok(true) => (),
+        Ok(false) => {
+            register_failed_login(context, &lockout_name, ip.as_deref(), "oauth").await;
+            return token_error(
                 StatusCode::BAD_REQUEST,
-                Json(AccessTokenResponse {
-                    access_token: None,
-                    refresh_token: None,
-                    expires_in: None,
-                    error: Some("invalid_credentials".to_string()),
-                    hint: Some("username".to_string()),
-                    message: Some("Invalid credentials.".to_string()),
-                    token_type: None,
-                }),
+                "invalid_otp",
+                "Invalid two-factor code.".to_string(),
+            );
+        }
+        Err(error) => {
+            error!("Failed to check two-factor of {}: {:?}", user.id, error);
+            return token_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Internal server error.".to_string(),
             );
         }
-        Ok(true) => (),
     }
 
-    //Fun fact: I skill issued myself
-    let exp = chrono::Utc::now().timestamp() + 3600;
+    reset_failed_logins(&context.redis, &lockout_name, ip.as_deref()).await;
+
+    issue_tokens(
+        context,
+        &user,
+        ip.as_deref(),
+        TokenGrant {
+            client_id,
+            grant_type: &body.grant_type,
+            amr,
+            scope: None,
+        },
+    )
+    .await
+}
+
+pub type TokenResponse = (StatusCode, Json<AccessTokenResponse>);
+
+pub fn token_error(status: StatusCode, error: &str, message: String) -> TokenResponse {
+    (
+        status,
+        Json(AccessTokenResponse {
+            access_token: None,
+            refresh_token: None,
+            expires_in: None,
+            token_type: None,
+            error: Some(error.to_string()),
+            hint: Some(error.to_string()),
+            message: Some(message),
+        }),
+    )
+}
+
+pub async fn check_client(
+    context: &Context,
+    client_id: &str,
+    client_secret: &str,
+    grant_type: &str,
+) -> Result<(), TokenResponse> {
+    let app = get_app_by_id(&context.pool, client_id.parse::<i32>().unwrap_or(0)).await;
+
+    match app {
+        Ok(Some(app)) => {
+            if !app.allowed_grant_type.contains(&grant_type.to_string()) {
+                return Err(token_error(
+                    StatusCode::BAD_REQUEST,
+                    "grant_type",
+                    "grant_type is incorrect".to_string(),
+                ));
+            }
 
-    let mut hmac: hmac::digest::core_api::CoreWrapper<hmac::HmacCore<Sha256>> =
+            if app.secret != client_secret {
+                return Err(token_error(
+                    StatusCode::BAD_REQUEST,
+                    "client_secret",
+                    "client_secret is incorrect".to_string(),
+                ));
+            }
+
+            Ok(())
+        }
+        Ok(None) | Err(_) => Err(token_error(
+            StatusCode::BAD_REQUEST,
+            "client_id",
+            "client_id is incorrect".to_string(),
+        )),
+    }
+}
+
+//Tokens carry hmac of the password hash, so changing password revokes all of them
+pub fn password_hmac(context: &Context, user: &User) -> String {
+    let mut hmac: Hmac<Sha256> =
         Hmac::new_from_slice(context.config.token_hmac_secret.as_bytes()).unwrap();
 
     hmac.update(user.password.as_bytes());
 
-    let result = hmac.finalize();
-    let result = result.into_bytes();
-    let result = result.to_vec();
+    STANDARD_NO_PAD.encode(hmac.finalize().into_bytes())
+}
+
+//How the token was obtained, copied to claims of both tokens
+pub struct TokenGrant<'a> {
+    pub client_id: Option<i32>,
+    pub grant_type: &'a str,
+    //Sign-in methods, see TokenClaim
+    pub amr: Vec<String>,
+    //None for first party grants, which aren't limited
+    pub scope: Option<Vec<String>>,
+}
 
-    let result = STANDARD_NO_PAD.encode(result);
-    let hmac: hmac::digest::core_api::CoreWrapper<hmac::HmacCore<Sha256>> =
+//Same access and refresh token pair for every grant type
+pub async fn issue_tokens(
+    context: &Context,
+    user: &User,
+    ip: Option<&str>,
+    grant: TokenGrant<'_>,
+) -> TokenResponse {
+    let hash = password_hmac(context, user);
+    let hmac: Hmac<Sha256> =
         Hmac::new_from_slice(context.config.token_hmac_secret.as_bytes()).unwrap();
 
     let access_token_claims = TokenClaim {
-        exp,
+        exp: chrono::Utc::now().timestamp() + 3600,
         sub: user.id,
         iat: chrono::Utc::now().timestamp(),
-        hash: result.clone(),
+        hash: hash.clone(),
+        gen: user.token_generation,
+        amr: grant.amr.clone(),
+        client_id: grant.client_id,
+        scope: grant.scope.clone(),
     };
 
-    let access_token_claims = Token::new(
+    let access_token = Token::new(
         Header {
             algorithm: jwt::AlgorithmType::Hs256,
             ..Default::default()
@@ -454,34 +537,29 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
     )
     .sign_with_key(&hmac);
 
-    let access_token_claims = match access_token_claims {
+    let access_token = match access_token {
         Err(error) => {
-            return (
+            return token_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AccessTokenResponse {
-                    access_token: None,
-                    refresh_token: None,
-                    expires_in: None,
-                    token_type: None,
-                    error: Some("internal_error".to_string()),
-                    hint: Some("internal_error".to_string()),
-                    message: Some(error.to_string()),
-                }),
-            );
+                "internal_error",
+                error.to_string(),
+            )
         }
-        Ok(claims) => claims,
+        Ok(token) => token,
     };
 
-    let refresh_token_exp = chrono::Utc::now().add(chrono::Days::new(14)).timestamp();
-
     let refresh_token_claims = TokenClaim {
-        exp: refresh_token_exp,
+        exp: chrono::Utc::now().add(chrono::Days::new(14)).timestamp(),
         sub: user.id,
         iat: chrono::Utc::now().timestamp(),
-        hash: result,
+        hash,
+        gen: user.token_generation,
+        amr: grant.amr,
+        client_id: grant.client_id,
+        scope: grant.scope,
     };
 
-    let refresh_token_claims = Token::new(
+    let refresh_token = Token::new(
         Header {
             algorithm: jwt::AlgorithmType::Hs256,
             ..Default::default()
@@ -490,29 +568,24 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
     )
     .sign_with_key(&hmac);
 
-    let refresh_token_claims = match refresh_token_claims {
+    let refresh_token = match refresh_token {
         Err(error) => {
-            return (
+            return token_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AccessTokenResponse {
-                    access_token: None,
-                    refresh_token: None,
-                    expires_in: None,
-                    token_type: None,
-                    error: Some("internal_error".to_string()),
-                    hint: Some("internal_error".to_string()),
-                    message: Some(error.to_string()),
-                }),
-            );
+                "internal_error",
+                error.to_string(),
+            )
         }
-        Ok(claims) => claims,
+        Ok(token) => token,
     };
 
+    record_token_issued(context, user.id, ip, grant.client_id, grant.grant_type).await;
+
     (
         StatusCode::OK,
         Json(AccessTokenResponse {
-            access_token: Some(access_token_claims.as_str().to_string()),
-            refresh_token: Some(refresh_token_claims.as_str().to_string()),
+            access_token: Some(access_token.as_str().to_string()),
+            refresh_token: Some(refresh_token.as_str().to_string()),
             expires_in: Some(3600),
             token_type: Some("Bearer".to_string()),
             error: None,
@@ -521,3 +594,27 @@ pub async fn login(req: extract::Request<Body>) -> impl IntoResponse {
         }),
     )
 }
+
+async fn record_token_issued(
+    context: &Context,
+    user_id: i32,
+    ip: Option<&str>,
+    client_id: Option<i32>,
+    grant_type: &str,
+) {
+    let country = context.geoip.lookup(ip.map(str::to_string)).await;
+
+    insert_login_history(
+        &context.pool,
+        &LoginRecord {
+            user_id,
+            source: "oauth",
+            ip,
+            country: country.as_ref().map(|country| country.code.as_str()),
+            client_id,
+            grant_type: Some(grant_type),
+            ..Default::default()
+        },
+    )
+    .await;
+}
@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use tracing::debug;
 
-use crate::{context::Context, db::user::User, utils::user_utils::get_user_by_id};
+use crate::{context::Context, db::user::User, utils::oauth_utils::scope_allows};
 
 #[derive(Debug, Serialize)]
 pub enum ErrorKind {
@@ -31,6 +31,32 @@ pub struct TokenClaim {
     pub iat: i64,
     //hmac of our password to verify is this token valid or not
     pub hash: String,
+    //Has to match "User"."tokenGeneration"
+    #[serde(default)]
+    pub gen: i32,
+    //Sign-in methods as in RFC 8176: pwd, otp or hwk for passkeys
+    #[serde(default)]
+    pub amr: Vec<String>,
+    //Application the token was issued to, refresh has to come from the same one
+    #[serde(default)]
+    pub client_id: Option<i32>,
+    //Set only for third-party applications, limits routes the token can be used on
+    #[serde(default)]
+    pub scope: Option<Vec<String>>,
+}
+
+//What the request token proves besides the user, empty without a valid token
+#[derive(Debug, Clone, Default)]
+pub struct TokenAuth {
+    pub amr: Vec<String>,
+}
+
+impl TokenAuth {
+    pub fn has_second_factor(&self) -> bool {
+        self.amr
+            .iter()
+            .any(|method| method == "otp" || method == "hwk")
+    }
 }
 
 type HmacSha256 = Hmac<Sha256>;
@@ -40,6 +66,8 @@ pub async fn auth(
     mut req: Request,
     next: Next,
 ) -> Result<impl IntoResponse, (StatusCode, Json<AuthFailedError>)> {
+    req.extensions_mut().insert(TokenAuth::default());
+
     let token = req.headers().get("Authorization");
     let token = match token {
         Some(token) => token,
@@ -109,7 +137,20 @@ pub async fn auth(
 
     let claims = claims.unwrap();
 
-    let user = get_user_by_id(&ctx.pool, claims.sub).await;
+    if let Some(scopes) = &claims.scope {
+        if !scope_allows(scopes, req.method(), req.uri().path()) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(AuthFailedError {
+                    ok: false,
+                    message: "Token scope doesn't allow this route".to_string(),
+                    kind: ErrorKind::Auth,
+                }),
+            ));
+        }
+    }
+
+    let user = ctx.users.get_by_id(claims.sub).await;
 
     if let Err(_error) = user {
         return Ok(next.run(req).await);
@@ -137,8 +178,14 @@ pub async fn auth(
             "Mac verification failed, bytes #1: {:#?}, \nbytes #2: {:#?}",
             claims.hash, verified_hash
         );
+    } else if claims.gen != user.token_generation {
+        debug!(
+            "Token of {} was revoked, generation {} != {}",
+            user.id, claims.gen, user.token_generation
+        );
     } else {
         req.extensions_mut().insert(Some(user));
+        req.extensions_mut().insert(TokenAuth { amr: claims.amr });
     }
 
     Ok(next.run(req).await)
@@ -1,27 +1,48 @@
-use axum::{routing::post, Router};
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
 use serde::{Deserialize, Serialize};
 
-use self::auth::login;
+use self::{
+    auth::login,
+    oauth::{authorize, authorize_info},
+    passkey::{
+        delete_passkey, get_user_passkeys, login_finish, login_start, register_finish,
+        register_start,
+    },
+    password::{confirm_reset, request_reset, update_password},
+    two_factor::{confirm, disable, enroll, get_status, recovery_codes},
+};
 
 pub mod auth;
 pub mod middleware;
+pub mod oauth;
+pub mod passkey;
+pub mod password;
+pub mod two_factor;
 
 #[derive(Deserialize)]
 pub struct AccessTokenRequestBody {
     pub client_id: String,
     pub client_secret: String,
     pub grant_type: String,
+    #[serde(default)]
     pub scope: String,
 
     // grant_type = password
     pub username: Option<String>,
     pub password: Option<String>,
+    //Required when user has two-factor enabled
+    pub otp: Option<String>,
 
     // grant_type = refresh_token
     pub refresh_token: Option<String>,
 
     // grant_type = authorization_code
     pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -44,5 +65,27 @@ pub struct AccessTokenResponse {
 }
 
 pub fn router() -> Router {
-    Router::new().route("/oauth/token", post(login))
+    Router::new()
+        .route("/oauth/token", post(login))
+        .route("/oauth/authorize", get(authorize_info).post(authorize))
+        .route("/api/v2/auth/password", post(update_password))
+        .route("/api/v2/auth/password/reset", post(request_reset))
+        .route("/api/v2/auth/password/reset/confirm", post(confirm_reset))
+        .route("/api/v2/auth/two-factor", get(get_status))
+        .route("/api/v2/auth/two-factor/enroll", post(enroll))
+        .route("/api/v2/auth/two-factor/confirm", post(confirm))
+        .route("/api/v2/auth/two-factor/disable", post(disable))
+        .route(
+            "/api/v2/auth/two-factor/recovery-codes",
+            post(recovery_codes),
+        )
+        .route("/api/v2/auth/passkeys", get(get_user_passkeys))
+        .route("/api/v2/auth/passkeys/:id", delete(delete_passkey))
+        .route("/api/v2/auth/passkeys/register/start", post(register_start))
+        .route(
+            "/api/v2/auth/passkeys/register/finish",
+            post(register_finish),
+        )
+        .route("/api/v2/auth/passkeys/login/start", post(login_start))
+        .route("/api/v2/auth/passkeys/login/finish", post(login_finish))
 }
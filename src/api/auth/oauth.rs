@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::oauth_utils::{
+        authorize_application, build_redirect, get_consent, parse_scopes, validate_authorization,
+        AuthorizationError, AuthorizationRequest,
+    },
+};
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeApplication {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub icon_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeInfo {
+    pub application: AuthorizeApplication,
+    pub scopes: Vec<String>,
+    //Every requested scope was approved before, website may skip the consent screen
+    pub authorized: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeBody {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub approve: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeRedirect {
+    pub redirect: String,
+}
+
+fn authorization_failed<T>(error: AuthorizationError) -> ApiResponse<T> {
+    match error {
+        AuthorizationError::Internal(error) => respond(Err(error)),
+        error => failed(StatusCode::BAD_REQUEST, error.message()),
+    }
+}
+
+//Website renders consent screen from this, nothing is stored yet
+pub async fn authorize_info(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Query(request): Query<AuthorizationRequest>,
+) -> ApiResponse<AuthorizeInfo> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let app = match validate_authorization(&ctx.pool, &request).await {
+        Ok(app) => app,
+        Err(error) => return authorization_failed(error),
+    };
+
+    let scopes = parse_scopes(request.scope.as_deref());
+
+    respond(
+        get_consent(&ctx.pool, user.id, app.id)
+            .await
+            .map(|consent| AuthorizeInfo {
+                authorized: consent
+                    .is_some_and(|approved| scopes.iter().all(|scope| approved.contains(scope))),
+                application: AuthorizeApplication {
+                    id: app.id,
+                    name: app.name,
+                    description: app.description,
+                    icon_hash: app.icon_hash,
+                },
+                scopes,
+            }),
+    )
+}
+
+//Returns url the website sends the browser to, with code or access_denied error
+pub async fn authorize(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<AuthorizeBody>,
+) -> ApiResponse<AuthorizeRedirect> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let app = match validate_authorization(&ctx.pool, &body.request).await {
+        Ok(app) => app,
+        Err(error) => return authorization_failed(error),
+    };
+
+    if !body.approve {
+        return respond(
+            build_redirect(&app, &body.request, &[("error", "access_denied")])
+                .map(|redirect| AuthorizeRedirect { redirect }),
+        );
+    }
+
+    match authorize_application(&ctx, &user, &app, &body.request).await {
+        Ok(redirect) => respond(Ok(AuthorizeRedirect { redirect })),
+        Err(error) => authorization_failed(error),
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        ip_utils::ClientIp,
+        lockout_utils::{get_lockout, reset_failed_logins},
+        passkey_utils::{
+            finish_authentication, finish_registration, get_passkeys, remove_passkey,
+            start_authentication, start_registration, PassKeyEntry, PasskeyError,
+        },
+    },
+};
+
+use super::auth::{check_client, issue_tokens, token_error, TokenGrant, TokenResponse};
+
+#[derive(Deserialize)]
+pub struct RegisterFinishBody {
+    #[serde(flatten)]
+    pub credential: RegisterPublicKeyCredential,
+    #[serde(rename = "authenticatorAttachment")]
+    pub authenticator_attachment: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginStartBody {
+    pub username: String,
+}
+
+#[derive(Serialize)]
+pub struct LoginStartResponse {
+    pub session: String,
+    pub options: RequestChallengeResponse,
+}
+
+#[derive(Deserialize)]
+pub struct LoginFinishBody {
+    pub session: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub credential: PublicKeyCredential,
+}
+
+fn passkey_failed<T>(error: PasskeyError) -> ApiResponse<T> {
+    match error {
+        PasskeyError::Internal(error) => respond(Err(error)),
+        PasskeyError::UserNotFound | PasskeyError::NoPasskeys => {
+            failed(StatusCode::NOT_FOUND, &error.message())
+        }
+        error => failed(StatusCode::BAD_REQUEST, &error.message()),
+    }
+}
+
+pub async fn get_user_passkeys(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+) -> ApiResponse<Vec<PassKeyEntry>> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    respond(get_passkeys(&ctx.pool, user.id).await)
+}
+
+pub async fn delete_passkey(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Path(id): Path<i32>,
+) -> ApiResponse<bool> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match remove_passkey(&ctx, &user, id).await {
+        Ok(false) => failed(StatusCode::NOT_FOUND, "Passkey not found"),
+        result => respond(result),
+    }
+}
+
+pub async fn register_start(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+) -> ApiResponse<CreationChallengeResponse> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    respond(start_registration(&ctx, &user).await)
+}
+
+pub async fn register_finish(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<RegisterFinishBody>,
+) -> ApiResponse<PassKeyEntry> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match finish_registration(
+        &ctx,
+        &user,
+        &body.credential,
+        body.name,
+        body.authenticator_attachment,
+    )
+    .await
+    {
+        Ok(entry) => respond(Ok(entry)),
+        Err(error) => passkey_failed(error),
+    }
+}
+
+pub async fn login_start(
+    Extension(ctx): Extension<Arc<Context>>,
+    Json(body): Json<LoginStartBody>,
+) -> ApiResponse<LoginStartResponse> {
+    match start_authentication(&ctx, body.username).await {
+        Ok((session, options)) => respond(Ok(LoginStartResponse { session, options })),
+        Err(error) => passkey_failed(error),
+    }
+}
+
+//Signed challenge is exchanged for the same token pair as the password grant
+pub async fn login_finish(
+    Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
+    Json(body): Json<LoginFinishBody>,
+) -> TokenResponse {
+    if let Err(response) =
+        check_client(&ctx, &body.client_id, &body.client_secret, "password").await
+    {
+        return response;
+    }
+
+    let user = match finish_authentication(&ctx, &body.session, &body.credential).await {
+        Ok(user) => user,
+        Err(PasskeyError::Internal(error)) => {
+            error!("Failed to finish passkey login: {:?}", error);
+            return token_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                PasskeyError::Internal(error).message(),
+            );
+        }
+        Err(error) => {
+            return token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_credentials",
+                error.message(),
+            )
+        }
+    };
+
+    if let Some(remaining) = get_lockout(&ctx.redis, &user.username, ip.as_deref()).await {
+        return token_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "too_many_attempts",
+            format!(
+                "Too many failed login attempts, try again in {} seconds.",
+                remaining
+            ),
+        );
+    }
+
+    reset_failed_logins(&ctx.redis, &user.username, ip.as_deref()).await;
+
+    issue_tokens(
+        &ctx,
+        &user,
+        ip.as_deref(),
+        TokenGrant {
+            client_id: body.client_id.parse::<i32>().ok(),
+            grant_type: "passkey",
+            amr: vec!["hwk".to_string()],
+            scope: None,
+        },
+    )
+    .await
+}
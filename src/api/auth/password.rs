@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::password_utils::{
+        change_password, request_password_reset, reset_password, PasswordError,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordBody {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetRequestBody {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetConfirmBody {
+    pub token: String,
+    pub password: String,
+}
+
+fn password_failed<T>(error: PasswordError) -> ApiResponse<T> {
+    match error {
+        PasswordError::Internal(error) => respond(Err(error)),
+        PasswordError::WrongPassword => failed(StatusCode::FORBIDDEN, &error.message()),
+        error => failed(StatusCode::BAD_REQUEST, &error.message()),
+    }
+}
+
+//Every access and refresh token issued before stops working
+pub async fn update_password(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<ChangePasswordBody>,
+) -> ApiResponse<bool> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match change_password(&ctx, &user, &body.current_password, &body.new_password).await {
+        Ok(()) => respond(Ok(true)),
+        Err(error) => password_failed(error),
+    }
+}
+
+pub async fn request_reset(
+    Extension(ctx): Extension<Arc<Context>>,
+    Json(body): Json<ResetRequestBody>,
+) -> ApiResponse<bool> {
+    respond(
+        request_password_reset(&ctx, &body.email)
+            .await
+            .map(|_| true),
+    )
+}
+
+pub async fn confirm_reset(
+    Extension(ctx): Extension<Arc<Context>>,
+    Json(body): Json<ResetConfirmBody>,
+) -> ApiResponse<bool> {
+    match reset_password(&ctx, &body.token, &body.password).await {
+        Ok(()) => respond(Ok(true)),
+        Err(error) => password_failed(error),
+    }
+}
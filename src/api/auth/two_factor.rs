@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::two_factor_utils::{
+        confirm_enrollment, disable_two_factor, get_two_factor_status, regenerate_recovery_codes,
+        start_enrollment, TwoFactorEnrollment, TwoFactorError, TwoFactorStatus,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeBody {
+    pub code: String,
+}
+
+fn two_factor_failed<T>(error: TwoFactorError) -> ApiResponse<T> {
+    match error {
+        TwoFactorError::Internal(error) => respond(Err(error)),
+        TwoFactorError::InvalidCode => failed(StatusCode::FORBIDDEN, error.message()),
+        TwoFactorError::AlreadyEnabled | TwoFactorError::RequiredByGroup => {
+            failed(StatusCode::CONFLICT, error.message())
+        }
+        error => failed(StatusCode::BAD_REQUEST, error.message()),
+    }
+}
+
+pub async fn get_status(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+) -> ApiResponse<TwoFactorStatus> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    respond(get_two_factor_status(&ctx, &user).await)
+}
+
+pub async fn enroll(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+) -> ApiResponse<TwoFactorEnrollment> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match start_enrollment(&ctx, &user).await {
+        Ok(enrollment) => respond(Ok(enrollment)),
+        Err(error) => two_factor_failed(error),
+    }
+}
+
+pub async fn confirm(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<TwoFactorCodeBody>,
+) -> ApiResponse<Vec<String>> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match confirm_enrollment(&ctx, &user, &body.code).await {
+        Ok(codes) => respond(Ok(codes)),
+        Err(error) => two_factor_failed(error),
+    }
+}
+
+pub async fn disable(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<TwoFactorCodeBody>,
+) -> ApiResponse<bool> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match disable_two_factor(&ctx, &user, &body.code).await {
+        Ok(()) => respond(Ok(true)),
+        Err(error) => two_factor_failed(error),
+    }
+}
+
+//Old recovery codes stop working
+pub async fn recovery_codes(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<TwoFactorCodeBody>,
+) -> ApiResponse<Vec<String>> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match regenerate_recovery_codes(&ctx, &user, &body.code).await {
+        Ok(codes) => respond(Ok(codes)),
+        Err(error) => two_factor_failed(error),
+    }
+}
@@ -0,0 +1,51 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use self::routes::{
+    add_holder, create, list_badges, list_holders, record_tournament_winners, remove_holder,
+};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct HoldersQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBadgeBody {
+    pub name: String,
+    //Font Awesome icon name
+    pub icon: String,
+    //Hex colour, e.g. #ff66aa
+    pub color: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantBadgeBody {
+    //User id or username
+    pub user: String,
+    //Permanent when omitted
+    pub expires_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentWinnersBody {
+    pub tournament: String,
+    //User ids or usernames
+    pub users: Vec<String>,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(list_badges).post(create))
+        .route("/tournament-winners", post(record_tournament_winners))
+        .route("/:id/holders", get(list_holders).post(add_holder))
+        .route("/:id/holders/:user_id", delete(remove_holder))
+}
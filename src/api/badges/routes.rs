@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::Utc;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::{
+    api::{auth::middleware::TokenAuth, failed, require_capability, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        audit_utils::{insert_audit_log, AuditRecord},
+        badge_utils::{
+            create_badge, get_badge, get_badge_by_key, get_badge_holders, get_badges, grant_badge,
+            revoke_badge, BadgeDefinition, BadgeHolder, TOURNAMENT_WINNER_BADGE,
+        },
+        permission_utils::Capabilities,
+        user_utils::find_user_by_id_or_username,
+    },
+};
+
+use super::{CreateBadgeBody, GrantBadgeBody, HoldersQuery, TournamentWinnersBody};
+
+fn is_valid_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color.chars().skip(1).all(|c| c.is_ascii_hexdigit())
+}
+
+async fn find_badge<T>(ctx: &Context, id: i32) -> Result<BadgeDefinition, ApiResponse<T>> {
+    match get_badge(&ctx.pool, id).await {
+        Ok(Some(badge)) => Ok(badge),
+        Ok(None) => Err(failed(StatusCode::NOT_FOUND, "Not found")),
+        Err(error) => {
+            error!("Error getting badge: {:?}", error);
+            Err(failed(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error",
+            ))
+        }
+    }
+}
+
+async fn find_user<T>(ctx: &Context, term: String) -> Result<User, ApiResponse<T>> {
+    match find_user_by_id_or_username(&ctx.pool, term).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(failed(StatusCode::NOT_FOUND, "User not found")),
+        Err(error) => {
+            error!("Error getting user: {:?}", error);
+            Err(failed(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error",
+            ))
+        }
+    }
+}
+
+pub async fn list_badges(
+    Extension(ctx): Extension<Arc<Context>>,
+) -> ApiResponse<Vec<BadgeDefinition>> {
+    respond(get_badges(&ctx.pool).await)
+}
+
+pub async fn create(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Json(body): Json<CreateBadgeBody>,
+) -> ApiResponse<BadgeDefinition> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let name = body.name.trim();
+
+    if name.is_empty() || name.len() > 32 {
+        return failed(StatusCode::BAD_REQUEST, "Name must be 1-32 characters long");
+    }
+
+    if body.icon.trim().is_empty() {
+        return failed(StatusCode::BAD_REQUEST, "Icon can't be empty");
+    }
+
+    if !is_valid_color(&body.color) {
+        return failed(StatusCode::BAD_REQUEST, "Color must be in #rrggbb format");
+    }
+
+    let badge = create_badge(&ctx.pool, name, body.icon.trim(), &body.color).await;
+
+    if let Ok(badge) = &badge {
+        info!(
+            "{} created badge {} (#{})",
+            user.username, badge.name, badge.id
+        );
+
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: Some(user.id),
+                action: "badge.created",
+                after: Some(json!(badge)),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    respond(badge)
+}
+
+pub async fn list_holders(
+    Extension(ctx): Extension<Arc<Context>>,
+    Path(id): Path<i32>,
+    Query(query): Query<HoldersQuery>,
+) -> ApiResponse<Vec<BadgeHolder>> {
+    let badge = match find_badge(&ctx, id).await {
+        Ok(badge) => badge,
+        Err(response) => return response,
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_badge_holders(&ctx.pool, badge.id, offset, limit).await)
+}
+
+pub async fn add_holder(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+    Json(body): Json<GrantBadgeBody>,
+) -> ApiResponse<bool> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let badge = match find_badge(&ctx, id).await {
+        Ok(badge) => badge,
+        Err(response) => return response,
+    };
+
+    let target = match find_user(&ctx, body.user).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return failed(StatusCode::BAD_REQUEST, "Expiry must be in the future");
+    }
+
+    let granted = grant_badge(
+        &ctx,
+        &badge,
+        target.id,
+        Some(user.id),
+        body.expires_at.map(|expires_at| expires_at.naive_utc()),
+        body.note.as_deref().unwrap_or_default(),
+    )
+    .await;
+
+    respond(granted.map(|_| true))
+}
+
+pub async fn remove_holder(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path((id, user_id)): Path<(i32, i32)>,
+) -> ApiResponse<bool> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let badge = match find_badge(&ctx, id).await {
+        Ok(badge) => badge,
+        Err(response) => return response,
+    };
+
+    match revoke_badge(&ctx, &badge, user_id, user.id).await {
+        Ok(false) => failed(StatusCode::NOT_FOUND, "User doesn't have this badge"),
+        result => respond(result),
+    }
+}
+
+//Recording tournament result grants winner badge to whole team
+pub async fn record_tournament_winners(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Json(body): Json<TournamentWinnersBody>,
+) -> ApiResponse<i32> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let tournament = body.tournament.trim();
+
+    if tournament.is_empty() || body.users.is_empty() {
+        return failed(StatusCode::BAD_REQUEST, "Tournament and users are required");
+    }
+
+    let badge = match get_badge_by_key(&ctx.pool, TOURNAMENT_WINNER_BADGE).await {
+        Ok(Some(badge)) => badge,
+        Ok(None) => return failed(StatusCode::NOT_FOUND, "Tournament badge is not set up"),
+        Err(error) => return respond(Err(error)),
+    };
+
+    let mut winners = Vec::new();
+
+    for term in body.users {
+        match find_user(&ctx, term).await {
+            Ok(winner) => winners.push(winner),
+            Err(response) => return response,
+        }
+    }
+
+    let note = format!("Winner of {}", tournament);
+
+    for winner in &winners {
+        if let Err(error) = grant_badge(&ctx, &badge, winner.id, Some(user.id), None, &note).await {
+            return respond(Err(error));
+        }
+    }
+
+    respond(Ok(winners.len() as i32))
+}
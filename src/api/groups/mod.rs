@@ -0,0 +1,34 @@
+use axum::{
+    routing::{delete, get, patch},
+    Router,
+};
+use serde::Deserialize;
+
+use self::routes::{add_member, list_groups, list_members, remove_member, update_group};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct MembersQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberBody {
+    //User id or username
+    pub user: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGroupBody {
+    pub require_two_factor: bool,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(list_groups))
+        .route("/:id", patch(update_group))
+        .route("/:id/members", get(list_members).post(add_member))
+        .route("/:id/members/:user_id", delete(remove_member))
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::{
+    api::{auth::middleware::TokenAuth, failed, require_capability, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        audit_utils::{insert_audit_log, AuditRecord},
+        group_utils::{
+            add_group_member, get_group, get_group_members, get_groups, remove_group_member,
+            set_group_two_factor, Group, GroupMember,
+        },
+        permission_utils::{get_capabilities, Capabilities},
+        user_utils::{find_user_by_id_or_username, send_bancho_message},
+    },
+};
+
+use super::{AddMemberBody, MembersQuery, UpdateGroupBody};
+
+async fn find_group<T>(ctx: &Context, id: &str) -> Result<Group, ApiResponse<T>> {
+    match get_group(&ctx.pool, id).await {
+        Ok(Some(group)) => Ok(group),
+        Ok(None) => Err(failed(StatusCode::NOT_FOUND, "Not found")),
+        Err(error) => {
+            error!("Error getting group: {:?}", error);
+            Err(failed(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error",
+            ))
+        }
+    }
+}
+
+//Managers can only touch groups granting capabilities they have themselves,
+//otherwise MANAGE_GROUPS would be enough to add yourself to admins
+async fn find_managed_group<T>(
+    ctx: &Context,
+    actor: &User,
+    id: &str,
+) -> Result<Group, ApiResponse<T>> {
+    let group = find_group(ctx, id).await?;
+    let missing = Capabilities(group.permissions & !get_capabilities(ctx, actor).await.0);
+
+    if missing != Capabilities::NONE {
+        return Err(failed(
+            StatusCode::FORBIDDEN,
+            &format!(
+                "You can't manage this group without: {}",
+                missing.names().join(", ")
+            ),
+        ));
+    }
+
+    Ok(group)
+}
+
+pub async fn list_groups(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+) -> ApiResponse<Vec<Group>> {
+    if let Err(response) = require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await
+    {
+        return response;
+    }
+
+    respond(get_groups(&ctx.pool).await)
+}
+
+//Members of groups requiring 2FA can't use staff routes until they enable it and log in with it
+pub async fn update_group(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<String>,
+    Json(body): Json<UpdateGroupBody>,
+) -> ApiResponse<bool> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let group = match find_managed_group(&ctx, &user, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    if let Err(error) = set_group_two_factor(&ctx.pool, &group.id, body.require_two_factor).await {
+        return respond(Err(error));
+    }
+
+    info!(
+        "{} set two-factor requirement of group {} to {}",
+        user.username, group.id, body.require_two_factor
+    );
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "group.updated",
+            before: Some(
+                json!({ "group": group.id, "require_two_factor": group.require_two_factor }),
+            ),
+            after: Some(
+                json!({ "group": group.id, "require_two_factor": body.require_two_factor }),
+            ),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    respond(Ok(true))
+}
+
+pub async fn list_members(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<String>,
+    Query(query): Query<MembersQuery>,
+) -> ApiResponse<Vec<GroupMember>> {
+    if let Err(response) = require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await
+    {
+        return response;
+    }
+
+    let group = match find_group(&ctx, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_group_members(&ctx.pool, &group.id, offset, limit).await)
+}
+
+pub async fn add_member(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<String>,
+    Json(body): Json<AddMemberBody>,
+) -> ApiResponse<bool> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let group = match find_managed_group(&ctx, &user, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    let target = find_user_by_id_or_username(&ctx.pool, body.user).await;
+
+    if let Err(error) = target {
+        error!("Error getting user: {:?}", error);
+        return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+    }
+
+    let target = target.unwrap();
+
+    if target.is_none() {
+        return failed(StatusCode::NOT_FOUND, "User not found");
+    }
+
+    let target = target.unwrap();
+
+    match add_group_member(&ctx.pool, &group.id, target.id).await {
+        Ok(false) => failed(StatusCode::CONFLICT, "User is already a member"),
+        Ok(true) => {
+            info!(
+                "{} added {} to group {}",
+                user.username, target.username, group.id
+            );
+
+            insert_audit_log(
+                &ctx.pool,
+                AuditRecord {
+                    actor_id: Some(user.id),
+                    action: "group.member_added",
+                    target_id: Some(target.id),
+                    after: Some(json!({ "group": group.id })),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            send_bancho_message(&target.id, "user:privileges".to_string(), None).await;
+
+            respond(Ok(true))
+        }
+        Err(error) => respond(Err(error)),
+    }
+}
+
+pub async fn remove_member(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path((id, user_id)): Path<(String, i32)>,
+) -> ApiResponse<bool> {
+    let user = match require_capability(&ctx, user, &auth, Capabilities::MANAGE_GROUPS).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let group = match find_managed_group(&ctx, &user, &id).await {
+        Ok(group) => group,
+        Err(response) => return response,
+    };
+
+    match remove_group_member(&ctx.pool, &group.id, user_id).await {
+        Ok(false) => failed(StatusCode::NOT_FOUND, "User is not a member"),
+        Ok(true) => {
+            info!(
+                "{} removed {} from group {}",
+                user.username, user_id, group.id
+            );
+
+            insert_audit_log(
+                &ctx.pool,
+                AuditRecord {
+                    actor_id: Some(user.id),
+                    action: "group.member_removed",
+                    target_id: Some(user_id),
+                    before: Some(json!({ "group": group.id })),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            send_bancho_message(&user_id, "user:privileges".to_string(), None).await;
+
+            respond(Ok(true))
+        }
+        Err(error) => respond(Err(error)),
+    }
+}
@@ -0,0 +1,52 @@
+use axum::{
+    routing::{delete, get, patch, post},
+    Router,
+};
+use serde::Deserialize;
+
+use self::routes::{
+    add_whitelist_entry, get_clusters, get_flags, get_linked_accounts, get_rules, get_whitelist,
+    remove_whitelist_entry, resolve_flag, update_rule,
+};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct PaginationQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FlagsQuery {
+    pub resolved: Option<bool>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRuleBody {
+    pub action: Option<String>,
+    pub min_accounts: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WhitelistBody {
+    pub component: String,
+    pub value: String,
+    pub note: Option<String>,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/clusters", get(get_clusters))
+        .route("/users/:id", get(get_linked_accounts))
+        .route("/rules", get(get_rules))
+        .route("/rules/:id", patch(update_rule))
+        .route("/whitelist", get(get_whitelist))
+        .route("/whitelist", post(add_whitelist_entry))
+        .route("/whitelist/:id", delete(remove_whitelist_entry))
+        .route("/flags", get(get_flags))
+        .route("/flags/:id/resolve", post(resolve_flag))
+}
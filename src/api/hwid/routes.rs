@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::{
+    api::{auth::middleware::TokenAuth, failed, require_manager, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        audit_utils::{insert_audit_log, AuditRecord},
+        hwid_utils::{
+            add_hwid_whitelist, get_hwid_flags, get_hwid_rules, get_hwid_whitelist,
+            get_linked_cluster, get_shared_hwids, remove_hwid_whitelist, resolve_hwid_flag,
+            update_hwid_rule, ClusterMember, HwidAction, HwidComponent, HwidFlag, HwidRule,
+            HwidWhitelistEntry, SharedHwid,
+        },
+        user_utils::find_user_by_id_or_username,
+    },
+};
+
+use super::{FlagsQuery, PaginationQuery, UpdateRuleBody, WhitelistBody};
+
+pub async fn get_clusters(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Query(query): Query<PaginationQuery>,
+) -> ApiResponse<Vec<SharedHwid>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_shared_hwids(&ctx.pool, offset, limit).await)
+}
+
+pub async fn get_linked_accounts(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<String>,
+) -> ApiResponse<Vec<ClusterMember>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let target = find_user_by_id_or_username(&ctx.pool, id).await;
+
+    if let Err(error) = target {
+        error!("Error getting user: {:?}", error);
+        return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+    }
+
+    let target = target.unwrap();
+
+    if target.is_none() {
+        return failed(StatusCode::NOT_FOUND, "Not found");
+    }
+
+    respond(get_linked_cluster(&ctx.pool, target.unwrap().id).await)
+}
+
+pub async fn get_rules(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+) -> ApiResponse<Vec<HwidRule>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    respond(get_hwid_rules(&ctx.pool).await)
+}
+
+pub async fn update_rule(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+    Json(body): Json<UpdateRuleBody>,
+) -> ApiResponse<HwidRule> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let action = match body.action.as_deref().map(HwidAction::parse) {
+        Some(None) => return failed(StatusCode::BAD_REQUEST, "Unknown action"),
+        Some(action) => action,
+        None => None,
+    };
+
+    if body
+        .min_accounts
+        .is_some_and(|min_accounts| min_accounts < 1)
+    {
+        return failed(StatusCode::BAD_REQUEST, "min_accounts must be at least 1");
+    }
+
+    let rule = update_hwid_rule(&ctx.pool, id, action, body.min_accounts, body.enabled).await;
+
+    match rule {
+        Ok(None) => failed(StatusCode::NOT_FOUND, "Not found"),
+        Ok(Some(rule)) => {
+            info!(
+                "{} updated hwid rule #{}: {} -> {} (enabled: {})",
+                user.username, rule.id, rule.component, rule.action, rule.enabled
+            );
+
+            insert_audit_log(
+                &ctx.pool,
+                AuditRecord {
+                    actor_id: Some(user.id),
+                    action: "hwid.rule_updated",
+                    after: Some(json!(rule)),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            respond(Ok(rule))
+        }
+        Err(error) => respond(Err(error)),
+    }
+}
+
+pub async fn get_whitelist(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+) -> ApiResponse<Vec<HwidWhitelistEntry>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    respond(get_hwid_whitelist(&ctx.pool).await)
+}
+
+pub async fn add_whitelist_entry(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Json(body): Json<WhitelistBody>,
+) -> ApiResponse<HwidWhitelistEntry> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let component = HwidComponent::parse(&body.component);
+
+    if component.is_none() {
+        return failed(StatusCode::BAD_REQUEST, "Unknown component");
+    }
+
+    if body.value.is_empty() {
+        return failed(StatusCode::BAD_REQUEST, "Value can't be empty");
+    }
+
+    let entry = add_hwid_whitelist(
+        &ctx.pool,
+        component.unwrap(),
+        body.value,
+        body.note.unwrap_or_default(),
+        user.id,
+    )
+    .await;
+
+    if let Ok(entry) = &entry {
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: Some(user.id),
+                action: "hwid.whitelist_added",
+                after: Some(json!(entry)),
+                note: &entry.note,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    respond(entry)
+}
+
+pub async fn remove_whitelist_entry(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+) -> ApiResponse<bool> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match remove_hwid_whitelist(&ctx.pool, id).await {
+        Ok(false) => failed(StatusCode::NOT_FOUND, "Not found"),
+        Ok(true) => {
+            insert_audit_log(
+                &ctx.pool,
+                AuditRecord {
+                    actor_id: Some(user.id),
+                    action: "hwid.whitelist_removed",
+                    before: Some(json!({ "id": id })),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            respond(Ok(true))
+        }
+        result => respond(result),
+    }
+}
+
+pub async fn get_flags(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Query(query): Query<FlagsQuery>,
+) -> ApiResponse<Vec<HwidFlag>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_hwid_flags(&ctx.pool, query.resolved.unwrap_or(false), offset, limit).await)
+}
+
+pub async fn resolve_flag(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+) -> ApiResponse<bool> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match resolve_hwid_flag(&ctx.pool, id).await {
+        Ok(false) => failed(StatusCode::NOT_FOUND, "Not found"),
+        Ok(true) => {
+            insert_audit_log(
+                &ctx.pool,
+                AuditRecord {
+                    actor_id: Some(user.id),
+                    action: "hwid.flag_resolved",
+                    after: Some(json!({ "flagId": id })),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            respond(Ok(true))
+        }
+        result => respond(result),
+    }
+}
@@ -1,11 +1,12 @@
 use std::sync::Arc;
 
 use axum::{extract::Query, http::StatusCode, Extension, Json};
+use tracing::error;
 
 use crate::{
     api::FailableResponse,
     context::Context,
-    utils::user_utils::{find_user_by_id_or_username, increment_user_coins},
+    utils::{shop_utils::reward_listing_vote, user_utils::find_user_by_id_or_username},
 };
 
 use super::GetBackQuery;
@@ -68,14 +69,27 @@ pub async fn handle_vote(
 
     let user = user.unwrap();
 
-    increment_user_coins(&ctx.pool, &user.id, 50).await;
+    //Listing may call back more than once a day, only the first vote is rewarded
+    let rewarded = reward_listing_vote(&ctx.pool, user.id).await;
+
+    if let Err(error) = rewarded {
+        error!("Failed to reward vote of {}: {:?}", user.username, error);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FailableResponse {
+                ok: false,
+                message: Some("Failed to reward vote".to_string()),
+                data: None,
+            }),
+        );
+    }
 
     (
         StatusCode::OK,
         Json(FailableResponse {
             ok: true,
             message: None,
-            data: Some(true),
+            data: Some(rewarded.unwrap()),
         }),
     )
 }
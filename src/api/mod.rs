@@ -1,19 +1,39 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{extract::DefaultBodyLimit, middleware, routing::get, Extension, Router};
+use axum::{
+    extract::DefaultBodyLimit, http::StatusCode, middleware, routing::get, Extension, Json, Router,
+};
 use serde::Serialize;
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
-use tracing::Level;
+use tracing::{error, Level};
 
-use crate::{context::Context, health_check};
+use crate::{
+    context::Context,
+    db::user::User,
+    health_check,
+    utils::{
+        permission_utils::{has_capability, Capabilities},
+        score_utils::OsuServerError,
+        two_factor_utils::{is_two_factor_enabled, is_two_factor_required},
+    },
+};
 
-use self::auth::middleware::auth;
+use self::auth::middleware::{auth, TokenAuth};
 
+pub mod audit;
 pub mod auth;
+pub mod badges;
 pub mod beatmaps;
+pub mod groups;
+pub mod hwid;
 pub mod listing;
+pub mod notifications;
+pub mod payments;
 pub mod rankings;
+pub mod reports;
+pub mod sessions;
+pub mod shop;
 pub mod users;
 
 #[derive(Debug, Serialize)]
@@ -25,6 +45,79 @@ pub struct FailableResponse<T> {
     pub data: Option<T>,
 }
 
+pub type ApiResponse<T> = (StatusCode, Json<FailableResponse<T>>);
+
+pub fn failed<T>(status: StatusCode, message: &str) -> ApiResponse<T> {
+    (
+        status,
+        Json(FailableResponse {
+            ok: false,
+            message: Some(message.to_string()),
+            data: None,
+        }),
+    )
+}
+
+pub fn respond<T>(result: Result<T, OsuServerError>) -> ApiResponse<T> {
+    match result {
+        Ok(data) => (
+            StatusCode::OK,
+            Json(FailableResponse {
+                ok: true,
+                message: None,
+                data: Some(data),
+            }),
+        ),
+        Err(error) => {
+            error!("{:#?}", error);
+            failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+pub fn require_user<T>(user: Option<User>) -> Result<User, ApiResponse<T>> {
+    user.ok_or_else(|| failed(StatusCode::UNAUTHORIZED, "Not logged in"))
+}
+
+pub async fn require_capability<T>(
+    ctx: &Context,
+    user: Option<User>,
+    auth: &TokenAuth,
+    capability: Capabilities,
+) -> Result<User, ApiResponse<T>> {
+    let user = require_user(user)?;
+
+    if !has_capability(ctx, &user, capability).await {
+        return Err(failed(StatusCode::FORBIDDEN, "Not authorized"));
+    }
+
+    //Only api routes are gated, bancho and bot commands keep working without 2FA.
+    //Enabling it isn't enough, token has to be issued with a second factor too
+    let enforced = match is_two_factor_required(&ctx.pool, user.id).await {
+        Ok(true) => is_two_factor_enabled(&ctx.pool, user.id)
+            .await
+            .map(|enabled| !enabled || !auth.has_second_factor()),
+        result => result,
+    };
+
+    match enforced {
+        Ok(false) => Ok(user),
+        Ok(true) => Err(failed(
+            StatusCode::FORBIDDEN,
+            "Your group requires two-factor authentication, enable it and log in with it",
+        )),
+        Err(error) => Err(respond(Err(error))),
+    }
+}
+
+pub async fn require_manager<T>(
+    ctx: &Context,
+    user: Option<User>,
+    auth: &TokenAuth,
+) -> Result<User, ApiResponse<T>> {
+    require_capability(ctx, user, auth, Capabilities::MODERATE).await
+}
+
 pub async fn serve_api(ctx: Context) {
     let ctx = Arc::new(ctx);
 
@@ -48,10 +141,24 @@ pub async fn serve_api(ctx: Context) {
         .merge(crate::api::listing::router())
         .nest("/api/v2/beatmaps", crate::api::beatmaps::router())
         .nest("/api/v2/rankings", crate::api::rankings::router())
+        .nest("/api/v2/notifications", crate::api::notifications::router())
+        .nest("/api/v2/hwid", crate::api::hwid::router())
+        .nest("/api/v2/sessions", crate::api::sessions::router())
+        .nest("/api/v2/reports", crate::api::reports::router())
+        .nest("/api/v2/audit", crate::api::audit::router())
+        .nest("/api/v2/groups", crate::api::groups::router())
+        .nest("/api/v2/badges", crate::api::badges::router())
+        .nest("/api/v2/payments", crate::api::payments::router())
+        .nest("/api/v2/shop", crate::api::shop::router())
         .route("/health", get(health_check))
         .layer(layer_ctx)
         .layer(DefaultBodyLimit::max(1024 * 8));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, router).await.unwrap();
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
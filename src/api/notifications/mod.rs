@@ -0,0 +1,36 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::notification_utils::UserNotification;
+
+use self::routes::{get_user_notifications, mark_seen};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationsQuery {
+    pub unseen: Option<bool>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkSeenBody {
+    //Marks everything when omitted
+    pub ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationList {
+    pub unseen: i64,
+    pub notifications: Vec<UserNotification>,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(get_user_notifications))
+        .route("/seen", post(mark_seen))
+}
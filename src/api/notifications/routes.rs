@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, Extension, Json};
+
+use crate::{
+    api::{require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::notification_utils::{
+        count_unseen_notifications, get_notifications, mark_notifications_seen,
+    },
+};
+
+use super::{MarkSeenBody, NotificationList, NotificationsQuery};
+
+pub async fn get_user_notifications(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Query(query): Query<NotificationsQuery>,
+) -> ApiResponse<NotificationList> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    let notifications = get_notifications(
+        &ctx.pool,
+        user.id,
+        query.unseen.unwrap_or(false),
+        offset,
+        limit,
+    )
+    .await;
+
+    if let Err(error) = notifications {
+        return respond(Err(error));
+    }
+
+    let unseen = count_unseen_notifications(&ctx.pool, user.id).await;
+
+    respond(unseen.map(|unseen| NotificationList {
+        unseen,
+        notifications: notifications.unwrap(),
+    }))
+}
+
+pub async fn mark_seen(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<MarkSeenBody>,
+) -> ApiResponse<u64> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    respond(mark_notifications_seen(&ctx.pool, user.id, body.ids).await)
+}
@@ -0,0 +1,14 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use self::routes::{get_payments, webhook};
+
+pub mod routes;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(get_payments))
+        .route("/:provider/webhook", post(webhook))
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    Extension,
+};
+use tracing::warn;
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::payment_utils::{get_user_payments, process_payment, PaymentEntry},
+};
+
+//Payment history of logged in user
+pub async fn get_payments(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+) -> ApiResponse<Vec<PaymentEntry>> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    respond(get_user_payments(&ctx.pool, user.id).await)
+}
+
+//Providers retry on anything but 2xx, already processed payments are acknowledged as well
+pub async fn webhook(
+    Extension(ctx): Extension<Arc<Context>>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResponse<bool> {
+    let payment_provider = ctx.payments.get(&provider);
+
+    if payment_provider.is_none() {
+        return failed(StatusCode::NOT_FOUND, "Unknown payment provider");
+    }
+
+    let payment = payment_provider
+        .unwrap()
+        .parse_webhook(&headers, &body)
+        .await;
+
+    if let Err(error) = payment {
+        warn!("Rejected {} payment webhook: {}", provider, error);
+        return failed(StatusCode::BAD_REQUEST, "Invalid webhook");
+    }
+
+    match payment.unwrap() {
+        Some(payment) => respond(
+            process_payment(&ctx, &provider, &payment)
+                .await
+                .map(|entry| entry.is_some()),
+        ),
+        None => respond(Ok(false)),
+    }
+}
@@ -8,10 +8,7 @@ use crate::{
     context::Context,
     utils::{
         general_utils::to_fixed,
-        user_utils::{
-            calculate_level, get_leaderboard, get_leaderboard_count, get_users_many,
-            get_usersats_many,
-        },
+        user_utils::{calculate_level, get_users_many, get_usersats_many},
     },
 };
 
@@ -22,15 +19,29 @@ pub async fn leaderboard(
     Extension(ctx): Extension<Arc<Context>>,
     Query(query): Query<RankingsRequestQuery>,
 ) -> (StatusCode, Json<FailableResponse<RankingsResponse>>) {
-    let leaderboard = get_leaderboard(
-        &ctx.redis,
-        query.clone().mode,
-        query.clone().offset,
-        query.clone().limit,
-    )
-    .await;
+    let leaderboard = ctx
+        .rankings
+        .get_leaderboard(
+            query.clone().mode,
+            query.clone().offset,
+            query.clone().limit,
+        )
+        .await;
+    let total_users = ctx.rankings.get_leaderboard_count(query.clone().mode).await;
+
+    if leaderboard.is_err() || total_users.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FailableResponse {
+                ok: false,
+                message: Some(String::from("Internal server error.")),
+                data: None,
+            }),
+        );
+    }
 
-    let total_users = get_leaderboard_count(&ctx.redis, query.clone().mode).await;
+    let leaderboard = leaderboard.unwrap();
+    let total_users = total_users.unwrap();
     let stats = get_usersats_many(&ctx.pool, &leaderboard, query.clone().mode).await;
     let users = get_users_many(&ctx.pool, &leaderboard).await;
 
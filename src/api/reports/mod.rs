@@ -0,0 +1,40 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+
+use self::routes::{claim, create_report, list_reports, resolve};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReportBody {
+    //User id or username
+    pub user: String,
+    pub category: Option<String>,
+    pub reason: String,
+    pub score_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportsQuery {
+    pub status: Option<String>,
+    pub user: Option<i32>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveBody {
+    //Dismissed when omitted
+    pub punishment_id: Option<String>,
+    pub resolution: Option<String>,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(list_reports).post(create_report))
+        .route("/:id/claim", post(claim))
+        .route("/:id/resolve", post(resolve))
+}
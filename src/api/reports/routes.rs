@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use tracing::info;
+
+use crate::{
+    api::{
+        auth::middleware::TokenAuth, failed, require_manager, require_user, respond, ApiResponse,
+    },
+    context::Context,
+    db::user::User,
+    utils::report_utils::{
+        claim_report, get_report, get_reports, resolve_report, submit_report, Report, ReportError,
+        ReportReason,
+    },
+};
+
+use super::{CreateReportBody, ReportsQuery, ResolveBody};
+
+pub async fn create_report(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<CreateReportBody>,
+) -> ApiResponse<Report> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let category = match body.category.as_deref().map(ReportReason::parse) {
+        Some(None) => return failed(StatusCode::BAD_REQUEST, "Unknown category"),
+        Some(category) => category,
+        None => None,
+    };
+
+    let report = submit_report(
+        &ctx,
+        &user,
+        body.user,
+        category.unwrap_or(ReportReason::Other),
+        body.reason,
+        body.score_id,
+    )
+    .await;
+
+    match report {
+        Ok(report) => respond(Ok(report)),
+        Err(ReportError::Internal(error)) => respond(Err(error)),
+        Err(ReportError::UserNotFound) => {
+            failed(StatusCode::NOT_FOUND, ReportError::UserNotFound.message())
+        }
+        Err(ReportError::AlreadyReported) => {
+            failed(StatusCode::CONFLICT, ReportError::AlreadyReported.message())
+        }
+        Err(error) => failed(StatusCode::BAD_REQUEST, error.message()),
+    }
+}
+
+pub async fn list_reports(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Query(query): Query<ReportsQuery>,
+) -> ApiResponse<Vec<Report>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_reports(&ctx.pool, query.status, query.user, offset, limit).await)
+}
+
+pub async fn claim(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+) -> ApiResponse<Report> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match claim_report(&ctx.pool, id, user.id).await {
+        Ok(None) => failed(StatusCode::CONFLICT, "Report is not open"),
+        Ok(Some(report)) => {
+            info!("{} claimed report #{}", user.username, report.id);
+            respond(Ok(report))
+        }
+        Err(error) => respond(Err(error)),
+    }
+}
+
+pub async fn resolve(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Path(id): Path<i32>,
+    Json(body): Json<ResolveBody>,
+) -> ApiResponse<Report> {
+    let user = match require_manager(&ctx, user, &auth).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let report = get_report(&ctx.pool, id).await;
+
+    if let Err(error) = report {
+        return respond(Err(error));
+    }
+
+    let report = report.unwrap();
+
+    if report.is_none() {
+        return failed(StatusCode::NOT_FOUND, "Not found");
+    }
+
+    let report = report.unwrap();
+
+    if report
+        .claimed_by
+        .is_some_and(|claimed_by| claimed_by != user.id)
+    {
+        return failed(StatusCode::CONFLICT, "Report is claimed by someone else");
+    }
+
+    //Linked punishment has to be applied to reported user
+    if let Some(punishment_id) = &body.punishment_id {
+        let punishment = ctx.punishments.get_by_id(punishment_id.clone()).await;
+
+        if punishment.is_none() {
+            return failed(StatusCode::BAD_REQUEST, "Unknown punishment");
+        }
+
+        if punishment.unwrap().applied_to != report.reported_user {
+            return failed(
+                StatusCode::BAD_REQUEST,
+                "Punishment doesn't belong to reported user",
+            );
+        }
+    }
+
+    match resolve_report(&ctx, id, user.id, body.punishment_id, body.resolution).await {
+        Ok(None) => failed(StatusCode::CONFLICT, "Report is already closed"),
+        Ok(Some(report)) => {
+            info!(
+                "{} closed report #{} as {}",
+                user.username, report.id, report.status
+            );
+            respond(Ok(report))
+        }
+        Err(error) => respond(Err(error)),
+    }
+}
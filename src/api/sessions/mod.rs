@@ -0,0 +1,21 @@
+use axum::{routing::get, Router};
+use serde::Deserialize;
+
+use self::routes::get_sessions;
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    //User id or username
+    pub user: Option<String>,
+    pub ip: Option<String>,
+    pub hwid: Option<String>,
+    pub source: Option<String>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub fn router() -> Router {
+    Router::new().route("/", get(get_sessions))
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, Extension};
+use tracing::error;
+
+use crate::{
+    api::{auth::middleware::TokenAuth, failed, require_manager, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        session_utils::{get_login_history, LoginHistoryEntry, LoginHistoryFilter},
+        user_utils::find_user_by_id_or_username,
+    },
+};
+
+use super::SessionsQuery;
+
+//Full login history including hardware ids, used to investigate account sharing
+pub async fn get_sessions(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Extension(auth): Extension<TokenAuth>,
+    Query(query): Query<SessionsQuery>,
+) -> ApiResponse<Vec<LoginHistoryEntry>> {
+    if let Err(response) = require_manager(&ctx, user, &auth).await {
+        return response;
+    }
+
+    let mut user_id = None;
+
+    if let Some(term) = query.user {
+        let target = find_user_by_id_or_username(&ctx.pool, term).await;
+
+        if let Err(error) = target {
+            error!("Error getting user: {:?}", error);
+            return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+
+        match target.unwrap() {
+            Some(target) => user_id = Some(target.id),
+            None => return failed(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+
+    let filter = LoginHistoryFilter {
+        user_id,
+        ip: query.ip,
+        hwid: query.hwid.filter(|hwid| !hwid.is_empty()),
+        source: query.source,
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_login_history(&ctx.pool, &filter, offset, limit).await)
+}
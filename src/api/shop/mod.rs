@@ -0,0 +1,27 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+
+use self::routes::{buy, catalogue, history};
+
+pub mod routes;
+
+#[derive(Debug, Deserialize)]
+pub struct PurchaseBody {
+    //username_change | donor_days | background_slot
+    pub item: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurchasesQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(catalogue))
+        .route("/purchases", get(history).post(buy))
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, Extension, Json};
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::shop_utils::{
+        get_catalogue, get_purchases, purchase_item, ShopError, ShopItem, ShopItemInfo,
+        ShopPurchase,
+    },
+};
+
+use super::{PurchaseBody, PurchasesQuery};
+
+pub async fn catalogue() -> ApiResponse<Vec<ShopItemInfo>> {
+    respond(Ok(get_catalogue()))
+}
+
+pub async fn buy(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Json(body): Json<PurchaseBody>,
+) -> ApiResponse<ShopPurchase> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let item = ShopItem::parse(&body.item);
+
+    if item.is_none() {
+        return failed(StatusCode::BAD_REQUEST, "Unknown item");
+    }
+
+    match purchase_item(&ctx, &user, item.unwrap()).await {
+        Ok(purchase) => respond(Ok(purchase)),
+        Err(ShopError::Internal(error)) => respond(Err(error)),
+        Err(ShopError::Restricted) => {
+            failed(StatusCode::FORBIDDEN, ShopError::Restricted.message())
+        }
+        Err(error) => failed(StatusCode::CONFLICT, error.message()),
+    }
+}
+
+pub async fn history(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(user): Extension<Option<User>>,
+    Query(query): Query<PurchasesQuery>,
+) -> ApiResponse<Vec<ShopPurchase>> {
+    let user = match require_user(user) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    respond(get_purchases(&ctx.pool, user.id, offset, limit).await)
+}
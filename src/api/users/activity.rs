@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        activity_utils::{get_user_activity, UserActivity},
+        permission_utils::{has_capability, Capabilities},
+        preference_utils::{get_user_preferences, update_user_preferences, UserPreferences},
+        user_utils::{find_user_by_id_or_username, is_restricted},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreferencesBody {
+    pub notify_first_place_lost: Option<bool>,
+}
+
+async fn find_target<T>(ctx: &Context, id: String) -> Result<User, ApiResponse<T>> {
+    let user = find_user_by_id_or_username(&ctx.pool, id).await;
+
+    if let Err(error) = user {
+        error!("Error getting user: {:?}", error);
+        return Err(failed(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error.",
+        ));
+    }
+
+    user.unwrap()
+        .ok_or_else(|| failed(StatusCode::NOT_FOUND, "Not found"))
+}
+
+pub async fn get_activity(
+    Extension(ctx): Extension<Arc<Context>>,
+    Path(id): Path<String>,
+    Query(query): Query<ActivityQuery>,
+) -> ApiResponse<Vec<UserActivity>> {
+    let user = match find_target(&ctx, id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if is_restricted(&user).await {
+        return failed(StatusCode::FORBIDDEN, "This profile is unaccessable.");
+    }
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(20).clamp(1, 50);
+
+    respond(get_user_activity(&ctx.pool, user.id, offset, limit).await)
+}
+
+pub async fn get_preferences(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+) -> ApiResponse<UserPreferences> {
+    let author = match require_user(author) {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let user = match find_target(&ctx, id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if user.id != author.id && !has_capability(&ctx, &author, Capabilities::MODERATE).await {
+        return failed(StatusCode::FORBIDDEN, "Not authorized");
+    }
+
+    respond(get_user_preferences(&ctx.pool, user.id).await)
+}
+
+pub async fn update_preferences(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+    Json(body): Json<PreferencesBody>,
+) -> ApiResponse<UserPreferences> {
+    let author = match require_user(author) {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let user = match find_target(&ctx, id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if user.id != author.id {
+        return failed(StatusCode::FORBIDDEN, "Not authorized");
+    }
+
+    respond(update_user_preferences(&ctx.pool, user.id, body.notify_first_place_lost).await)
+}
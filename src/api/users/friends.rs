@@ -8,9 +8,13 @@ use crate::{
     api::FailableResponse,
     context::Context,
     db::user::User,
-    utils::user_utils::{
-        add_friend, find_user_by_id_or_username, get_user_followers, get_user_relationships,
-        is_donator, is_user_manager, remove_friend,
+    utils::{
+        notification_utils::{notify, NewNotification, NotificationKind},
+        permission_utils::{has_capability, Capabilities},
+        user_utils::{
+            add_friend, find_user_by_id_or_username, get_user_followers, get_user_relationships,
+            remove_friend,
+        },
     },
 };
 
@@ -47,7 +51,7 @@ pub async fn get_friends(
     let user = user.unwrap();
     let id = id.parse::<i32>().unwrap();
 
-    if user.id != id && !is_user_manager(&user) {
+    if user.id != id && !has_capability(&ctx, &user, Capabilities::MODERATE).await {
         return (
             StatusCode::FORBIDDEN,
             Json(FailableResponse {
@@ -150,6 +154,18 @@ pub async fn change_friend_status(
         remove_friend(&ctx.pool, &user.id, &requested_user.id).await;
     } else {
         add_friend(&ctx.pool, &user.id, &requested_user.id).await;
+
+        notify(
+            &ctx.pool,
+            NewNotification {
+                user_id: requested_user.id,
+                kind: NotificationKind::NewFollower,
+                subject: user.id.to_string(),
+                text: format!("{} started following you!", user.username),
+                avatar_of: Some(user.id),
+            },
+        )
+        .await;
     }
 
     (
@@ -194,7 +210,9 @@ pub async fn get_followers(
     }
 
     let authorized_user = authorized_user.unwrap();
-    if authorized_user.id != user.id && !is_user_manager(&user) {
+    if authorized_user.id != user.id
+        && !has_capability(&ctx, &authorized_user, Capabilities::MODERATE).await
+    {
         return (
             StatusCode::FORBIDDEN,
             Json(FailableResponse {
@@ -205,7 +223,7 @@ pub async fn get_followers(
         );
     }
 
-    if !is_donator(&authorized_user) {
+    if !has_capability(&ctx, &authorized_user, Capabilities::SUPPORTER).await {
         return (
             StatusCode::FORBIDDEN,
             Json(FailableResponse {
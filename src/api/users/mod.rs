@@ -1,15 +1,17 @@
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::utils::{http_utils::OsuMode, Badge, UserDbStats};
+use crate::utils::{group_utils::Group, http_utils::OsuMode, Badge, UserDbStats};
 
+pub mod activity;
 pub mod avatar;
 pub mod friends;
 pub mod scores;
 pub mod security;
+pub mod username;
 pub mod users;
 
 #[derive(Debug, Serialize)]
@@ -48,6 +50,7 @@ pub struct PublicUserProfile {
     pub created_at: chrono::NaiveDateTime,
     pub last_seen: chrono::NaiveDateTime,
     pub badges: Vec<Badge>,
+    pub groups: Vec<Group>,
     pub is_donor: bool,
     pub background_url: Option<String>,
     pub leveling: Leveling,
@@ -92,6 +95,27 @@ pub fn router() -> Router {
             "/api/v2/users/:id/violations",
             get(crate::api::users::security::get_user_account_standing),
         )
+        .route(
+            "/api/v2/users/:id/lockout",
+            delete(crate::api::users::security::clear_user_lockout),
+        )
+        .route(
+            "/api/v2/users/:id/sessions",
+            get(crate::api::users::security::get_user_sessions),
+        )
+        .route(
+            "/api/v2/users/:id/verify",
+            post(crate::api::users::security::verify_user),
+        )
+        .route(
+            "/api/v2/users/:id/activity",
+            get(crate::api::users::activity::get_activity),
+        )
+        .route(
+            "/api/v2/users/:id/preferences",
+            get(crate::api::users::activity::get_preferences)
+                .patch(crate::api::users::activity::update_preferences),
+        )
         .route(
             "/api/v2/users/:id/graph",
             get(crate::api::users::users::get_user_graph),
@@ -108,6 +132,10 @@ pub fn router() -> Router {
             "/api/v2/users/:id/followers",
             get(crate::api::users::friends::get_followers),
         )
+        .route(
+            "/api/v2/users/:id/username",
+            post(crate::api::users::username::update_username),
+        )
         .route(
             "/api/v2/users/:id/avatar",
             post(crate::api::users::avatar::upload_avatar),
@@ -15,6 +15,7 @@ use crate::{
     db::user::User,
     utils::{
         http_utils::OsuMode,
+        permission_utils::{has_capability, Capabilities},
         score_utils::{get_user_best_scores, get_user_recent_scores, SortMode},
         user_utils::{find_user_by_id_or_username, is_restricted},
     },
@@ -69,7 +70,7 @@ pub async fn get_best_scores(
         let mut is_admin = false;
 
         if let Some(u) = current_user {
-            is_admin = u.permissions & 1 > 0;
+            is_admin = has_capability(&ctx, &u, Capabilities::MODERATE).await;
 
             if u.id == user.id {
                 is_admin = true;
@@ -161,7 +162,7 @@ pub async fn get_recent_scores(
         let mut is_admin = false;
 
         if let Some(u) = current_user {
-            is_admin = u.permissions & 1 > 0;
+            is_admin = has_capability(&ctx, &u, Capabilities::MODERATE).await;
 
             if u.id == user.id {
                 is_admin = true;
@@ -1,14 +1,28 @@
 use std::sync::Arc;
 
-use axum::{extract::Path, http::StatusCode, Extension, Json};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Extension, Json,
+};
 use chrono::NaiveDateTime;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
 use crate::{
-    api::FailableResponse,
+    api::{failed, require_user, respond, ApiResponse, FailableResponse},
     context::Context,
-    utils::user_utils::{find_user_by_id_or_username, get_user_recent_vilations, is_restricted},
+    db::user::User,
+    utils::{
+        lockout_utils::{clear_lockout, LockoutTarget},
+        permission_utils::{has_capability, Capabilities},
+        session_utils::{get_login_history, LoginHistoryFilter},
+        user_utils::{
+            find_user_by_id_or_username, get_user_recent_vilations, is_pending_verification,
+            is_restricted, send_bancho_message,
+        },
+        verification_utils::{complete_verification, confirm_verification_code, CodeConfirmation},
+    },
 };
 
 #[derive(Debug, Serialize)]
@@ -25,6 +39,37 @@ pub struct AccountStanding {
     pub vilations: Vec<AccountViolation>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct UserSession {
+    pub source: String,
+    pub ip: Option<String>,
+    pub country: Option<String>,
+    pub client_version: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyBody {
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClearLockoutQuery {
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClearedLockout {
+    pub user: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<bool>,
+}
+
 pub async fn get_user_account_standing(
     Extension(ctx): Extension<Arc<Context>>,
     Path(id): Path<String>,
@@ -119,3 +164,231 @@ pub async fn get_user_account_standing(
         }),
     )
 }
+
+pub async fn clear_user_lockout(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+    Query(query): Query<ClearLockoutQuery>,
+) -> (StatusCode, Json<FailableResponse<ClearedLockout>>) {
+    if author.is_none() {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(FailableResponse {
+                ok: false,
+                message: Some("Not logged in".to_string()),
+                data: None,
+            }),
+        );
+    }
+
+    let author = author.unwrap();
+
+    if !has_capability(&ctx, &author, Capabilities::MODERATE).await {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(FailableResponse {
+                ok: false,
+                message: Some("Not authorized".to_string()),
+                data: None,
+            }),
+        );
+    }
+
+    let user = find_user_by_id_or_username(&ctx.pool, id).await;
+
+    if let Err(error) = user {
+        error!("Error getting user: {:?}", error);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FailableResponse {
+                ok: false,
+                message: Some(String::from("Internal server error.")),
+                data: None,
+            }),
+        );
+    }
+
+    let user = user.unwrap();
+
+    if user.is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(FailableResponse {
+                ok: false,
+                message: Some(String::from("Not found")),
+                data: None,
+            }),
+        );
+    }
+
+    let user = user.unwrap();
+
+    let cleared_user = clear_lockout(&ctx.redis, LockoutTarget::User, &user.username).await;
+
+    if cleared_user.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FailableResponse {
+                ok: false,
+                message: Some(String::from("Internal server error.")),
+                data: None,
+            }),
+        );
+    }
+
+    let mut cleared_ip = None;
+
+    if let Some(ip) = query.ip {
+        let result = clear_lockout(&ctx.redis, LockoutTarget::Ip, &ip).await;
+
+        if result.is_err() {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(FailableResponse {
+                    ok: false,
+                    message: Some(String::from("Internal server error.")),
+                    data: None,
+                }),
+            );
+        }
+
+        cleared_ip = result.ok();
+    }
+
+    info!(
+        "{} cleared login lockout of {} (ip: {:?})",
+        author.username, user.username, cleared_ip
+    );
+
+    (
+        StatusCode::OK,
+        Json(FailableResponse {
+            ok: true,
+            message: None,
+            data: Some(ClearedLockout {
+                user: cleared_user.unwrap(),
+                ip: cleared_ip,
+            }),
+        }),
+    )
+}
+
+//Users can only see their own sessions, hardware ids are left out
+pub async fn get_user_sessions(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+    Query(query): Query<SessionsQuery>,
+) -> ApiResponse<Vec<UserSession>> {
+    let author = match require_user(author) {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let user = find_user_by_id_or_username(&ctx.pool, id).await;
+
+    if let Err(error) = user {
+        error!("Error getting user: {:?}", error);
+        return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.");
+    }
+
+    let user = user.unwrap();
+
+    if user.is_none() {
+        return failed(StatusCode::NOT_FOUND, "Not found");
+    }
+
+    let user = user.unwrap();
+
+    if user.id != author.id && !has_capability(&ctx, &author, Capabilities::MODERATE).await {
+        return failed(StatusCode::FORBIDDEN, "Not authorized");
+    }
+
+    let filter = LoginHistoryFilter {
+        user_id: Some(user.id),
+        ..Default::default()
+    };
+
+    let limit = query.limit.unwrap_or(20).clamp(1, 50);
+    let sessions = get_login_history(&ctx.pool, &filter, 0, limit).await;
+
+    respond(sessions.map(|sessions| {
+        sessions
+            .into_iter()
+            .map(|session| UserSession {
+                source: session.source,
+                ip: session.ip,
+                country: session.country,
+                client_version: session.client_version,
+                created_at: session.created_at,
+            })
+            .collect()
+    }))
+}
+
+//Users confirm code sent by Mio, managers can verify held accounts directly
+pub async fn verify_user(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+    Json(body): Json<VerifyBody>,
+) -> ApiResponse<bool> {
+    let author = match require_user(author) {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    let user = find_user_by_id_or_username(&ctx.pool, id).await;
+
+    if let Err(error) = user {
+        error!("Error getting user: {:?}", error);
+        return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.");
+    }
+
+    let user = user.unwrap();
+
+    if user.is_none() {
+        return failed(StatusCode::NOT_FOUND, "Not found");
+    }
+
+    let user = user.unwrap();
+
+    if !is_pending_verification(&user) {
+        return failed(StatusCode::BAD_REQUEST, "Account is already verified");
+    }
+
+    if user.id == author.id {
+        if body.code.is_none() {
+            return failed(StatusCode::BAD_REQUEST, "Verification code is required");
+        }
+
+        let confirmed = confirm_verification_code(&ctx, &user, &body.code.unwrap()).await;
+
+        if let Err(error) = confirmed {
+            error!("{:#?}", error);
+            return failed(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.");
+        }
+
+        match confirmed.unwrap() {
+            CodeConfirmation::Confirmed => {}
+            CodeConfirmation::Invalid => {
+                return failed(StatusCode::BAD_REQUEST, "Invalid verification code")
+            }
+            CodeConfirmation::Expired => {
+                return failed(
+                    StatusCode::BAD_REQUEST,
+                    "Verification code has expired, log in again to receive a new one",
+                )
+            }
+        }
+    } else if has_capability(&ctx, &author, Capabilities::MODERATE).await {
+        complete_verification(&ctx, &user, Some(author.id), "Verified by staff").await;
+    } else {
+        return failed(StatusCode::FORBIDDEN, "Not authorized");
+    }
+
+    send_bancho_message(&user.id, "user:verified".to_string(), None).await;
+
+    respond(Ok(true))
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+use crate::{
+    api::{failed, require_user, respond, ApiResponse},
+    context::Context,
+    db::user::User,
+    utils::{
+        user_utils::to_safe,
+        username_utils::{change_username, UsernameError},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UsernameBody {
+    pub username: String,
+}
+
+pub async fn update_username(
+    Extension(ctx): Extension<Arc<Context>>,
+    Extension(author): Extension<Option<User>>,
+    Path(id): Path<String>,
+    Json(body): Json<UsernameBody>,
+) -> ApiResponse<String> {
+    let author = match require_user(author) {
+        Ok(author) => author,
+        Err(response) => return response,
+    };
+
+    if id != author.id.to_string() && to_safe(&id) != author.username_safe {
+        return failed(StatusCode::FORBIDDEN, "Not authorized");
+    }
+
+    match change_username(&ctx, &author, &body.username).await {
+        Ok(user) => respond(Ok(user.username)),
+        Err(UsernameError::Internal(error)) => respond(Err(error)),
+        Err(UsernameError::Taken) => failed(StatusCode::CONFLICT, UsernameError::Taken.message()),
+        Err(UsernameError::Restricted) => {
+            failed(StatusCode::FORBIDDEN, UsernameError::Restricted.message())
+        }
+        Err(error) => failed(StatusCode::BAD_REQUEST, error.message()),
+    }
+}
@@ -16,11 +16,12 @@ use crate::{
     utils::{
         beatmap_utils::PublicBeatmap,
         http_utils::OsuMode,
+        permission_utils::{has_capability, Capabilities},
         score_utils::{get_user_grades_count, SortMode},
         user_utils::{
             calculate_level, calculate_level_progress, find_user_by_id_or_username,
-            get_country_rank, get_rank, get_user_badges, get_user_followers, get_user_graph_data,
-            get_user_stats, is_restricted, is_user_friend, is_user_mutual,
+            get_user_badges, get_user_followers, get_user_graph_data, get_user_stats,
+            is_restricted, is_user_friend, is_user_mutual,
         },
         GraphEntry,
     },
@@ -75,7 +76,7 @@ pub async fn get_user(
         let mut is_admin = false;
 
         if let Some(u) = current_user.clone() {
-            is_admin = u.permissions & 1 > 0;
+            is_admin = has_capability(&ctx, &u, Capabilities::MODERATE).await;
 
             if u.id == user.id {
                 is_admin = true;
@@ -110,8 +111,22 @@ pub async fn get_user(
 
     let stats = stats.unwrap();
 
-    let global_rank = get_rank(&ctx.redis, &user, &mode).await;
-    let country_rank = get_country_rank(&ctx.redis, &user, &mode).await;
+    let global_rank = ctx.rankings.get_rank(&user, &mode).await;
+    let country_rank = ctx.rankings.get_country_rank(&user, &mode).await;
+
+    if global_rank.is_err() || country_rank.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FailableResponse {
+                ok: false,
+                message: Some(String::from("Internal server error.")),
+                data: None,
+            }),
+        );
+    }
+
+    let global_rank = global_rank.unwrap();
+    let country_rank = country_rank.unwrap();
 
     let badges = get_user_badges(&ctx.pool, &user).await;
     if let Err(error) = badges {
@@ -166,6 +181,12 @@ pub async fn get_user(
     let mut is_friend: Option<bool> = None;
     let mut is_mutual_friend: Option<bool> = None;
 
+    let groups = ctx
+        .groups
+        .get_user_groups(user.id)
+        .await
+        .unwrap_or_default();
+
     if let Some(u) = current_user {
         is_friend = Some(is_user_friend(&ctx.pool, &u.id, &user.id).await);
         is_mutual_friend = Some(
@@ -195,6 +216,7 @@ pub async fn get_user(
                 created_at: user.created_at,
                 last_seen: user.last_seen,
                 badges,
+                groups,
                 is_donor: user
                     .donor_until
                     .unwrap_or(NaiveDateTime::UNIX_EPOCH)
@@ -265,7 +287,7 @@ pub async fn get_user_graph(
         let mut is_admin = false;
 
         if let Some(u) = current_user {
-            is_admin = u.permissions & 1 > 0;
+            is_admin = has_capability(&ctx, &u, Capabilities::MODERATE).await;
 
             if u.id == user.id {
                 is_admin = true;
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use bancho_packets::{
     server::{
@@ -13,12 +16,13 @@ use tracing::{debug, error, info};
 
 use crate::{
     context::Context,
+    db::user::User,
     utils::{
         http_utils::OsuMode,
-        user_utils::{
-            get_rank, get_silenced_until, get_user_by_id, is_pending_verification, is_restricted,
-            to_safe,
-        },
+        hwid_utils::HwidHit,
+        permission_utils::get_capabilities,
+        user_utils::{is_pending_verification, is_restricted, to_safe},
+        verification_utils::{verify_first_login, VerificationOutcome},
     },
 };
 
@@ -26,6 +30,7 @@ use super::{channel_manager::ChannelManager, client::ClientData, presence::Prese
 
 pub struct BanchoManager {
     presences: RwLock<HashMap<String, Arc<Presence>>>,
+    captured_users: RwLock<HashSet<i32>>,
     context: Arc<Context>,
 }
 
@@ -33,6 +38,7 @@ impl BanchoManager {
     pub fn init(context: Arc<Context>) -> Self {
         Self {
             presences: RwLock::new(HashMap::new()),
+            captured_users: RwLock::new(HashSet::new()),
             context,
         }
     }
@@ -55,7 +61,7 @@ impl BanchoManager {
     }
 
     pub async fn init_bot(&self) -> bool {
-        let bot = get_user_by_id(&self.context.pool, 1).await;
+        let bot = self.context.users.get_by_id(1).await;
 
         if let Err(error) = bot {
             error!("Failed to init bot: {:#?}", error);
@@ -95,7 +101,7 @@ impl BanchoManager {
         lon: f32,
         channel_manager: &ChannelManager,
     ) -> Option<String> {
-        let user = get_user_by_id(&self.context.pool, user_id).await;
+        let user = self.context.users.get_by_id(user_id).await;
 
         if let Err(error) = user {
             error!("Failed to init user: {:#?}", error);
@@ -118,9 +124,13 @@ impl BanchoManager {
                     presence.clone().user.username,
                     presence.clone().user.clone().id
                 );
+                let capabilities = get_capabilities(&self.context, &user).await;
+
                 //Sending general packets
                 presence
-                    .enqueue(BanchoPrivileges::new(4).into_packet_data())
+                    .enqueue(
+                        BanchoPrivileges::new(capabilities.bancho_privileges()).into_packet_data(),
+                    )
                     .await;
                 presence
                     .enqueue(ProtocolVersion::new(19).into_packet_data())
@@ -135,7 +145,7 @@ impl BanchoManager {
                 channel_manager.join_channel("#osu", &presence).await;
                 channel_manager.join_channel("#announce", &presence).await;
 
-                let silenced_until = get_silenced_until(&self.context.pool, user.id).await;
+                let silenced_until = self.context.punishments.get_silenced_until(user.id).await;
                 if silenced_until > 0 {
                     let seconds_until = (silenced_until - Utc::now().timestamp()) as i32;
                     presence
@@ -144,8 +154,12 @@ impl BanchoManager {
                     *presence.silenced_until.write().await = silenced_until;
                 }
 
-                let user_rank = get_rank(&self.context.redis, &user, &OsuMode::Osu)
+                let user_rank = self
+                    .context
+                    .rankings
+                    .get_rank(&user, &OsuMode::Osu)
                     .await
+                    .unwrap_or_default()
                     .unwrap_or(0);
                 presence
                     .enqueue(
@@ -162,9 +176,7 @@ impl BanchoManager {
                         .into_packet_data(),
                     )
                     .await;
-                presence
-                    .refresh_stats(&self.context.pool, &self.context.redis)
-                    .await;
+                presence.refresh_stats(&self.context).await;
 
                 //Sending everyone about new user
                 for another_presence in self.presences.write().await.values() {
@@ -184,21 +196,6 @@ impl BanchoManager {
                         )
                         .await;
 
-                    if is_pending_verification(&user) {
-                        let _ = sqlx::query!(
-                            r#"UPDATE "User" SET permissions = 0, flags = 0 WHERE "id" = $1"#,
-                            user.id
-                        )
-                        .execute(&*self.context.pool)
-                        .await;
-                        presence
-                            .enqueue(
-                                Notification::new("You has been verified.".into())
-                                    .into_packet_data(),
-                            )
-                            .await;
-                    }
-
                     if !is_restricted(&user).await {
                         another_presence
                             .enqueue(
@@ -224,6 +221,12 @@ impl BanchoManager {
                     .await
                     .insert(token.clone(), Arc::new(presence.clone()));
 
+                if self.captured_users.read().await.contains(&user.id) {
+                    presence
+                        .start_recording(&self.context.config.capture_directory)
+                        .await;
+                }
+
                 if is_restricted(&user).await {
                     channel_manager.handle_private_message(&self.get_bot_presence().await.expect("Failed to get bot."), &BanchoMessage {
                         sender: "Mio".into(),
@@ -233,13 +236,10 @@ impl BanchoManager {
                     }).await;
                 }
 
-                let _ = sqlx::query!(
-                    r#"UPDATE "User" SET "lastSeen" = $1 WHERE id = $2"#,
-                    Utc::now().naive_utc(),
-                    user.id
-                )
-                .execute(&*self.context.pool)
-                .await;
+                self.context
+                    .users
+                    .update_last_seen(user.id, Utc::now().naive_utc())
+                    .await;
                 Some(token)
             }
             None => {
@@ -249,6 +249,67 @@ impl BanchoManager {
         }
     }
 
+    //First bancho login of a new account, verification is only completed when policy checks pass
+    pub async fn verify_pending_user(
+        &self,
+        token: &str,
+        ip: Option<&str>,
+        hwid_hits: &[HwidHit],
+        channel_manager: &ChannelManager,
+    ) {
+        let presence = self.get_presence_by_token(token.to_string()).await;
+
+        if presence.is_none() {
+            return;
+        }
+
+        let presence = presence.unwrap();
+
+        if !is_pending_verification(&presence.user) {
+            return;
+        }
+
+        let outcome = verify_first_login(&self.context, &presence.user, ip, hwid_hits).await;
+
+        if let Err(error) = outcome {
+            error!("Failed to verify user: {:#?}", error);
+            return;
+        }
+
+        let message = match outcome.unwrap() {
+            VerificationOutcome::Verified => {
+                presence
+                    .enqueue(Notification::new("You have been verified.".into()).into_packet_data())
+                    .await;
+                return;
+            }
+            VerificationOutcome::CodeRequired(code) => format!(
+                "Welcome! To finish verification enter code {} on https://{}/verify",
+                code, self.context.config.server_url
+            ),
+            VerificationOutcome::Held(_) => "Welcome! Your account is waiting for a review by our staff, you will get a message once it is verified.".to_string(),
+        };
+
+        let bot = self.get_bot_presence().await;
+
+        if bot.is_none() {
+            error!("Failed to get bot.");
+            return;
+        }
+
+        channel_manager
+            .handle_private_message(
+                &bot.unwrap(),
+                &BanchoMessage {
+                    sender: "Mio".into(),
+                    content: message,
+                    target: presence.user.username.to_string(),
+                    sender_id: 1,
+                },
+            )
+            .await;
+    }
+
     pub async fn get_online(&self) -> i32 {
         self.presences.read().await.len() as i32
     }
@@ -280,6 +341,50 @@ impl BanchoManager {
         }
     }
 
+    //Presence is shared by reference, so it is replaced with a copy holding the new user
+    pub async fn rename_presence(&self, user: User) -> Option<Arc<Presence>> {
+        let mut presences = self.presences.write().await;
+
+        let token = presences
+            .iter()
+            .find(|(_token, presence)| presence.user.id == user.id)
+            .map(|(token, _presence)| token.clone())?;
+
+        let mut renamed = (*presences[&token]).clone();
+        renamed.user = user;
+
+        let renamed = Arc::new(renamed);
+        presences.insert(token, Arc::clone(&renamed));
+        drop(presences);
+
+        if !is_restricted(&renamed.user).await {
+            let rank = self
+                .context
+                .rankings
+                .get_rank(&renamed.user, &renamed.get_active_mode().await)
+                .await
+                .unwrap_or_default()
+                .unwrap_or(0);
+
+            self.broadcast_packet(
+                UserPresence::new(
+                    renamed.user.id,
+                    renamed.user.username.clone().into(),
+                    (renamed.client_data.time_offset + 24) as u8,
+                    renamed.country,
+                    0,
+                    0.0,
+                    0.0,
+                    rank,
+                )
+                .into_packet_data(),
+            )
+            .await;
+        }
+
+        Some(renamed)
+    }
+
     pub async fn broadcast_packet(&self, packet: Vec<u8>) {
         let presences = self.presences.read().await;
         debug!("Found {} presences", presences.len());
@@ -306,10 +411,8 @@ impl BanchoManager {
 
         channel_manager.dispose_presence(&presence).await;
 
-        //Deleting it from presences map
-        let mut presences = self.presences.write().await;
-
-        presences.remove(&token);
+        //Deleting it from presences map, lock has to be released before broadcasting
+        self.presences.write().await.remove(&token);
 
         info!("User {} logged out.", presence.user.username);
         //Broadcasting logout packet
@@ -317,6 +420,30 @@ impl BanchoManager {
             .await;
     }
 
+    pub async fn set_capture(&self, user_id: i32, enabled: bool) -> bool {
+        if enabled {
+            self.captured_users.write().await.insert(user_id);
+        } else {
+            self.captured_users.write().await.remove(&user_id);
+        }
+
+        let presence = self.get_presence_by_user_id(user_id).await;
+
+        match presence {
+            Some(presence) => {
+                if enabled {
+                    return presence
+                        .start_recording(&self.context.config.capture_directory)
+                        .await;
+                }
+
+                presence.stop_recording().await;
+                true
+            }
+            None => true,
+        }
+    }
+
     pub async fn get_presences(&self) -> Vec<Arc<Presence>> {
         self.presences.read().await.values().cloned().collect()
     }
@@ -1,16 +1,21 @@
 use chrono::Utc;
+use serde_json::json;
+use tracing::error;
 
 use crate::{
     bancho::presence::Presence,
     utils::{
-        beatmap_utils::{announce_beatmap_status, get_beatmap_by_id, rank_to_str},
+        audit_utils::{insert_audit_log, AuditRecord},
+        beatmap_utils::{announce_beatmap_status, get_beatmap_by_id, rank_to_str, Beatmap},
         general_utils::to_fixed,
+        notification_utils::{notify, NewNotification, NotificationKind},
         performance_utils::calculate_performance_with_accuracy_list,
+        permission_utils::{has_capability, Capabilities},
+        report_utils::{submit_report, ReportError, ReportReason},
         score_utils::{format_mods, parse_mods},
         user_utils::{
-            find_user_by_id_or_username, insert_user_punishment, is_restricted, is_user_manager,
-            punishment_alert, remove_ranking, restrict_user, send_bancho_message,
-            send_message_announcement, unrestrict_user,
+            find_user_by_id_or_username, insert_user_punishment, is_restricted, punishment_alert,
+            send_bancho_message, send_message_announcement, RESTRICTED_PERMISSION,
         },
         Punishment,
     },
@@ -36,7 +41,7 @@ pub fn roll(_bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<S
 }
 
 pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<String> {
-    if !is_user_manager(&author.user) {
+    if !has_capability(&bot.ctx, &author.user, Capabilities::MODERATE).await {
         return Some("No permissions.".to_string());
     }
 
@@ -63,7 +68,7 @@ pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) ->
     let user = user.unwrap();
 
     if is_restricted(&user).await {
-        unrestrict_user(&bot.ctx.pool, user.id).await;
+        bot.ctx.users.unrestrict(user.id).await;
 
         let note = args
             .iter()
@@ -71,6 +76,19 @@ pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) ->
             .map(|x| x.to_owned())
             .collect::<Vec<String>>()
             .join(" ");
+        insert_audit_log(
+            &bot.ctx.pool,
+            AuditRecord {
+                actor_id: Some(author.user.id),
+                action: "punishment.unrestrict",
+                target_id: Some(user.id),
+                before: Some(json!({ "permissions": user.permissions })),
+                after: Some(json!({ "permissions": user.permissions & !RESTRICTED_PERMISSION })),
+                note: &note,
+            },
+        )
+        .await;
+
         punishment_alert(
             &Punishment {
                 id: String::new(),
@@ -89,10 +107,25 @@ pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) ->
         .await;
 
         //lifting all punishments
-        let _ = sqlx::query!(r#"UPDATE "Punishment" SET "expires" = true, "expiresAt" = '1970-01-01T00:00:00+00:00' WHERE "appliedTo" = $1"#, user.id).execute(&*bot.ctx.pool).await;
+        let _ = sqlx::query(r#"UPDATE "Punishment" SET "expires" = true, "expiresAt" = '1970-01-01T00:00:00+00:00', "liftedAt" = NOW() WHERE "appliedTo" = $1 AND "liftedAt" IS NULL"#)
+            .bind(user.id)
+            .execute(&*bot.ctx.pool)
+            .await;
         send_bancho_message(&user.id, "user:restricted".to_string(), None).await;
+
+        notify(
+            &bot.ctx.pool,
+            NewNotification {
+                user_id: user.id,
+                kind: NotificationKind::PunishmentLifted,
+                subject: user.id.to_string(),
+                text: "Your account restriction has been lifted.".to_string(),
+                avatar_of: None,
+            },
+        )
+        .await;
     } else {
-        restrict_user(&bot.ctx.pool, user.id).await;
+        bot.ctx.users.restrict(user.id).await;
 
         let note = args
             .iter()
@@ -100,7 +133,7 @@ pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) ->
             .map(|x| x.to_owned())
             .collect::<Vec<String>>()
             .join(" ");
-        insert_user_punishment(
+        let punishment = insert_user_punishment(
             &bot.ctx.pool,
             "CRITICAL".to_string(),
             author.user.id,
@@ -108,16 +141,153 @@ pub async fn restrict(bot: &mut MioBot, author: &Presence, args: Vec<String>) ->
             "RESTRICTION".to_string(),
             false,
             None,
-            note,
+            note.clone(),
         )
         .await;
-        remove_ranking(&bot.ctx.redis, &user).await;
+        let punishment_id = punishment
+            .map(|punishment| punishment.id)
+            .unwrap_or_default();
+
+        insert_audit_log(
+            &bot.ctx.pool,
+            AuditRecord {
+                actor_id: Some(author.user.id),
+                action: "punishment.restrict",
+                target_id: Some(user.id),
+                before: Some(json!({ "permissions": user.permissions })),
+                after: Some(json!({ "permissions": 8, "punishmentId": punishment_id })),
+                note: &note,
+            },
+        )
+        .await;
+        if let Err(error) = bot.ctx.rankings.remove(&user).await {
+            error!(
+                "Failed to remove ranking of {}: {:#?}",
+                user.username, error
+            );
+        }
         send_bancho_message(&user.id, "user:restricted".to_string(), None).await;
+
+        notify(
+            &bot.ctx.pool,
+            NewNotification {
+                user_id: user.id,
+                kind: NotificationKind::PunishmentApplied,
+                subject: punishment_id,
+                text: "Your account has been restricted, details are on \"Account standing\" page."
+                    .to_string(),
+                avatar_of: None,
+            },
+        )
+        .await;
     }
 
     Some("Done".to_string())
 }
 
+pub async fn report(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<String> {
+    let usage = "Usage: !report <username> [cheating/multi/toxic/name/spam] <reason>";
+
+    let username = args.first();
+
+    if username.is_none() || args.len() < 2 {
+        return Some(usage.to_string());
+    }
+
+    let mut words = args.iter().skip(1).peekable();
+
+    let category = words
+        .peek()
+        .and_then(|word| ReportReason::parse(word.as_str()));
+
+    if category.is_some() {
+        words.next();
+    }
+
+    let text = words
+        .map(|x| x.to_owned())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let report = submit_report(
+        &bot.ctx,
+        &author.user,
+        username.unwrap().to_owned(),
+        category.unwrap_or(ReportReason::Other),
+        text,
+        None,
+    )
+    .await;
+
+    match report {
+        Ok(report) => Some(format!(
+            "Thanks! Your report #{} against {} was sent to our staff.",
+            report.id, report.reported_username
+        )),
+        Err(ReportError::Internal(error)) => {
+            error!("Failed to submit report: {:#?}", error);
+            Some(ReportError::Internal(error).message().to_string())
+        }
+        Err(ReportError::EmptyReason) => Some(usage.to_string()),
+        Err(error) => Some(error.message().to_string()),
+    }
+}
+
+pub async fn capture(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<String> {
+    if !has_capability(&bot.ctx, &author.user, Capabilities::DEVELOPER).await {
+        return Some("No permissions.".to_string());
+    }
+
+    let usage = "Usage: !capture <username> <on/off>";
+    let username = args.first();
+    let state = args.get(1);
+
+    if username.is_none() || state.is_none() {
+        return Some(usage.to_string());
+    }
+
+    let enabled = match state.unwrap().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Some(usage.to_string()),
+    };
+
+    let user = find_user_by_id_or_username(&bot.ctx.pool, username.unwrap().to_owned()).await;
+
+    if user.is_err() {
+        return Some("Failed to fetch user".to_string());
+    }
+
+    let user = user.unwrap();
+
+    if user.is_none() {
+        return Some("Could not find user.".to_string());
+    }
+
+    let user = user.unwrap();
+
+    if !bot.bancho_manager.set_capture(user.id, enabled).await {
+        return Some("Failed to start packet capture, check logs.".to_string());
+    }
+
+    insert_audit_log(
+        &bot.ctx.pool,
+        AuditRecord {
+            actor_id: Some(author.user.id),
+            action: "capture.toggled",
+            target_id: Some(user.id),
+            after: Some(json!({ "enabled": enabled })),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    match enabled {
+        true => Some(format!("Capturing packets of {}.", user.username)),
+        false => Some(format!("Stopped capturing packets of {}.", user.username)),
+    }
+}
+
 pub async fn with(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<String> {
     let beatmaps = bot.user_beatmaps.lock().await;
     let beatmap = beatmaps.get(&author.user.id);
@@ -230,7 +400,7 @@ pub async fn acc(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Opti
 }
 
 pub async fn map(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Option<String> {
-    if author.user.permissions & 4 == 0 {
+    if !has_capability(&bot.ctx, &author.user, Capabilities::NOMINATE).await {
         return Some("Not enough permissions".to_string());
     }
     let beatmaps = bot.user_beatmaps.lock().await;
@@ -294,6 +464,26 @@ pub async fn map(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Opti
 
     let current_beatmap = current_beatmap.unwrap();
 
+    insert_audit_log(
+        &bot.ctx.pool,
+        AuditRecord {
+            actor_id: Some(author.user.id),
+            action: "beatmap.status",
+            target_id: None,
+            before: Some(json!({
+                "beatmapId": current_beatmap.beatmap_id,
+                "parentId": current_beatmap.parent_id,
+                "status": current_beatmap.status,
+            })),
+            after: Some(json!({ "status": new_beatmap_status, "scope": ranking_type })),
+            note: &format!(
+                "{} - {} [{}]",
+                current_beatmap.artist, current_beatmap.title, current_beatmap.version
+            ),
+        },
+    )
+    .await;
+
     match ranking_type.as_str() {
         "set" => {
             let _ = sqlx::query!(
@@ -346,6 +536,8 @@ pub async fn map(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Opti
             announce_beatmap_status(author, beatmap, &BeatmapStatus::from(new_beatmap_status))
                 .await;
 
+            notify_mapper(bot, beatmap, new_beatmap_status).await;
+
             Some(format!(
                 "Updated status for set {} - {}",
                 beatmap.artist, beatmap.title
@@ -366,6 +558,8 @@ pub async fn map(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Opti
                 let _ = sqlx::query!(r#"UPDATE "Score" SET "status" = 0 WHERE "beatmapChecksum" = $1 AND "status" = 2"#, current_beatmap.checksum).execute(&*bot.ctx.pool).await;
             }
 
+            notify_mapper(bot, beatmap, new_beatmap_status).await;
+
             Some(format!(
                 "Updated status for beatmap {} - {}[{}]",
                 beatmap.artist, beatmap.title, beatmap.version
@@ -374,3 +568,30 @@ pub async fn map(bot: &mut MioBot, author: &Presence, args: Vec<String>) -> Opti
         _ => Some("Unknown".to_string()),
     }
 }
+
+//Mappers are matched by name, maps of people without account here are skipped
+async fn notify_mapper(bot: &MioBot, beatmap: &Beatmap, status: i32) {
+    let mapper = bot.ctx.users.get_id(&beatmap.creator).await;
+
+    if mapper.is_none() {
+        return;
+    }
+
+    notify(
+        &bot.ctx.pool,
+        NewNotification {
+            user_id: mapper.unwrap(),
+            kind: NotificationKind::BeatmapStatus,
+            subject: beatmap.parent_id.to_string(),
+            text: format!(
+                "Your beatmap {} - {} [{}] is now {}!",
+                beatmap.artist,
+                beatmap.title,
+                beatmap.version,
+                rank_to_str(&BeatmapStatus::from(status))
+            ),
+            avatar_of: None,
+        },
+    )
+    .await;
+}
@@ -14,7 +14,7 @@ use tracing::debug;
 use crate::{
     bancho::{
         bancho_manager::BanchoManager,
-        bot::commands::{acc, map, restrict, with},
+        bot::commands::{acc, capture, map, report, restrict, with},
         channel_manager::ChannelManager,
         presence::Presence,
     },
@@ -33,7 +33,7 @@ pub type BotCommand = fn(&mut MioBot, &Presence, Vec<String>) -> Option<String>;
 
 pub struct MioBot {
     presence: Arc<Presence>,
-    _bancho_manager: Arc<BanchoManager>,
+    pub bancho_manager: Arc<BanchoManager>,
     channel_manager: Arc<ChannelManager>,
     pub ctx: Arc<Context>,
     pub commands: HashMap<String, BotCommand>,
@@ -52,7 +52,7 @@ impl MioBot {
             presence: Arc::clone(&presence),
             ctx,
             commands: HashMap::new(),
-            _bancho_manager: Arc::clone(&bancho_manager),
+            bancho_manager: Arc::clone(&bancho_manager),
             channel_manager: Arc::clone(&channel_manager),
             beatmap_regex: Regex::new(r#"\/(\d+)\s"#).expect("Failed to parse regex"),
             user_beatmaps: Mutex::new(HashMap::new()),
@@ -298,6 +298,30 @@ impl MioBot {
                     .await;
                 }
             }
+            "report" => {
+                //Reports are always answered privately
+                let response = report(self, author, args.clone()).await;
+                if let Some(response) = response {
+                    self.handle_response(author.user.username.to_string(), response, author)
+                        .await;
+                }
+            }
+            "capture" => {
+                let response = capture(self, author, args.clone()).await;
+                if let Some(response) = response {
+                    self.handle_response(
+                        message
+                            .target
+                            .to_string()
+                            .eq(&self.presence.user.username.clone())
+                            .then(|| author.user.username.to_string())
+                            .unwrap_or(message.target.to_string()),
+                        response,
+                        author,
+                    )
+                    .await;
+                }
+            }
             _ => {}
         }
         let command = self.commands.get(&command_name);
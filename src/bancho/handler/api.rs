@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{extract::Path, http::StatusCode, Extension, Json};
 use bancho_packets::{
-    server::{Notification, UserLogout},
+    server::{BanchoPrivileges, Notification, UserLogout},
     BanchoMessage, BanchoPacket, BanchoPacketWrite,
 };
 
@@ -14,6 +14,7 @@ use crate::{
     context::Context,
     utils::{
         beatmap_utils::{get_beatmap_by_id, PublicBeatmap},
+        permission_utils::get_capabilities,
         user_utils::{find_user_by_id_or_username, is_restricted},
     },
 };
@@ -365,9 +366,9 @@ pub async fn refresh_user(
 
             let presence = presence.unwrap();
 
-            presence.refresh_stats(&ctx.pool, &ctx.redis).await;
+            presence.refresh_stats(&ctx).await;
             bancho_manager
-                .broadcast_packet(presence.stats_packet(&ctx.redis).await.into_packet())
+                .broadcast_packet(presence.stats_packet(&ctx).await.into_packet())
                 .await;
 
             (
@@ -422,9 +423,9 @@ pub async fn refresh_user(
                     }),
                 );
             }
-            presence.refresh_stats(&ctx.pool, &ctx.redis).await;
+            presence.refresh_stats(&ctx).await;
             bancho_manager
-                .broadcast_packet(presence.stats_packet(&ctx.redis).await.into_packet())
+                .broadcast_packet(presence.stats_packet(&ctx).await.into_packet())
                 .await;
 
             channel_manager.handle_private_message(&bancho_manager.get_bot_presence().await.expect("Failed to get bot"), &BanchoMessage { sender: "Mio".to_string(), content: "Your account currently in restricted state, more details you can get from \"Account standing\" page on the website".to_string(), target: presence.user.username.to_string(), sender_id: 1 }).await;
@@ -446,6 +447,183 @@ pub async fn refresh_user(
                 }),
             )
         }
+        "user:verified" => {
+            let presence = bancho_manager
+                .get_presence_by_user_id(payload.user_id)
+                .await;
+
+            if presence.is_none() {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("Presence not found".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            presence
+                .unwrap()
+                .enqueue(Notification::new("You have been verified.".into()).into_packet_data())
+                .await;
+
+            (
+                StatusCode::OK,
+                Json(FailableResponse {
+                    ok: true,
+                    message: None,
+                    data: Some("Sent verified notification.".to_string()),
+                }),
+            )
+        }
+        "user:privileges" => {
+            let presence = bancho_manager
+                .get_presence_by_user_id(payload.user_id)
+                .await;
+
+            if presence.is_none() {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("Presence not found".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            let presence = presence.unwrap();
+
+            //Presence keeps user from login, donor time or restriction may have changed since
+            let user = ctx
+                .users
+                .get_by_id(presence.user.id)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(presence.user.clone());
+            let capabilities = get_capabilities(&ctx, &user).await;
+
+            presence
+                .enqueue(BanchoPrivileges::new(capabilities.bancho_privileges()).into_packet_data())
+                .await;
+
+            (
+                StatusCode::OK,
+                Json(FailableResponse {
+                    ok: true,
+                    message: None,
+                    data: Some("Sent privileges packet.".to_string()),
+                }),
+            )
+        }
+        "user:renamed" => {
+            let user = ctx.users.get_by_id(payload.user_id).await.ok().flatten();
+
+            if user.is_none() {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("User not found".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            let presence = bancho_manager.rename_presence(user.unwrap()).await;
+
+            if presence.is_none() {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("Presence not found".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            let presence = presence.unwrap();
+
+            presence
+                .enqueue(
+                    Notification::new(
+                        format!(
+                            "Your username has been changed to {}, use it on your next login.",
+                            presence.user.username
+                        )
+                        .into(),
+                    )
+                    .into_packet_data(),
+                )
+                .await;
+
+            (
+                StatusCode::OK,
+                Json(FailableResponse {
+                    ok: true,
+                    message: None,
+                    data: Some("Renamed presence.".to_string()),
+                }),
+            )
+        }
+        "user:notification" => {
+            let presence = bancho_manager
+                .get_presence_by_user_id(payload.user_id)
+                .await;
+
+            if presence.is_none() {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("Presence not found".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            let text = payload.args.join(" ");
+
+            presence
+                .unwrap()
+                .enqueue(Notification::new(text.into()).into_packet_data())
+                .await;
+
+            (
+                StatusCode::OK,
+                Json(FailableResponse {
+                    ok: true,
+                    message: None,
+                    data: Some("Sent notification.".to_string()),
+                }),
+            )
+        }
+        "user:capture" => {
+            let enabled = payload.args.first().map(|x| x == "on").unwrap_or(false);
+
+            if !bancho_manager.set_capture(payload.user_id, enabled).await {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(FailableResponse {
+                        ok: false,
+                        message: Some("Failed to start packet capture".to_string()),
+                        data: None,
+                    }),
+                );
+            }
+
+            (
+                StatusCode::OK,
+                Json(FailableResponse {
+                    ok: true,
+                    message: None,
+                    data: Some(format!("Packet capture enabled: {}", enabled)),
+                }),
+            )
+        }
         _ => (
             StatusCode::OK,
             Json(FailableResponse {
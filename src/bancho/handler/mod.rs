@@ -16,17 +16,18 @@ use bancho_packets::{
 use chrono::Utc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
-use webhook::client::WebhookClient;
 
 use crate::{
-    bancho::{bot::mio::MioBot, channel_manager::ChannelManager, client::ClientData},
+    bancho::{
+        bot::mio::MioBot, channel_manager::ChannelManager, client::ClientData,
+        recorder::CaptureDirection,
+    },
     context::Context,
     utils::{
-        ip_utils::{get_ip_info, Country},
-        user_utils::{
-            find_hwids, get_user_by_id, get_user_id, update_user_country, update_user_hwid,
-            validate_auth,
-        },
+        hwid_utils::{apply_hwid_policy, evaluate_hwid_policy},
+        ip_utils::{get_client_ip, Country},
+        lockout_utils::{check_login, LoginCheck},
+        session_utils::{insert_login_history, LoginRecord},
     },
 };
 
@@ -144,96 +145,67 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
         let username = username.unwrap();
         let password = password.unwrap();
 
-        let is_auth_ok = validate_auth(&ctx.redis, &ctx.pool, username, password).await;
+        let ip = get_client_ip(ctx, &parts);
 
-        if !is_auth_ok {
-            return login_failed(host.to_string(), "Invalid credentials".to_string());
+        match check_login(ctx, username, password, ip.as_deref(), "bancho").await {
+            LoginCheck::Ok => {}
+            LoginCheck::InvalidCredentials => {
+                return login_failed(host.to_string(), "Invalid credentials".to_string());
+            }
+            LoginCheck::LockedOut(remaining) => {
+                return login_failed(
+                    host.to_string(),
+                    format!(
+                        "Too many failed login attempts, try again in {} minutes",
+                        (remaining + 59) / 60
+                    ),
+                );
+            }
         }
 
-        let user_id = get_user_id(&ctx.redis, &ctx.pool, username).await;
+        let user_id = ctx.users.get_id(username).await;
         if user_id.is_none() {
             return login_failed(host.to_string(), "Invalid credentials".to_string());
         }
 
         let user_id = user_id.unwrap();
 
-        let user = get_user_by_id(&ctx.pool, user_id).await.unwrap().unwrap();
+        let user = ctx.users.get_by_id(user_id).await.unwrap().unwrap();
 
         let client_data = lines.next();
         let client_data = ClientData::from(client_data.unwrap().to_string());
 
-        let users_with_current_hwid = find_hwids(&ctx.pool, &client_data.hwid).await;
+        let hwid_hits = evaluate_hwid_policy(ctx, &user, &client_data.hwid).await;
 
-        if let Err(e) = users_with_current_hwid {
-            error!("Failed to find user: {:#?}", e);
+        if let Err(e) = hwid_hits {
+            error!("Failed to evaluate hwid policy: {:#?}", e);
             return login_server_error();
         }
 
-        let users_with_current_hwid = users_with_current_hwid.unwrap();
+        let hwid_hits = hwid_hits.unwrap();
 
-        if users_with_current_hwid.len() > 1 && ctx.config.alert_discord_webhook.clone().is_some() {
-            let formatted_report = users_with_current_hwid
-                .iter()
-                .map(|entry| {
-                    format!(
-                        "{} -> https://{}/users/{}",
-                        entry.user.username, ctx.config.server_url, entry.user.id
-                    )
-                })
-                .collect::<Vec<String>>()
-                .join("\n");
-            //Reporting it to alerts
-            let client =
-                WebhookClient::new(ctx.config.alert_discord_webhook.clone().unwrap().as_str());
-            let is_report_ok = client
-                .send(|message| {
-                    message
-                        .content("Multiple users with the same HWID found!")
-                        .embed(|embed| {
-                            embed
-                                .author(
-                                    user.username.as_str(),
-                                    Some(format!(
-                                        "https://{}/users/{}",
-                                        ctx.config.server_url, user.id
-                                    )),
-                                    Some(format!(
-                                        "https://a.{}/{}",
-                                        ctx.config.server_url, user.id
-                                    )),
-                                )
-                                .description(formatted_report.as_str())
-                                .footer(format!("Host: {}", host).as_str(), None)
-                        })
-                })
-                .await;
-            if let Err(e) = is_report_ok {
-                error!("Report of hwid was unsuccessful: {}", e);
-                return login_server_error();
-            }
+        if !apply_hwid_policy(ctx, &user, &hwid_hits, host).await {
+            return login_failed(
+                host.to_string(),
+                "This machine is linked to another account, contact support".to_string(),
+            );
         }
 
-        update_user_hwid(&ctx.pool, &user, &client_data.hwid).await;
-        let ip_header = parts.headers.get("Cf-Connecting-Ip");
-        let mut ip: Option<String> = None;
+        ctx.users.update_hwid(&user, &client_data.hwid).await;
+        let country = ctx.geoip.lookup(ip.clone()).await;
 
-        if ip_header.is_some() {
-            ip = Some(ip_header.unwrap().to_str().unwrap().to_string());
-        }
-
-        if let Some(ref completed_ip) = ip {
-            if completed_ip == "127.0.0.1" {
-                ip = None;
-            }
-        }
-        let country = get_ip_info(ip).await;
+        let country_code = country.as_ref().map(|country| country.code.clone());
+        let client_version = client_data.client_version.clone();
+        let hwid = client_data.hwid.clone();
 
         let mut code = 0_u8;
         let mut lat = 0.0;
         let mut lon = 0.0;
         if let Some(country) = country {
             if user.country == "XX" {
-                update_user_country(&ctx.pool, user.id, country.clone().code).await;
+                ctx.users
+                    .update_country(user.id, country.clone().code)
+                    .await;
             }
 
             lat = country.lat;
@@ -251,6 +223,24 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
 
         let token = presence.unwrap();
 
+        manager
+            .verify_pending_user(&token, ip.as_deref(), &hwid_hits, channel_manager)
+            .await;
+
+        insert_login_history(
+            &ctx.pool,
+            &LoginRecord {
+                user_id: user.id,
+                source: "bancho",
+                ip: ip.as_deref(),
+                country: country_code.as_deref(),
+                client_version: Some(&client_version),
+                hwid: Some(&hwid),
+                ..Default::default()
+            },
+        )
+        .await;
+
         return Response::builder()
             .header("cho-token", token)
             .body(Body::from(
@@ -314,6 +304,8 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
     }
     let body = body.unwrap();
 
+    presence.record(CaptureDirection::Inbound, &body).await;
+
     let reader = PacketReader::new(&body);
 
     for packet in reader {
@@ -322,18 +314,18 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
 
         match id {
             bancho_packets::PacketId::OSU_USER_REQUEST_STATUS_UPDATE => {
-                presence.refresh_stats(&ctx.pool, &ctx.redis).await;
+                presence.refresh_stats(ctx).await;
             }
             bancho_packets::PacketId::OSU_USER_CHANGE_ACTION => {
                 let data = ClientChangeAction::read(&mut payload_reader);
 
                 if let Some(status) = data.clone() {
                     presence.update_status(status.clone()).await;
-                    presence.refresh_stats(&ctx.pool, &ctx.redis).await;
+                    presence.refresh_stats(ctx).await;
 
                     //Broadcasting to everyone about new status
                     manager
-                        .broadcast_packet(presence.stats_packet(&ctx.redis).await)
+                        .broadcast_packet(presence.stats_packet(ctx).await)
                         .await;
                 }
             }
@@ -415,7 +407,7 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
                     let user_presence = user_presence.unwrap();
 
                     presence
-                        .enqueue(user_presence.stats_packet(&ctx.redis).await)
+                        .enqueue(user_presence.stats_packet(ctx).await)
                         .await;
                 }
             }
@@ -445,6 +437,7 @@ async fn bancho_post(req: axum::http::Request<Body>) -> Response<Body> {
     }
 
     let packets = presence.dequeue().await;
+    presence.record(CaptureDirection::Outbound, &packets).await;
     debug!("Sending back buffer with: {} bytes", packets.len());
     Response::builder().body(Body::from(packets)).unwrap()
 }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
     routing::{get, post},
@@ -25,6 +25,9 @@ pub mod channel_manager;
 pub mod client;
 pub mod handler;
 pub mod presence;
+pub mod recorder;
+#[cfg(test)]
+mod replay;
 
 pub async fn serve_bancho(ctx: Context) {
     let ctx = Arc::new(ctx);
@@ -86,5 +89,10 @@ pub async fn serve_bancho(ctx: Context) {
         .layer(layer_ctx);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, router).await.unwrap();
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use bancho_packets::{
     server::{
@@ -8,8 +8,6 @@ use bancho_packets::{
     BanchoMessage, BanchoPacket, ClientChangeAction,
 };
 use chrono::{DateTime, Utc};
-use redis::Client;
-use sqlx::{Pool, Postgres};
 use tokio::sync::{Mutex, RwLock};
 use tracing::{error, info};
 use uuid::Uuid;
@@ -17,14 +15,15 @@ use uuid::Uuid;
 use crate::{
     context::Context,
     db::user::User,
-    utils::{
-        http_utils::OsuMode,
-        user_utils::{get_rank, get_user_stats},
-        UserDbStats,
-    },
+    utils::{http_utils::OsuMode, UserDbStats},
 };
 
-use super::{bancho_manager::BanchoManager, channel_manager::ChannelManager, client::ClientData};
+use super::{
+    bancho_manager::BanchoManager,
+    channel_manager::ChannelManager,
+    client::ClientData,
+    recorder::{CaptureDirection, CaptureHeader, PacketRecorder},
+};
 
 #[derive(Debug, Clone)]
 pub struct Presence {
@@ -45,6 +44,7 @@ pub struct Presence {
     pub spectating: Arc<Mutex<Option<Presence>>>,
     pub spectators: Arc<Mutex<Vec<i32>>>,
     pub last_ping: Arc<Mutex<DateTime<Utc>>>,
+    recorder: Arc<RwLock<Option<Arc<PacketRecorder>>>>,
 }
 
 impl Presence {
@@ -73,6 +73,7 @@ impl Presence {
             spectating: Arc::new(Mutex::new(None)),
             spectators: Arc::new(Mutex::new(Vec::new())),
             last_ping: Arc::new(Mutex::new(Utc::now())),
+            recorder: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -173,10 +174,13 @@ impl Presence {
         }
     }
 
-    pub async fn refresh_stats(&self, connection: &Pool<Postgres>, redis: &Client) {
+    pub async fn refresh_stats(&self, ctx: &Context) {
         let status = self.status.read().await;
 
-        let stats = get_user_stats(connection, &self.user.id, &self.get_active_mode().await).await;
+        let stats = ctx
+            .users
+            .get_stats(self.user.id, &self.get_active_mode().await)
+            .await;
         if let Err(err) = stats {
             error!("Failed to fetch user stats: {:#?}", err);
             self.enqueue(
@@ -208,8 +212,10 @@ impl Presence {
                 (stats.accuracy * 100.0) as f32,
                 stats.playcount,
                 stats.total_score,
-                get_rank(redis, &self.user, &self.get_active_mode().await)
+                ctx.rankings
+                    .get_rank(&self.user, &self.get_active_mode().await)
                     .await
+                    .unwrap_or_default()
                     .unwrap_or(0),
                 stats.performance as i16,
             )
@@ -233,7 +239,7 @@ impl Presence {
         OsuMode::from_id(status.mode)
     }
 
-    pub async fn stats_packet(&self, redis: &Client) -> Vec<u8> {
+    pub async fn stats_packet(&self, ctx: &Context) -> Vec<u8> {
         let status = self.status.read().await;
         let stats = self.cached_stats.read().await;
 
@@ -249,8 +255,10 @@ impl Presence {
             (stats.accuracy * 100.0) as f32,
             stats.playcount,
             stats.total_score,
-            get_rank(redis, &self.user, &self.get_active_mode().await)
+            ctx.rankings
+                .get_rank(&self.user, &self.get_active_mode().await)
                 .await
+                .unwrap_or_default()
                 .unwrap_or(0),
             stats.performance as i16,
         )
@@ -288,6 +296,64 @@ impl Presence {
     }
 }
 
+impl Presence {
+    pub async fn start_recording(&self, directory: &str) -> bool {
+        let mut recorder = self.recorder.write().await;
+
+        if recorder.is_some() {
+            return true;
+        }
+
+        let created = PacketRecorder::create(
+            Path::new(directory),
+            CaptureHeader {
+                user_id: self.user.id,
+                username: self.user.username.clone(),
+                client_version: self.client_data.client_version.clone(),
+                time_offset: self.client_data.time_offset,
+                started_at: Utc::now().timestamp(),
+            },
+        )
+        .await;
+
+        match created {
+            Ok(created) => {
+                *recorder = Some(Arc::new(created));
+                true
+            }
+            Err(error) => {
+                error!(
+                    "Failed to start packet capture for {}: {}",
+                    self.user.username, error
+                );
+                false
+            }
+        }
+    }
+
+    pub async fn stop_recording(&self) {
+        if let Some(recorder) = self.recorder.write().await.take() {
+            info!(
+                "Stopped packet capture for {} ({})",
+                self.user.username,
+                recorder.path.display()
+            );
+        }
+    }
+
+    pub async fn is_recording(&self) -> bool {
+        self.recorder.read().await.is_some()
+    }
+
+    pub async fn record(&self, direction: CaptureDirection, data: &[u8]) {
+        let recorder = self.recorder.read().await.clone();
+
+        if let Some(recorder) = recorder {
+            recorder.record(direction, data).await;
+        }
+    }
+}
+
 impl Presence {
     pub async fn enqueue(&self, packet: Vec<u8>) {
         let mut queue = self.packet_queue.lock().await;
@@ -0,0 +1,128 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureDirection {
+    Inbound,
+    Outbound,
+}
+
+//First line of every capture, everything required to log in the same user again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub user_id: i32,
+    pub username: String,
+    pub client_version: String,
+    pub time_offset: i8,
+    pub started_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureEntry {
+    pub direction: CaptureDirection,
+    //milliseconds since unix epoch
+    pub timestamp: i64,
+    //base64 encoded request body or dequeued buffer
+    pub data: String,
+}
+
+#[derive(Debug)]
+pub struct PacketRecorder {
+    pub path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl PacketRecorder {
+    pub async fn create(directory: &Path, header: CaptureHeader) -> std::io::Result<Self> {
+        if fs::metadata(directory).await.is_err() {
+            fs::create_dir_all(directory).await?;
+        }
+
+        let path = directory.join(format!("{}-{}.jsonl", header.user_id, header.started_at));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        let header = serde_json::to_string(&header).unwrap_or_default();
+        file.write_all(format!("{}\n", header).as_bytes()).await?;
+        file.flush().await?;
+
+        info!("Started packet capture in {}", path.display());
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub async fn record(&self, direction: CaptureDirection, data: &[u8]) {
+        let entry = CaptureEntry {
+            direction,
+            timestamp: Utc::now().timestamp_millis(),
+            data: STANDARD.encode(data),
+        };
+
+        let line = serde_json::to_string(&entry).unwrap_or_default();
+        let mut file = self.file.lock().await;
+
+        let written = match file.write_all(format!("{}\n", line).as_bytes()).await {
+            Ok(()) => file.flush().await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = written {
+            error!(
+                "Failed to write capture entry to {}: {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+}
+
+//Captures hold raw traffic including private messages, so they are only kept for a while
+pub async fn purge_expired_captures(
+    directory: &Path,
+    retention_days: u64,
+) -> std::io::Result<usize> {
+    let mut entries = match fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+
+    let cutoff = SystemTime::now() - Duration::from_secs(retention_days * 24 * 60 * 60);
+    let mut purged = 0;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some("jsonl") {
+            continue;
+        }
+
+        let modified = entry.metadata().await?.modified()?;
+
+        if modified < cutoff {
+            fs::remove_file(&path).await?;
+            purged += 1;
+        }
+    }
+
+    Ok(purged)
+}
@@ -0,0 +1,278 @@
+//Replays captures written by PacketRecorder against handler::serve() and compares
+//every response with the recorded one byte for byte. Sessions are recorded by the real
+//recorder first and run against in-memory storage, no database or redis needed.
+
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::Request,
+    Extension, Router,
+};
+use bancho_packets::{
+    client::{
+        Ping, SendPublicMessage, UserChannelJoin, UserChannelPart, UserLogout,
+        UserRequestStatusUpdate, UserStatsRequest,
+    },
+    PacketBuilder, PacketReader,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::sync::{Mutex, RwLock};
+use tower::{ServiceBuilder, ServiceExt};
+use uuid::Uuid;
+
+use crate::{
+    config::RunConfiguration,
+    repositories::memory::MemoryStorage,
+    testing::{test_context, test_user},
+};
+
+use super::{
+    bancho_manager::BanchoManager,
+    bot::mio::MioBot,
+    channel_manager::{Channel, ChannelManager},
+    client::ClientData,
+    recorder::{CaptureDirection, CaptureEntry, CaptureHeader},
+};
+
+struct ReplayStep {
+    step: usize,
+    expected: Vec<u8>,
+    actual: Vec<u8>,
+}
+
+fn packet_ids(data: &[u8]) -> Vec<String> {
+    PacketReader::new(data)
+        .map(|packet| packet.id.to_string())
+        .collect()
+}
+
+fn decode(entry: &CaptureEntry) -> Vec<u8> {
+    STANDARD.decode(&entry.data).expect("invalid capture data")
+}
+
+fn parse_capture(contents: &str) -> (CaptureHeader, Vec<CaptureEntry>) {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+
+    let header = serde_json::from_str(lines.next().expect("capture is empty"))
+        .expect("invalid capture header");
+    let entries = lines
+        .map(|line| serde_json::from_str(line).expect("invalid capture entry"))
+        .collect();
+
+    (header, entries)
+}
+
+//Same channels every login joins, only #osu is listed so channel info order is stable
+async fn seed_channels(channel_manager: &ChannelManager) {
+    let mut channels = channel_manager.channels.write().await;
+
+    for (id, name, channel_type) in [(1, "#osu", "public"), (2, "#announce", "announce")] {
+        channels.insert(
+            id,
+            Arc::new(Channel {
+                id,
+                channel_type: channel_type.to_string(),
+                name: name.to_string(),
+                description: String::new(),
+                users: Mutex::new(Vec::new()),
+                messages: RwLock::new(Vec::new()),
+            }),
+        );
+    }
+}
+
+struct Session {
+    router: Router,
+    token: String,
+}
+
+//Logs in the captured user with fresh managers behind the same handler bancho uses,
+//recording the session into capture_directory when one is given
+async fn start_session(header: &CaptureHeader, capture_directory: Option<&Path>) -> Session {
+    let storage = Arc::new(MemoryStorage::new());
+    storage.add_user(test_user(1, "Mio")).await;
+    storage
+        .add_user(test_user(header.user_id, &header.username))
+        .await;
+
+    let mut ctx = test_context(storage).await;
+
+    if let Some(directory) = capture_directory {
+        ctx.config = Arc::new(RunConfiguration {
+            capture_directory: directory.display().to_string(),
+            ..(*ctx.config).clone()
+        });
+    }
+
+    let ctx = Arc::new(ctx);
+
+    let manager = Arc::new(BanchoManager::init(ctx.clone()));
+    assert!(manager.init_bot().await, "failed to init bot");
+
+    if capture_directory.is_some() {
+        assert!(manager.set_capture(header.user_id, true).await);
+    }
+
+    let channel_manager = Arc::new(ChannelManager::new(manager.clone(), ctx.clone()));
+    seed_channels(&channel_manager).await;
+
+    let bot_presence = manager.get_bot_presence().await.expect("no bot presence");
+    let mut bot = MioBot::new(
+        ctx.clone(),
+        bot_presence,
+        manager.clone(),
+        channel_manager.clone(),
+    );
+    bot.register_commands();
+
+    let token = manager
+        .init_user(
+            header.user_id,
+            ClientData {
+                client_version: header.client_version.clone(),
+                time_offset: header.time_offset,
+                hwid: Default::default(),
+            },
+            0,
+            0.0,
+            0.0,
+            &channel_manager,
+        )
+        .await
+        .expect("failed to log in captured user");
+
+    let router: Router = super::handler::serve().layer(
+        ServiceBuilder::new()
+            .layer(Extension(ctx))
+            .layer(Extension(manager))
+            .layer(Extension(channel_manager))
+            .layer(Extension(Arc::new(Mutex::new(bot)))),
+    );
+
+    Session { router, token }
+}
+
+async fn send(session: &Session, body: Vec<u8>) -> Vec<u8> {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/")
+        .header("host", "c.replay")
+        .header("osu-token", session.token.as_str())
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = session.router.clone().oneshot(request).await.unwrap();
+
+    to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+        .to_vec()
+}
+
+//Plays the requests as a recorded client and returns the capture PacketRecorder wrote
+async fn record_session(
+    directory: &Path,
+    header: &CaptureHeader,
+    requests: Vec<Vec<u8>>,
+) -> String {
+    let session = start_session(header, Some(directory)).await;
+
+    for body in requests {
+        send(&session, body).await;
+    }
+
+    let mut captures = std::fs::read_dir(directory)
+        .expect("no capture directory")
+        .map(|entry| entry.unwrap().path());
+    let capture = captures.next().expect("no capture recorded");
+    assert!(captures.next().is_none(), "more than one capture recorded");
+
+    std::fs::read_to_string(capture).expect("failed to read capture")
+}
+
+//Feeds every inbound body to a fresh session, pairing it with the outbound buffer
+//recorded right after
+async fn replay_capture(header: &CaptureHeader, entries: &[CaptureEntry]) -> Vec<ReplayStep> {
+    let session = start_session(header, None).await;
+
+    let mut steps = Vec::new();
+    let mut entries = entries.iter().peekable();
+
+    while let Some(entry) = entries.next() {
+        if entry.direction != CaptureDirection::Inbound {
+            continue;
+        }
+
+        let expected = match entries.peek() {
+            Some(next) if next.direction == CaptureDirection::Outbound => {
+                decode(entries.next().unwrap())
+            }
+            _ => Vec::new(),
+        };
+
+        let actual = send(&session, decode(entry)).await;
+
+        steps.push(ReplayStep {
+            step: steps.len(),
+            expected,
+            actual,
+        });
+    }
+
+    steps
+}
+
+async fn assert_replay(capture: &str) {
+    let (header, entries) = parse_capture(capture);
+    let steps = replay_capture(&header, &entries).await;
+
+    assert!(!steps.is_empty(), "capture has no inbound entries");
+
+    for step in steps {
+        assert!(
+            step.expected == step.actual,
+            "step #{}: expected packets {:?}, got {:?}",
+            step.step,
+            packet_ids(&step.expected),
+            packet_ids(&step.actual)
+        );
+    }
+}
+
+#[tokio::test]
+async fn replays_recorded_chat_session() {
+    let directory = std::env::temp_dir().join(format!("dub-replay-{}", Uuid::new_v4()));
+    let header = CaptureHeader {
+        user_id: 2,
+        username: "Replay Player".to_string(),
+        client_version: "b20240123".to_string(),
+        time_offset: 0,
+        started_at: 0,
+    };
+
+    let requests = vec![
+        PacketBuilder::new()
+            .add(UserRequestStatusUpdate::new())
+            .add(Ping::new())
+            .build(),
+        UserStatsRequest::pack(&[1]),
+        PacketBuilder::new()
+            .add(UserChannelPart::new("#announce".into()))
+            .add(UserChannelJoin::new("#announce".into()))
+            .build(),
+        SendPublicMessage::pack("".into(), "hello".into(), "#osu".into(), 0),
+        UserChannelPart::pack("#announce".into()),
+        Ping::pack(),
+        UserLogout::pack(0),
+    ];
+
+    let capture = record_session(&directory, &header, requests).await;
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let (recorded, entries) = parse_capture(&capture);
+    assert_eq!(recorded.username, header.username);
+    assert_eq!(entries.len(), 14, "every request and response is recorded");
+
+    assert_replay(&capture).await;
+}
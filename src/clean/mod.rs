@@ -1,15 +1,77 @@
-use tracing::info;
+use std::{path::Path, time::Duration};
+
+use tracing::{error, info};
 
 use crate::{
+    bancho::recorder::purge_expired_captures,
     context::Context,
-    utils::user_utils::{get_inactive_users, get_restricted_users, remove_ranking},
+    utils::{
+        badge_utils::sync_donor_badges,
+        payment_utils::expire_donors,
+        user_utils::{get_inactive_users, get_restricted_users, purge_legacy_password_cache},
+    },
 };
 
+use self::punishments::lift_expired_punishments;
+
+mod punishments;
+
+pub async fn run_worker(ctx: Context) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ctx.config.worker_interval_secs));
+
+    info!(
+        "worker started, running every {}s",
+        ctx.config.worker_interval_secs
+    );
+
+    loop {
+        interval.tick().await;
+        lift_expired_punishments(&ctx).await;
+
+        if let Err(why) = sync_donor_badges(&ctx).await {
+            error!("Failed to sync donor badges: {:?}", why);
+        }
+
+        if let Err(why) = expire_donors(&ctx).await {
+            error!("Failed to expire donors: {:?}", why);
+        }
+
+        purge_captures(&ctx).await;
+    }
+}
+
+async fn purge_captures(ctx: &Context) {
+    let directory = Path::new(&ctx.config.capture_directory);
+
+    match purge_expired_captures(directory, ctx.config.capture_retention_days).await {
+        Ok(0) => {}
+        Ok(count) => info!("purged {} expired packet captures", count),
+        Err(why) => error!("Failed to purge expired packet captures: {:?}", why),
+    }
+}
+
 pub async fn run_cleanup(ctx: Context) {
+    lift_expired_punishments(&ctx).await;
+
+    if let Err(why) = sync_donor_badges(&ctx).await {
+        error!("Failed to sync donor badges: {:?}", why);
+    }
+
+    if let Err(why) = expire_donors(&ctx).await {
+        error!("Failed to expire donors: {:?}", why);
+    }
+
+    purge_captures(&ctx).await;
+
+    match purge_legacy_password_cache(&ctx.redis).await {
+        Ok(count) => info!("purged {} legacy password cache entries", count),
+        Err(why) => error!("Failed to purge legacy password cache: {:?}", why),
+    }
+
     let restricted_users = get_restricted_users(&ctx.pool).await;
 
     if let Err(why) = restricted_users {
-        println!("Error: {:?}", why);
+        error!("Failed to fetch restricted users: {:?}", why);
         return;
     }
 
@@ -17,19 +79,30 @@ pub async fn run_cleanup(ctx: Context) {
 
     info!("found {} restricted users", users.len());
     for user in users {
-        remove_ranking(&ctx.redis, &user).await;
+        if let Err(why) = ctx.rankings.remove(&user).await {
+            error!(
+                "Failed to remove restricted user {} ({}) from rankings: {:?}",
+                user.username, user.id, why
+            );
+        }
     }
 
     let users = get_inactive_users(&ctx.pool).await;
     if let Err(why) = users {
-        println!("Error: {:?}", why);
+        error!("Failed to fetch inactive users: {:?}", why);
         return;
     }
 
     let users = users.unwrap();
 
     for user in users {
-        remove_ranking(&ctx.redis, &user).await;
+        if let Err(why) = ctx.rankings.remove(&user).await {
+            error!(
+                "Failed to remove inactive user {} ({}) from rankings: {:?}",
+                user.username, user.id, why
+            );
+            continue;
+        }
         info!("flagged user {} as active", user.username);
     }
 }
@@ -0,0 +1,193 @@
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::{
+    context::Context,
+    utils::{
+        audit_utils::{insert_audit_log, AuditRecord},
+        http_utils::OsuMode,
+        notification_utils::{notify, NewNotification, NotificationKind},
+        user_utils::{is_restricted, send_bancho_message, RESTRICTED_PERMISSION},
+        Punishment,
+    },
+};
+
+pub async fn lift_expired_punishments(ctx: &Context) {
+    let punishments = ctx.punishments.get_expired().await;
+
+    if let Err(why) = punishments {
+        error!("Failed to fetch expired punishments: {:?}", why);
+        return;
+    }
+
+    let punishments = punishments.unwrap();
+
+    if !punishments.is_empty() {
+        info!("found {} expired punishments", punishments.len());
+    }
+
+    //Punishments that failed to lift stay expired and are retried on the next run
+    for punishment in punishments {
+        if lift_punishment(ctx, &punishment).await {
+            ctx.punishments.mark_lifted(&punishment.id).await;
+        }
+    }
+}
+
+async fn lift_punishment(ctx: &Context, punishment: &Punishment) -> bool {
+    let user = ctx.users.get_by_id(punishment.applied_to).await;
+
+    if let Err(why) = user {
+        error!("Failed to fetch user {}: {:?}", punishment.applied_to, why);
+        return false;
+    }
+
+    let user = user.unwrap();
+
+    //Nothing left to lift for deleted users
+    if user.is_none() {
+        return true;
+    }
+
+    let user = user.unwrap();
+
+    //Another punishment of the same kind still holds, only this one is marked as lifted
+    let still_active = ctx
+        .punishments
+        .has_active(user.id, &punishment.punishment_type)
+        .await;
+
+    if let Err(why) = still_active {
+        error!(
+            "Failed to check active punishments of {}: {:?}",
+            user.username, why
+        );
+        return false;
+    }
+
+    let still_active = still_active.unwrap();
+
+    let mut after = user.permissions;
+
+    if !still_active {
+        match punishment.punishment_type.as_str() {
+            "RESTRICTION" if is_restricted(&user).await => {
+                ctx.users.unrestrict(user.id).await;
+                after = user.permissions & !RESTRICTED_PERMISSION;
+
+                //Rankings were removed on restriction, rebuilding them from scores
+                if let Ok(Some(unrestricted)) = ctx.users.get_by_id(user.id).await {
+                    for mode in [
+                        OsuMode::Osu,
+                        OsuMode::Taiko,
+                        OsuMode::Fruits,
+                        OsuMode::Mania,
+                        OsuMode::Relax,
+                    ] {
+                        if let Err(why) = ctx.rankings.recalculate(&unrestricted, &mode).await {
+                            error!("Failed to restore ranking of {}: {:?}", user.username, why);
+                        }
+                    }
+                }
+
+                send_bancho_message(&user.id, "user:restricted".to_string(), None).await;
+
+                notify(
+                    &ctx.pool,
+                    NewNotification {
+                        user_id: user.id,
+                        kind: NotificationKind::PunishmentLifted,
+                        subject: punishment.id.clone(),
+                        text: "Your account restriction has expired.".to_string(),
+                        avatar_of: None,
+                    },
+                )
+                .await;
+            }
+            "TIMEOUT" => {
+                notify(
+                    &ctx.pool,
+                    NewNotification {
+                        user_id: user.id,
+                        kind: NotificationKind::PunishmentLifted,
+                        subject: punishment.id.clone(),
+                        text: "Your chat silence has expired.".to_string(),
+                        avatar_of: None,
+                    },
+                )
+                .await;
+            }
+            _ => {}
+        }
+    }
+
+    info!(
+        "lifted {} {} of {}",
+        punishment.punishment_type, punishment.id, user.username
+    );
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: None,
+            action: "punishment.expired",
+            target_id: Some(user.id),
+            before: Some(json!({ "permissions": user.permissions })),
+            after: Some(json!({ "permissions": after, "stillPunished": still_active })),
+            note: &punishment.id,
+        },
+    )
+    .await;
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use crate::{
+        repositories::{
+            memory::MemoryStorage, postgres::PostgresUserRepository, PunishmentRepository,
+        },
+        testing::{test_context, test_user},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn punishment_stays_expired_until_lifted() {
+        let storage = Arc::new(MemoryStorage::new());
+        let mut ctx = test_context(storage.clone()).await;
+
+        PunishmentRepository::insert(
+            storage.as_ref(),
+            "LOW".to_string(),
+            1,
+            2,
+            "TIMEOUT".to_string(),
+            true,
+            Some(Utc::now().naive_utc() - chrono::Duration::hours(1)),
+            String::new(),
+        )
+        .await
+        .unwrap();
+
+        //Users can't be fetched, punishment is retried on the next run
+        let users = ctx.users.clone();
+        ctx.users = Arc::new(PostgresUserRepository::new(
+            ctx.pool.clone(),
+            ctx.redis.clone(),
+            String::new(),
+        ));
+        lift_expired_punishments(&ctx).await;
+        assert_eq!(storage.get_expired().await.unwrap().len(), 1);
+
+        ctx.users = users;
+        storage.add_user(test_user(2, "Silenced Player")).await;
+        lift_expired_punishments(&ctx).await;
+        assert!(storage.get_expired().await.unwrap().is_empty());
+    }
+}
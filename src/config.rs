@@ -8,6 +8,8 @@ pub struct RunConfiguration {
     pub database_dsn: String,
     #[arg(long, env)]
     pub redis_url: String,
+    #[arg(long, env, default_value_t = 3000)]
+    pub redis_timeout_ms: u64,
     #[arg(long, env)]
     pub alert_discord_webhook: Option<String>,
     #[arg(long, env)]
@@ -18,4 +20,56 @@ pub struct RunConfiguration {
     pub port: Option<i16>,
     #[arg(long, env)]
     pub listing_key: Option<String>,
+    #[arg(long, env)]
+    pub geoip_database_path: Option<String>,
+    #[arg(long, env, default_value = "mmdb,ip-api")]
+    pub geoip_resolvers: String,
+    #[arg(long, env, default_value_t = 1500)]
+    pub geoip_timeout_ms: u64,
+    #[arg(
+        long,
+        env,
+        default_value = "127.0.0.1/32,::1/128,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16"
+    )]
+    pub trusted_proxies: String,
+    #[arg(long, env)]
+    pub client_ip_header: Option<String>,
+    #[arg(long, env, default_value_t = false)]
+    pub verification_require_code: bool,
+    #[arg(long, env, default_value_t = 60)]
+    pub worker_interval_secs: u64,
+    #[arg(long, env, default_value = "dummy")]
+    pub payment_providers: String,
+    #[arg(long, env)]
+    pub dummy_payment_secret: Option<String>,
+    #[arg(
+        long,
+        env,
+        default_value = "mio,peppy,bancho,banchobot,admin,moderator,system"
+    )]
+    pub reserved_usernames: String,
+    #[arg(long, env, default_value_t = 90)]
+    pub username_hold_days: i32,
+    #[arg(long, env)]
+    pub smtp_host: Option<String>,
+    #[arg(long, env)]
+    pub smtp_port: Option<u16>,
+    #[arg(long, env, default_value = "starttls")]
+    pub smtp_security: String,
+    #[arg(long, env)]
+    pub smtp_username: Option<String>,
+    #[arg(long, env)]
+    pub smtp_password: Option<String>,
+    #[arg(long, env)]
+    pub smtp_from: Option<String>,
+    #[arg(long, env, default_value_t = 30)]
+    pub password_reset_ttl_minutes: i32,
+    #[arg(long, env)]
+    pub webauthn_rp_id: Option<String>,
+    #[arg(long, env)]
+    pub webauthn_origin: Option<String>,
+    #[arg(long, env, default_value = "data/captures")]
+    pub capture_directory: String,
+    #[arg(long, env, default_value_t = 7)]
+    pub capture_retention_days: u64,
 }
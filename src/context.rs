@@ -1,12 +1,91 @@
 use std::sync::Arc;
 
-use redis::Client;
+use redis::aio::ConnectionManager;
 use sqlx::{Pool, Postgres};
+use webauthn_rs::Webauthn;
 
-use crate::config::RunConfiguration;
+#[cfg(test)]
+use crate::repositories::memory::MemoryStorage;
+use crate::{
+    config::RunConfiguration,
+    repositories::{
+        postgres::{
+            PostgresBeatmapRepository, PostgresGroupRepository, PostgresPasswordRepository,
+            PostgresPaymentRepository, PostgresPunishmentRepository, PostgresScoreRepository,
+            PostgresUserRepository, RedisRankingRepository,
+        },
+        BeatmapRepository, GroupRepository, PasswordRepository, PaymentRepository,
+        PunishmentRepository, RankingRepository, ScoreRepository, UserRepository,
+    },
+    utils::{
+        ip_utils::{GeoIpService, TrustedProxies},
+        mail_utils::{create_mailer, Mailer},
+        passkey_utils::create_webauthn,
+        payment_utils::PaymentService,
+    },
+};
 
 pub struct Context {
     pub pool: Arc<Pool<Postgres>>,
     pub config: Arc<RunConfiguration>,
-    pub redis: Arc<Client>,
+    pub redis: ConnectionManager,
+    pub users: Arc<dyn UserRepository>,
+    pub scores: Arc<dyn ScoreRepository>,
+    pub beatmaps: Arc<dyn BeatmapRepository>,
+    pub punishments: Arc<dyn PunishmentRepository>,
+    pub rankings: Arc<dyn RankingRepository>,
+    pub groups: Arc<dyn GroupRepository>,
+    pub payment_entries: Arc<dyn PaymentRepository>,
+    pub passwords: Arc<dyn PasswordRepository>,
+    pub geoip: Arc<GeoIpService>,
+    pub proxies: Arc<TrustedProxies>,
+    pub payments: Arc<PaymentService>,
+    pub mailer: Arc<dyn Mailer>,
+    pub webauthn: Arc<Webauthn>,
+}
+
+impl Context {
+    pub fn new(pool: Pool<Postgres>, config: RunConfiguration, redis: ConnectionManager) -> Self {
+        let pool = Arc::new(pool);
+
+        Self {
+            users: Arc::new(PostgresUserRepository::new(
+                pool.clone(),
+                redis.clone(),
+                config.token_hmac_secret.clone(),
+            )),
+            scores: Arc::new(PostgresScoreRepository::new(pool.clone())),
+            beatmaps: Arc::new(PostgresBeatmapRepository::new(pool.clone())),
+            punishments: Arc::new(PostgresPunishmentRepository::new(pool.clone())),
+            rankings: Arc::new(RedisRankingRepository::new(pool.clone(), redis.clone())),
+            groups: Arc::new(PostgresGroupRepository::new(pool.clone())),
+            payment_entries: Arc::new(PostgresPaymentRepository::new(pool.clone())),
+            passwords: Arc::new(PostgresPasswordRepository::new(pool.clone())),
+            geoip: Arc::new(GeoIpService::new(&config, redis.clone())),
+            proxies: Arc::new(TrustedProxies::from_config(&config)),
+            payments: Arc::new(PaymentService::new(&config)),
+            mailer: create_mailer(&config),
+            webauthn: create_webauthn(&config),
+            pool,
+            config: Arc::new(config),
+            redis,
+        }
+    }
+
+    //Swaps every repository for the same in-memory storage. Pool and redis are kept as they are
+    //for code paths that weren't moved to repositories yet, so never use it with live ones
+    #[cfg(test)]
+    pub fn with_storage(self, storage: Arc<MemoryStorage>) -> Self {
+        Self {
+            users: storage.clone(),
+            scores: storage.clone(),
+            beatmaps: storage.clone(),
+            punishments: storage.clone(),
+            rankings: storage.clone(),
+            groups: storage.clone(),
+            payment_entries: storage.clone(),
+            passwords: storage,
+            ..self
+        }
+    }
 }
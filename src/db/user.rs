@@ -25,4 +25,7 @@ pub struct User {
     pub userpage_content: String,
     #[sqlx(rename = "coins")]
     pub coins: i32,
+    //Tokens carry it, bumping it revokes every issued token
+    #[sqlx(rename = "tokenGeneration")]
+    pub token_generation: i32,
 }
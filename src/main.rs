@@ -1,17 +1,18 @@
 use std::env;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use config::RunConfiguration;
 
+use redis::aio::ConnectionManager;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::ConnectOptions;
 use tracing::{error, info, Level};
 
 use crate::api::serve_api;
 use crate::bancho::serve_bancho;
-use crate::clean::run_cleanup;
+use crate::clean::{run_cleanup, run_worker};
 use crate::context::Context;
 use crate::recalculate::recalculate_terminal;
 use crate::web::serve as serve_web;
@@ -23,6 +24,9 @@ mod config;
 mod context;
 mod db;
 mod recalculate;
+mod repositories;
+#[cfg(test)]
+mod testing;
 mod utils;
 mod web;
 
@@ -49,10 +53,7 @@ async fn main() {
     let connection_options = PgConnectOptions::from_str(&run_configuration.database_dsn)
         .unwrap()
         .log_statements(tracing::log::LevelFilter::Debug)
-        .log_slow_statements(
-            tracing::log::LevelFilter::Warn,
-            std::time::Duration::from_secs(1),
-        );
+        .log_slow_statements(tracing::log::LevelFilter::Warn, Duration::from_secs(1));
     let pool = PgPoolOptions::new().connect_with(connection_options).await;
 
     if let Err(error) = pool {
@@ -71,11 +72,27 @@ async fn main() {
     }
 
     let redis = redis.unwrap();
-    let context = Context {
-        pool: Arc::new(pool),
-        config: Arc::new(run_configuration.clone()),
-        redis: Arc::new(redis),
-    };
+    let redis_timeout = Duration::from_millis(run_configuration.redis_timeout_ms);
+    //Single multiplexed connection shared by every handler, reconnects on its own
+    let redis = ConnectionManager::new_with_backoff_and_timeouts(
+        redis,
+        2,
+        100,
+        6,
+        redis_timeout,
+        redis_timeout,
+    )
+    .await;
+
+    if let Err(error) = redis {
+        error!("Error while connecting to redis: {}", error);
+        return;
+    }
+
+    let redis = redis.unwrap();
+    info!(name: "redis", "Connected to redis!");
+
+    let context = Context::new(pool, run_configuration.clone(), redis);
 
     match run_configuration.app_component.as_str() {
         "web" => serve_web(context).await,
@@ -83,6 +100,7 @@ async fn main() {
         "api" => serve_api(context).await,
         "recalculation-terminal" => recalculate_terminal(context).await,
         "cleanup" => run_cleanup(context).await,
+        "worker" => run_worker(context).await,
         _ => {
             error!("Unknown component.");
         }
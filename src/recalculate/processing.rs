@@ -6,7 +6,6 @@ use crate::{
         http_utils::OsuMode,
         performance_utils::calculate_performance_safe,
         score_utils::{get_user_best_scores, SortMode},
-        user_utils::recalculate_user_stats,
     },
 };
 
@@ -101,7 +100,14 @@ pub async fn process_command(
             OsuMode::Mania,
             OsuMode::Relax,
         ] {
-            recalculate_user_stats(&ctx.pool, &ctx.redis, &user, &mode).await;
+            if let Err(error) = ctx.rankings.recalculate(&user, &mode).await {
+                error!(
+                    "Failed to recalculate stats of {} in mode {}: {:#?}",
+                    user.username,
+                    mode.to_osu(),
+                    error
+                );
+            }
         }
     }
 }
@@ -0,0 +1,1105 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ops::BitAnd,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use async_trait::async_trait;
+use bcrypt::verify;
+use chrono::{NaiveDateTime, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    bancho::client::HWID,
+    db::user::User,
+    utils::{
+        beatmap_utils::Beatmap,
+        general_utils::to_fixed,
+        group_utils::Group,
+        http_utils::OsuMode,
+        payment_utils::{CompletedPayment, PaymentEntry, DAYS_PER_MONTH},
+        score_utils::{OsuServerError, Score, UserScore, UserScoreWithBeatmap},
+        user_utils::{is_restricted, to_safe, PENDING_VERIFICATION_FLAG, RESTRICTED_PERMISSION},
+        DatabaseHwid, Punishment, UserDbStats, UserHwid,
+    },
+    web::scores::submission::{PlayerScore, ScoreStatus},
+};
+
+use super::{
+    BeatmapRepository, GroupRepository, PasswordRepository, PaymentRepository,
+    PunishmentRepository, RankingRepository, ScoreRepository, UserRepository,
+};
+
+struct StoredScore {
+    user_id: i32,
+    score: Score,
+}
+
+struct StoredReset {
+    user_id: i32,
+    token_hash: String,
+    created_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+    used: bool,
+}
+
+impl StoredReset {
+    fn is_valid(&self, now: NaiveDateTime) -> bool {
+        !self.used && self.expires_at > now
+    }
+}
+
+//Every repository backed by plain maps, nothing survives a restart
+#[derive(Default)]
+pub struct MemoryStorage {
+    users: RwLock<HashMap<i32, User>>,
+    stats: RwLock<HashMap<(i32, i32), UserDbStats>>,
+    hwids: RwLock<HashMap<i32, DatabaseHwid>>,
+    scores: RwLock<Vec<StoredScore>>,
+    beatmaps: RwLock<HashMap<String, Beatmap>>,
+    punishments: RwLock<Vec<Punishment>>,
+    lifted_punishments: RwLock<HashSet<String>>,
+    //user id -> groups
+    groups: RwLock<HashMap<i32, Vec<Group>>>,
+    //mode -> user id -> performance
+    rankings: RwLock<HashMap<i32, HashMap<i32, i64>>>,
+    payments: RwLock<Vec<PaymentEntry>>,
+    password_resets: RwLock<Vec<StoredReset>>,
+    next_score_id: AtomicI32,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self {
+            next_score_id: AtomicI32::new(1),
+            ..Default::default()
+        }
+    }
+
+    pub async fn add_user_group(&self, user_id: i32, group: Group) {
+        self.groups
+            .write()
+            .await
+            .entry(user_id)
+            .or_default()
+            .push(group);
+    }
+
+    pub async fn add_user(&self, user: User) {
+        self.users.write().await.insert(user.id, user);
+    }
+
+    pub async fn add_beatmap(&self, beatmap: Beatmap) {
+        self.beatmaps
+            .write()
+            .await
+            .insert(beatmap.checksum.clone(), beatmap);
+    }
+
+    async fn user_scores(
+        &self,
+        beatmap_checksum: &str,
+        playmode: i32,
+        status: i32,
+        order_by_performance: bool,
+    ) -> Vec<UserScore> {
+        let users = self.users.read().await;
+        let mut result: Vec<UserScore> = self
+            .scores
+            .read()
+            .await
+            .iter()
+            .filter(|stored| {
+                stored.score.beatmap_checksum == beatmap_checksum
+                    && stored.score.playmode == playmode
+                    && stored.score.status == status
+            })
+            .filter_map(|stored| {
+                let user = users.get(&stored.user_id)?;
+
+                if user.permissions & RESTRICTED_PERMISSION > 0 {
+                    return None;
+                }
+
+                Some(UserScore {
+                    score: stored.score.clone(),
+                    user: user.clone(),
+                })
+            })
+            .collect();
+
+        match order_by_performance {
+            true => result.sort_by(|a, b| b.score.performance.total_cmp(&a.score.performance)),
+            false => result.sort_by_key(|entry| Reverse(entry.score.total_score)),
+        }
+
+        for (index, entry) in result.iter_mut().enumerate() {
+            entry.score.rank = (index + 1) as i64;
+        }
+
+        result
+    }
+
+    async fn with_beatmap(&self, score: UserScore) -> Option<UserScoreWithBeatmap> {
+        let beatmap = self
+            .beatmaps
+            .read()
+            .await
+            .get(&score.score.beatmap_checksum)
+            .cloned()?;
+
+        Some(UserScoreWithBeatmap {
+            rank: score.score.rank as i32,
+            score: score.score,
+            user: score.user,
+            beatmap,
+        })
+    }
+
+    async fn update_stats(
+        &self,
+        user_id: i32,
+        mode: &OsuMode,
+        update: impl FnOnce(&mut UserDbStats),
+    ) {
+        let mut stats = self.stats.write().await;
+        let entry = stats.entry((user_id, mode.to_osu())).or_default();
+        update(entry);
+    }
+
+    async fn set_permissions(&self, user_id: i32, permissions: i32, flags: Option<i32>) {
+        if let Some(user) = self.users.write().await.get_mut(&user_id) {
+            user.permissions = permissions;
+
+            if let Some(flags) = flags {
+                user.flags = flags;
+            }
+        }
+    }
+}
+
+fn to_playmode(mode: &OsuMode, mods: i32) -> i32 {
+    if 128.bitand(mods).eq(&128) {
+        4
+    } else {
+        mode.to_osu()
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryStorage {
+    async fn validate_auth(&self, username: &str, password: &str) -> bool {
+        let user_id = self.get_id(username).await;
+
+        if user_id.is_none() {
+            return false;
+        }
+
+        match self.users.read().await.get(&user_id.unwrap()) {
+            Some(user) => verify(password, &user.password).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    async fn get_id(&self, username: &str) -> Option<i32> {
+        let safe = to_safe(username);
+
+        self.users
+            .read()
+            .await
+            .values()
+            .find(|user| user.username_safe == safe)
+            .map(|user| user.id)
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<User>, OsuServerError> {
+        Ok(self.users.read().await.get(&id).cloned())
+    }
+
+    async fn find_by_id_or_username(&self, term: String) -> Result<Option<User>, OsuServerError> {
+        let id = term.parse::<i32>().unwrap_or(-1);
+        let safe = to_safe(&term);
+
+        Ok(self
+            .users
+            .read()
+            .await
+            .values()
+            .find(|user| user.id == id || user.username_safe == safe)
+            .cloned())
+    }
+
+    async fn get_stats(&self, user_id: i32, mode: &OsuMode) -> Result<UserDbStats, OsuServerError> {
+        let stats = self
+            .stats
+            .read()
+            .await
+            .get(&(user_id, mode.to_osu()))
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(UserDbStats {
+            id: Some(user_id),
+            ..stats
+        })
+    }
+
+    async fn find_hwids(&self, hwid: &HWID) -> Result<Vec<UserHwid>, OsuServerError> {
+        let users = self.users.read().await;
+
+        Ok(self
+            .hwids
+            .read()
+            .await
+            .values()
+            .filter(|entry| {
+                (!hwid.mac.is_empty() && entry.mac == hwid.mac)
+                    || (!hwid.uid.is_empty() && entry.unique_id == hwid.uid)
+                    || (!hwid.disk.is_empty() && entry.disk_id == hwid.disk)
+            })
+            .filter_map(|entry| {
+                Some(UserHwid {
+                    user: users.get(&entry.user_id)?.clone(),
+                    hwid: entry.clone(),
+                })
+            })
+            .collect())
+    }
+
+    async fn update_hwid(&self, user: &User, hwid: &HWID) {
+        let mut hwids = self.hwids.write().await;
+        let id = hwids.len() as i32 + 1;
+
+        let entry = hwids.entry(user.id).or_insert_with(|| DatabaseHwid {
+            id,
+            user_id: user.id,
+            mac: String::new(),
+            unique_id: String::new(),
+            disk_id: String::new(),
+        });
+
+        entry.mac = hwid.mac.clone();
+        entry.unique_id = hwid.uid.clone();
+        entry.disk_id = hwid.disk.clone();
+    }
+
+    async fn update_country(&self, user_id: i32, country: String) {
+        if let Some(user) = self.users.write().await.get_mut(&user_id) {
+            user.country = country;
+        }
+    }
+
+    async fn update_last_seen(&self, user_id: i32, last_seen: NaiveDateTime) {
+        if let Some(user) = self.users.write().await.get_mut(&user_id) {
+            user.last_seen = last_seen;
+        }
+    }
+
+    async fn clear_pending_verification(&self, user_id: i32) {
+        self.set_permissions(user_id, 0, Some(0)).await;
+    }
+
+    async fn restrict(&self, user_id: i32) {
+        let flags = self
+            .users
+            .read()
+            .await
+            .get(&user_id)
+            .map(|user| user.flags & !PENDING_VERIFICATION_FLAG);
+
+        self.set_permissions(user_id, RESTRICTED_PERMISSION, flags)
+            .await;
+    }
+
+    async fn unrestrict(&self, user_id: i32) {
+        let permissions = self
+            .users
+            .read()
+            .await
+            .get(&user_id)
+            .map(|user| user.permissions & !RESTRICTED_PERMISSION)
+            .unwrap_or(0);
+
+        self.set_permissions(user_id, permissions, None).await;
+    }
+
+    async fn increase_score(&self, user_id: i32, mode: &OsuMode, score: i64) {
+        self.update_stats(user_id, mode, |stats| {
+            stats.total_score += score;
+            stats.ranked_score += score;
+        })
+        .await;
+    }
+
+    async fn increase_playcount(&self, user_id: i32, mode: &OsuMode) {
+        self.update_stats(user_id, mode, |stats| stats.playcount += 1)
+            .await;
+    }
+
+    async fn update_max_combo(&self, user_id: i32, mode: &OsuMode, max_combo: i16) {
+        self.update_stats(user_id, mode, |stats| stats.max_combo = max_combo as i32)
+            .await;
+    }
+}
+
+#[async_trait]
+impl ScoreRepository for MemoryStorage {
+    async fn get_user_best(
+        &self,
+        beatmap_checksum: String,
+        user_id: i32,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Option<UserScore>, OsuServerError> {
+        Ok(self
+            .user_scores(
+                &beatmap_checksum,
+                to_playmode(&mode, mods),
+                status.unwrap_or(0),
+                false,
+            )
+            .await
+            .into_iter()
+            .find(|entry| entry.user.id == user_id))
+    }
+
+    async fn get_beatmap_leaderboard(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Vec<UserScore>, OsuServerError> {
+        Ok(self
+            .user_scores(
+                &beatmap_checksum,
+                to_playmode(&mode, mods),
+                status.unwrap_or(2),
+                mode.eq(&OsuMode::Relax),
+            )
+            .await)
+    }
+
+    async fn get_first_place(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+    ) -> Option<UserScoreWithBeatmap> {
+        let first = self
+            .user_scores(&beatmap_checksum, mode.to_osu(), 2, true)
+            .await
+            .into_iter()
+            .next()?;
+
+        self.with_beatmap(first).await
+    }
+
+    async fn get_by_id(
+        &self,
+        score_id: i32,
+        beatmap_checksum: String,
+        mode: &OsuMode,
+        status: Option<i32>,
+    ) -> Result<Option<UserScoreWithBeatmap>, OsuServerError> {
+        let score = self
+            .user_scores(&beatmap_checksum, mode.to_osu(), status.unwrap_or(2), false)
+            .await
+            .into_iter()
+            .find(|entry| entry.score.id == score_id);
+
+        match score {
+            Some(score) => Ok(self.with_beatmap(score).await),
+            None => Ok(None),
+        }
+    }
+
+    async fn insert(&self, score: &PlayerScore, user_id: i32) -> Option<i32> {
+        let id = self.next_score_id.fetch_add(1, Ordering::SeqCst);
+
+        self.scores.write().await.push(StoredScore {
+            user_id,
+            score: Score {
+                id,
+                max_combo: score.max_combo,
+                count_50: score.count_50,
+                count_100: score.count_100,
+                count_300: score.count_300,
+                count_miss: score.count_miss,
+                count_geki: score.count_geki,
+                total_score: score.total_score,
+                count_katu: score.count_katu,
+                is_perfect: score.perfect,
+                status: score.status.to_db(),
+                submitted_at: score.submitted_at,
+                playmode: to_playmode(&OsuMode::from_id(score.playmode), score.mods),
+                performance: score.performance.unwrap_or(0.0),
+                mods: score.mods,
+                rank: 0,
+                beatmap_checksum: score.beatmap_md5.clone(),
+            },
+        });
+
+        Some(id)
+    }
+
+    async fn update_status(&self, score_id: i32, status: ScoreStatus) {
+        if let Some(stored) = self
+            .scores
+            .write()
+            .await
+            .iter_mut()
+            .find(|stored| stored.score.id == score_id)
+        {
+            stored.score.status = status.to_db();
+        }
+    }
+}
+
+#[async_trait]
+impl BeatmapRepository for MemoryStorage {
+    async fn get_by_hash(&self, checksum: String) -> Result<Option<Beatmap>, OsuServerError> {
+        Ok(self.beatmaps.read().await.get(&checksum).cloned())
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<Beatmap>, OsuServerError> {
+        Ok(self
+            .beatmaps
+            .read()
+            .await
+            .values()
+            .find(|beatmap| beatmap.beatmap_id as i64 == id)
+            .cloned())
+    }
+
+    async fn insert(&self, beatmap: Beatmap) {
+        self.add_beatmap(beatmap).await;
+    }
+}
+
+#[async_trait]
+impl PunishmentRepository for MemoryStorage {
+    async fn insert(
+        &self,
+        level: String,
+        applied_by: i32,
+        applied_to: i32,
+        punishment_type: String,
+        expires: bool,
+        expires_at: Option<NaiveDateTime>,
+        note: String,
+    ) -> Option<Punishment> {
+        let punishment = Punishment {
+            id: Uuid::new_v4().to_string(),
+            date: Utc::now().naive_utc(),
+            applied_by,
+            applied_to,
+            punishment_type,
+            level,
+            expires,
+            expires_at: Some(expires_at.unwrap_or(NaiveDateTime::UNIX_EPOCH)),
+            note,
+        };
+
+        self.punishments.write().await.push(punishment.clone());
+
+        Some(punishment)
+    }
+
+    async fn get_by_id(&self, id: String) -> Option<Punishment> {
+        self.punishments
+            .read()
+            .await
+            .iter()
+            .find(|punishment| punishment.id == id)
+            .cloned()
+    }
+
+    async fn get_silenced_until(&self, user_id: i32) -> i64 {
+        self.punishments
+            .read()
+            .await
+            .iter()
+            .find(|punishment| {
+                punishment.applied_to == user_id && punishment.punishment_type == "TIMEOUT"
+            })
+            .map(|punishment| {
+                punishment
+                    .expires_at
+                    .unwrap_or(NaiveDateTime::UNIX_EPOCH)
+                    .timestamp()
+            })
+            .unwrap_or(0)
+    }
+
+    async fn get_recent_violations(&self, user: &User) -> Result<Vec<Punishment>, OsuServerError> {
+        let month_ago = Utc::now().naive_utc() - chrono::Duration::days(30);
+
+        Ok(self
+            .punishments
+            .read()
+            .await
+            .iter()
+            .filter(|punishment| {
+                punishment.applied_to == user.id
+                    && (punishment.date < month_ago || punishment.expires_at.is_none())
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_expired(&self) -> Result<Vec<Punishment>, OsuServerError> {
+        let now = Utc::now().naive_utc();
+        let lifted = self.lifted_punishments.read().await;
+
+        Ok(self
+            .punishments
+            .read()
+            .await
+            .iter()
+            .filter(|punishment| {
+                punishment.expires
+                    && punishment
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at <= now)
+                    && !lifted.contains(&punishment.id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn has_active(
+        &self,
+        user_id: i32,
+        punishment_type: &str,
+    ) -> Result<bool, OsuServerError> {
+        let now = Utc::now().naive_utc();
+        let lifted = self.lifted_punishments.read().await;
+
+        Ok(self.punishments.read().await.iter().any(|punishment| {
+            punishment.applied_to == user_id
+                && punishment.punishment_type == punishment_type
+                && !lifted.contains(&punishment.id)
+                && (!punishment.expires
+                    || punishment
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at > now))
+        }))
+    }
+
+    async fn mark_lifted(&self, id: &str) {
+        self.lifted_punishments.write().await.insert(id.to_string());
+    }
+}
+
+impl MemoryStorage {
+    async fn ranked_user_ids(&self, mode: &OsuMode, country: Option<&str>) -> Vec<i32> {
+        let rankings = self.rankings.read().await;
+        let users = self.users.read().await;
+
+        let mut entries: Vec<(i32, i64)> = rankings
+            .get(&mode.to_osu())
+            .map(|ranking| ranking.iter().map(|(id, pp)| (*id, *pp)).collect())
+            .unwrap_or_default();
+
+        if let Some(country) = country {
+            entries.retain(|(id, _)| {
+                users
+                    .get(id)
+                    .map(|user| user.country == country)
+                    .unwrap_or(false)
+            });
+        }
+
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        entries.into_iter().map(|(id, _)| id).collect()
+    }
+}
+
+#[async_trait]
+impl RankingRepository for MemoryStorage {
+    async fn get_rank(&self, user: &User, mode: &OsuMode) -> Result<Option<i32>, OsuServerError> {
+        Ok(self
+            .ranked_user_ids(mode, None)
+            .await
+            .iter()
+            .position(|id| *id == user.id)
+            .map(|rank| rank as i32 + 1))
+    }
+
+    async fn get_country_rank(
+        &self,
+        user: &User,
+        mode: &OsuMode,
+    ) -> Result<Option<i32>, OsuServerError> {
+        Ok(self
+            .ranked_user_ids(mode, Some(&user.country))
+            .await
+            .iter()
+            .position(|id| *id == user.id)
+            .map(|rank| rank as i32 + 1))
+    }
+
+    async fn get_leaderboard(
+        &self,
+        mode: Option<OsuMode>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> Result<Vec<i32>, OsuServerError> {
+        //Same bounds as ZREVRANGE in redis backend
+        let offset = offset.unwrap_or(0).max(0) as usize;
+        let stop = (limit.unwrap_or(50) - 1).max(0) as usize;
+
+        Ok(self
+            .ranked_user_ids(&mode.unwrap_or(OsuMode::Osu), None)
+            .await
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| *index >= offset && *index <= stop)
+            .map(|(_, id)| id)
+            .collect())
+    }
+
+    async fn get_leaderboard_count(&self, mode: Option<OsuMode>) -> Result<i32, OsuServerError> {
+        Ok(self
+            .rankings
+            .read()
+            .await
+            .get(&mode.unwrap_or(OsuMode::Osu).to_osu())
+            .map(|ranking| ranking.len() as i32)
+            .unwrap_or(0))
+    }
+
+    async fn remove(&self, user: &User) -> Result<(), OsuServerError> {
+        for ranking in self.rankings.write().await.values_mut() {
+            ranking.remove(&user.id);
+        }
+
+        Ok(())
+    }
+
+    async fn recalculate(&self, user: &User, mode: &OsuMode) -> Result<(), OsuServerError> {
+        let mut scores: Vec<Score> = self
+            .scores
+            .read()
+            .await
+            .iter()
+            .filter(|stored| {
+                stored.user_id == user.id
+                    && stored.score.status == 2
+                    && stored.score.playmode == mode.to_osu()
+            })
+            .map(|stored| stored.score.clone())
+            .collect();
+
+        scores.sort_by(|a, b| b.performance.total_cmp(&a.performance));
+
+        let average_accuracy = scores
+            .iter()
+            .map(|score| score.calculate_accuracy())
+            .sum::<f64>()
+            / (scores.len() as f64);
+
+        let mut pp = 0.0;
+
+        for (index, score) in scores.iter().enumerate() {
+            pp += score.performance * to_fixed(((0.95_f32).powi(index as i32)) as f64, 2)
+        }
+
+        self.update_stats(user.id, mode, |stats| {
+            stats.performance = pp.round();
+            stats.accuracy = average_accuracy / 100.0;
+        })
+        .await;
+
+        let mut rankings = self.rankings.write().await;
+        let ranking = rankings.entry(mode.to_osu()).or_default();
+
+        match is_restricted(user).await {
+            true => {
+                ranking.remove(&user.id);
+            }
+            false => {
+                ranking.insert(user.id, pp as i64);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GroupRepository for MemoryStorage {
+    async fn get_user_groups(&self, user_id: i32) -> Result<Vec<Group>, OsuServerError> {
+        Ok(self
+            .groups
+            .read()
+            .await
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl PaymentRepository for MemoryStorage {
+    async fn record(
+        &self,
+        provider: &str,
+        payment: &CompletedPayment,
+    ) -> Result<Option<PaymentEntry>, OsuServerError> {
+        let mut payments = self.payments.write().await;
+        let mut users = self.users.write().await;
+
+        let user = users.get_mut(&payment.user_id).ok_or_else(|| {
+            OsuServerError::Internal(format!(
+                "Payment {} of {} is for unknown user {}",
+                payment.payment_id, provider, payment.user_id
+            ))
+        })?;
+
+        if payments
+            .iter()
+            .any(|entry| entry.payment_system == provider && entry.payment_id == payment.payment_id)
+        {
+            return Ok(None);
+        }
+
+        let now = Utc::now().naive_utc();
+        let until = user.donor_until.filter(|until| *until > now).unwrap_or(now)
+            + chrono::Duration::days((payment.months * DAYS_PER_MONTH).into());
+        user.donor_until = Some(until);
+
+        let entry = PaymentEntry {
+            id: Uuid::new_v4().to_string(),
+            user_id: payment.user_id,
+            payment_id: payment.payment_id,
+            payment_system: provider.to_string(),
+            sum: payment.sum,
+            currency: payment.currency.clone(),
+            finished_at: now,
+            until,
+        };
+
+        payments.push(entry.clone());
+
+        Ok(Some(entry))
+    }
+}
+
+#[async_trait]
+impl PasswordRepository for MemoryStorage {
+    async fn update_password(&self, user_id: i32, password: &str) -> Result<(), OsuServerError> {
+        if let Some(user) = self.users.write().await.get_mut(&user_id) {
+            user.password = password.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn has_recent_reset(&self, user_id: i32) -> Result<bool, OsuServerError> {
+        let since = Utc::now().naive_utc() - chrono::Duration::minutes(1);
+
+        Ok(self
+            .password_resets
+            .read()
+            .await
+            .iter()
+            .any(|reset| reset.user_id == user_id && reset.created_at > since))
+    }
+
+    async fn create_reset(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        ttl_minutes: i32,
+    ) -> Result<(), OsuServerError> {
+        let now = Utc::now().naive_utc();
+        let mut resets = self.password_resets.write().await;
+
+        for reset in resets.iter_mut().filter(|reset| reset.user_id == user_id) {
+            reset.used = true;
+        }
+
+        resets.push(StoredReset {
+            user_id,
+            token_hash: token_hash.to_string(),
+            created_at: now,
+            expires_at: now + chrono::Duration::minutes(ttl_minutes.into()),
+            used: false,
+        });
+
+        Ok(())
+    }
+
+    async fn get_reset_user(&self, token_hash: &str) -> Result<Option<User>, OsuServerError> {
+        let now = Utc::now().naive_utc();
+        let user_id = self
+            .password_resets
+            .read()
+            .await
+            .iter()
+            .find(|reset| reset.token_hash == token_hash && reset.is_valid(now))
+            .map(|reset| reset.user_id);
+
+        match user_id {
+            Some(user_id) => Ok(self.users.read().await.get(&user_id).cloned()),
+            None => Ok(None),
+        }
+    }
+
+    async fn redeem_reset(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<Option<User>, OsuServerError> {
+        let now = Utc::now().naive_utc();
+        let mut resets = self.password_resets.write().await;
+
+        let reset = resets
+            .iter_mut()
+            .find(|reset| reset.token_hash == token_hash && reset.is_valid(now));
+
+        if reset.is_none() {
+            return Ok(None);
+        }
+
+        let reset = reset.unwrap();
+        reset.used = true;
+
+        let mut users = self.users.write().await;
+        let user = users.get_mut(&reset.user_id);
+
+        Ok(user.map(|user| {
+            user.password = password.to_string();
+            user.clone()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_user;
+
+    fn player_score(beatmap_md5: &str, total_score: i32, performance: f64) -> PlayerScore {
+        PlayerScore {
+            id: None,
+            status: ScoreStatus::Best,
+            performance: Some(performance),
+            beatmap_md5: beatmap_md5.to_string(),
+            playmode: 0,
+            total_score,
+            max_combo: 100,
+            count_300: 100,
+            count_100: 0,
+            count_50: 0,
+            count_geki: 0,
+            count_katu: 0,
+            count_miss: 0,
+            mods: 0,
+            perfect: true,
+            submitted_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_users_by_safe_name() {
+        let storage = MemoryStorage::new();
+        storage.add_user(test_user(2, "Some Player")).await;
+
+        assert_eq!(storage.get_id("some_player").await, Some(2));
+        assert_eq!(storage.get_id("SOME PLAYER").await, Some(2));
+        assert_eq!(storage.get_id("someone else").await, None);
+
+        let found = storage
+            .find_by_id_or_username("2".to_string())
+            .await
+            .unwrap();
+        assert_eq!(found.map(|user| user.id), Some(2));
+    }
+
+    #[tokio::test]
+    async fn validates_passwords() {
+        let storage = MemoryStorage::new();
+        storage.add_user(test_user(2, "player")).await;
+
+        assert!(storage.validate_auth("player", "password").await);
+        assert!(!storage.validate_auth("player", "wrong").await);
+        assert!(!storage.validate_auth("nobody", "password").await);
+    }
+
+    #[tokio::test]
+    async fn restrict_hides_scores_and_unrestrict_restores_them() {
+        let storage = MemoryStorage::new();
+        storage.add_user(test_user(2, "first")).await;
+        storage.add_user(test_user(3, "second")).await;
+
+        ScoreRepository::insert(&storage, &player_score("map", 1000, 100.0), 2).await;
+        ScoreRepository::insert(&storage, &player_score("map", 2000, 50.0), 3).await;
+
+        let leaderboard = storage
+            .get_beatmap_leaderboard("map".to_string(), OsuMode::Osu, 0, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|entry| (entry.user.id, entry.score.rank))
+                .collect::<Vec<_>>(),
+            vec![(3, 1), (2, 2)]
+        );
+
+        storage.restrict(3).await;
+
+        let leaderboard = storage
+            .get_beatmap_leaderboard("map".to_string(), OsuMode::Osu, 0, None)
+            .await
+            .unwrap();
+        assert_eq!(leaderboard.len(), 1);
+        assert_eq!(leaderboard[0].user.id, 2);
+
+        storage.unrestrict(3).await;
+
+        let best = storage
+            .get_user_best("map".to_string(), 3, OsuMode::Osu, 0, Some(2))
+            .await
+            .unwrap();
+        assert_eq!(best.map(|entry| entry.score.rank), Some(1));
+    }
+
+    #[tokio::test]
+    async fn updates_score_status() {
+        let storage = MemoryStorage::new();
+        storage.add_user(test_user(2, "player")).await;
+
+        let id = ScoreRepository::insert(&storage, &player_score("map", 1000, 100.0), 2)
+            .await
+            .unwrap();
+        storage.update_status(id, ScoreStatus::Ranked).await;
+
+        let best = storage
+            .get_user_best("map".to_string(), 2, OsuMode::Osu, 0, Some(2))
+            .await
+            .unwrap();
+        assert!(best.is_none());
+
+        let ranked = storage
+            .get_user_best("map".to_string(), 2, OsuMode::Osu, 0, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(ranked.map(|entry| entry.score.id), Some(id));
+    }
+
+    #[tokio::test]
+    async fn recalculate_ranks_users_by_weighted_performance() {
+        let storage = MemoryStorage::new();
+        let first = test_user(2, "first");
+        let second = test_user(3, "second");
+        storage.add_user(first.clone()).await;
+        storage.add_user(second.clone()).await;
+
+        ScoreRepository::insert(&storage, &player_score("a", 1000, 100.0), 2).await;
+        ScoreRepository::insert(&storage, &player_score("b", 1000, 100.0), 2).await;
+        ScoreRepository::insert(&storage, &player_score("a", 1000, 150.0), 3).await;
+
+        storage.recalculate(&first, &OsuMode::Osu).await.unwrap();
+        storage.recalculate(&second, &OsuMode::Osu).await.unwrap();
+
+        //100 + 100 * 0.95
+        let stats = storage.get_stats(2, &OsuMode::Osu).await.unwrap();
+        assert_eq!(stats.performance, 195.0);
+
+        assert_eq!(
+            storage.get_leaderboard(None, None, None).await.unwrap(),
+            vec![2, 3]
+        );
+        assert_eq!(
+            storage.get_rank(&second, &OsuMode::Osu).await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(storage.get_leaderboard_count(None).await.unwrap(), 2);
+
+        storage.remove(&first).await.unwrap();
+        assert_eq!(
+            storage.get_rank(&second, &OsuMode::Osu).await.unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_hwids_by_any_component() {
+        let storage = MemoryStorage::new();
+        let user = test_user(2, "player");
+        storage.add_user(user.clone()).await;
+
+        storage
+            .update_hwid(
+                &user,
+                &HWID {
+                    mac: "mac".to_string(),
+                    uid: "uid".to_string(),
+                    disk: "disk".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+        let by_disk = storage
+            .find_hwids(&HWID {
+                disk: "disk".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(by_disk.len(), 1);
+        assert_eq!(by_disk[0].user.id, 2);
+
+        //Empty components never match
+        let empty = storage.find_hwids(&HWID::default()).await.unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_punishments_are_lifted_once() {
+        let storage = MemoryStorage::new();
+        let past = Utc::now().naive_utc() - chrono::Duration::hours(1);
+        let future = Utc::now().naive_utc() + chrono::Duration::hours(1);
+
+        let expired = PunishmentRepository::insert(
+            &storage,
+            "LOW".to_string(),
+            1,
+            2,
+            "TIMEOUT".to_string(),
+            true,
+            Some(past),
+            String::new(),
+        )
+        .await
+        .unwrap();
+        PunishmentRepository::insert(
+            &storage,
+            "LOW".to_string(),
+            1,
+            2,
+            "RESTRICTION".to_string(),
+            true,
+            Some(future),
+            String::new(),
+        )
+        .await;
+
+        assert!(!storage.has_active(2, "TIMEOUT").await.unwrap());
+        assert!(storage.has_active(2, "RESTRICTION").await.unwrap());
+
+        let due = storage.get_expired().await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, expired.id);
+
+        storage.mark_lifted(&expired.id).await;
+        assert!(storage.get_expired().await.unwrap().is_empty());
+    }
+}
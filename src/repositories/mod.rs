@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::{
+    bancho::client::HWID,
+    db::user::User,
+    utils::{
+        beatmap_utils::Beatmap,
+        group_utils::Group,
+        http_utils::OsuMode,
+        payment_utils::{CompletedPayment, PaymentEntry},
+        score_utils::{OsuServerError, UserScore, UserScoreWithBeatmap},
+        Punishment, UserDbStats, UserHwid,
+    },
+    web::scores::submission::{PlayerScore, ScoreStatus},
+};
+
+#[cfg(test)]
+pub mod memory;
+pub mod postgres;
+
+//Storage used by login, submission and leaderboards. Postgres/redis backed in production,
+//in-memory backend is used by tests, see testing::test_context.
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn validate_auth(&self, username: &str, password: &str) -> bool;
+    async fn get_id(&self, username: &str) -> Option<i32>;
+    async fn get_by_id(&self, id: i32) -> Result<Option<User>, OsuServerError>;
+    async fn find_by_id_or_username(&self, term: String) -> Result<Option<User>, OsuServerError>;
+    async fn get_stats(&self, user_id: i32, mode: &OsuMode) -> Result<UserDbStats, OsuServerError>;
+    async fn find_hwids(&self, hwid: &HWID) -> Result<Vec<UserHwid>, OsuServerError>;
+    async fn update_hwid(&self, user: &User, hwid: &HWID);
+    async fn update_country(&self, user_id: i32, country: String);
+    async fn update_last_seen(&self, user_id: i32, last_seen: NaiveDateTime);
+    async fn clear_pending_verification(&self, user_id: i32);
+    async fn restrict(&self, user_id: i32);
+    async fn unrestrict(&self, user_id: i32);
+    async fn increase_score(&self, user_id: i32, mode: &OsuMode, score: i64);
+    async fn increase_playcount(&self, user_id: i32, mode: &OsuMode);
+    async fn update_max_combo(&self, user_id: i32, mode: &OsuMode, max_combo: i16);
+}
+
+#[async_trait]
+pub trait ScoreRepository: Send + Sync {
+    async fn get_user_best(
+        &self,
+        beatmap_checksum: String,
+        user_id: i32,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Option<UserScore>, OsuServerError>;
+    async fn get_beatmap_leaderboard(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Vec<UserScore>, OsuServerError>;
+    async fn get_first_place(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+    ) -> Option<UserScoreWithBeatmap>;
+    async fn get_by_id(
+        &self,
+        score_id: i32,
+        beatmap_checksum: String,
+        mode: &OsuMode,
+        status: Option<i32>,
+    ) -> Result<Option<UserScoreWithBeatmap>, OsuServerError>;
+    async fn insert(&self, score: &PlayerScore, user_id: i32) -> Option<i32>;
+    async fn update_status(&self, score_id: i32, status: ScoreStatus);
+}
+
+#[async_trait]
+pub trait BeatmapRepository: Send + Sync {
+    async fn get_by_hash(&self, checksum: String) -> Result<Option<Beatmap>, OsuServerError>;
+    async fn get_by_id(&self, id: i64) -> Result<Option<Beatmap>, OsuServerError>;
+    async fn insert(&self, beatmap: Beatmap);
+}
+
+#[async_trait]
+pub trait PunishmentRepository: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
+    async fn insert(
+        &self,
+        level: String,
+        applied_by: i32,
+        applied_to: i32,
+        punishment_type: String,
+        expires: bool,
+        expires_at: Option<NaiveDateTime>,
+        note: String,
+    ) -> Option<Punishment>;
+    async fn get_by_id(&self, id: String) -> Option<Punishment>;
+    async fn get_silenced_until(&self, user_id: i32) -> i64;
+    async fn get_recent_violations(&self, user: &User) -> Result<Vec<Punishment>, OsuServerError>;
+    //Time limited punishments past expiry which were not lifted yet
+    async fn get_expired(&self) -> Result<Vec<Punishment>, OsuServerError>;
+    async fn has_active(&self, user_id: i32, punishment_type: &str)
+        -> Result<bool, OsuServerError>;
+    async fn mark_lifted(&self, id: &str);
+}
+
+#[async_trait]
+pub trait RankingRepository: Send + Sync {
+    async fn get_rank(&self, user: &User, mode: &OsuMode) -> Result<Option<i32>, OsuServerError>;
+    async fn get_country_rank(
+        &self,
+        user: &User,
+        mode: &OsuMode,
+    ) -> Result<Option<i32>, OsuServerError>;
+    async fn get_leaderboard(
+        &self,
+        mode: Option<OsuMode>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> Result<Vec<i32>, OsuServerError>;
+    async fn get_leaderboard_count(&self, mode: Option<OsuMode>) -> Result<i32, OsuServerError>;
+    async fn remove(&self, user: &User) -> Result<(), OsuServerError>;
+    async fn recalculate(&self, user: &User, mode: &OsuMode) -> Result<(), OsuServerError>;
+}
+
+#[async_trait]
+pub trait GroupRepository: Send + Sync {
+    async fn get_user_groups(&self, user_id: i32) -> Result<Vec<Group>, OsuServerError>;
+}
+
+#[async_trait]
+pub trait PaymentRepository: Send + Sync {
+    //Extends donor time of the payer, None when this payment was already recorded
+    async fn record(
+        &self,
+        provider: &str,
+        payment: &CompletedPayment,
+    ) -> Result<Option<PaymentEntry>, OsuServerError>;
+}
+
+#[async_trait]
+pub trait PasswordRepository: Send + Sync {
+    async fn update_password(&self, user_id: i32, password: &str) -> Result<(), OsuServerError>;
+    //Whether a reset was requested in the last minute
+    async fn has_recent_reset(&self, user_id: i32) -> Result<bool, OsuServerError>;
+    //Earlier unused tokens of the user stop working
+    async fn create_reset(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        ttl_minutes: i32,
+    ) -> Result<(), OsuServerError>;
+    //Owner of an unused and unexpired token
+    async fn get_reset_user(&self, token_hash: &str) -> Result<Option<User>, OsuServerError>;
+    //Uses the token and stores the password at once, None when the token is no longer valid
+    async fn redeem_reset(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<Option<User>, OsuServerError>;
+}
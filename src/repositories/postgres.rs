@@ -0,0 +1,468 @@
+use std::{ops::BitAnd, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use redis::aio::ConnectionManager;
+use sqlx::{Pool, Postgres, Row};
+use tracing::warn;
+
+use crate::{
+    bancho::client::HWID,
+    db::user::User,
+    utils::{
+        beatmap_utils::{get_beatmap_by_hash, get_beatmap_by_id, Beatmap},
+        group_utils::{get_user_groups, Group},
+        http_utils::OsuMode,
+        password_utils::{
+            get_reset_token_user, has_recent_reset_token, insert_reset_token, redeem_reset_token,
+            update_user_password,
+        },
+        payment_utils::{record_payment, CompletedPayment, PaymentEntry},
+        score_utils::{
+            get_beatmap_leaderboard, get_first_place_on_beatmap, get_score_by_id, get_user_best,
+            OsuServerError, UserScore, UserScoreWithBeatmap,
+        },
+        user_utils::{
+            find_hwids, find_user_by_id_or_username, get_country_rank, get_expired_punishments,
+            get_leaderboard, get_leaderboard_count, get_punishment_by_id, get_rank,
+            get_silenced_until, get_user_by_id, get_user_id, get_user_recent_vilations,
+            get_user_stats, has_active_punishment, increase_user_playcount, increase_user_score,
+            insert_user_punishment, invalidate_auth_cache, mark_punishment_lifted,
+            recalculate_user_stats, remove_ranking, restrict_user, unrestrict_user,
+            update_user_country, update_user_hwid, update_user_max_combo, validate_auth,
+        },
+        Punishment, UserDbStats, UserHwid,
+    },
+    web::scores::submission::{PlayerScore, ScoreStatus},
+};
+
+use super::{
+    BeatmapRepository, GroupRepository, PasswordRepository, PaymentRepository,
+    PunishmentRepository, RankingRepository, ScoreRepository, UserRepository,
+};
+
+pub struct PostgresUserRepository {
+    pool: Arc<Pool<Postgres>>,
+    redis: ConnectionManager,
+    //Key for auth cache digests
+    secret: String,
+}
+
+impl PostgresUserRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>, redis: ConnectionManager, secret: String) -> Self {
+        Self {
+            pool,
+            redis,
+            secret,
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn validate_auth(&self, username: &str, password: &str) -> bool {
+        validate_auth(&self.redis, &self.pool, &self.secret, username, password).await
+    }
+
+    async fn get_id(&self, username: &str) -> Option<i32> {
+        get_user_id(&self.redis, &self.pool, username).await
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<User>, OsuServerError> {
+        get_user_by_id(&self.pool, id).await
+    }
+
+    async fn find_by_id_or_username(&self, term: String) -> Result<Option<User>, OsuServerError> {
+        find_user_by_id_or_username(&self.pool, term).await
+    }
+
+    async fn get_stats(&self, user_id: i32, mode: &OsuMode) -> Result<UserDbStats, OsuServerError> {
+        get_user_stats(&self.pool, &user_id, mode).await
+    }
+
+    async fn find_hwids(&self, hwid: &HWID) -> Result<Vec<UserHwid>, OsuServerError> {
+        find_hwids(&self.pool, hwid).await
+    }
+
+    async fn update_hwid(&self, user: &User, hwid: &HWID) {
+        update_user_hwid(&self.pool, user, hwid).await
+    }
+
+    async fn update_country(&self, user_id: i32, country: String) {
+        update_user_country(&self.pool, user_id, country).await
+    }
+
+    async fn update_last_seen(&self, user_id: i32, last_seen: NaiveDateTime) {
+        let _ = sqlx::query(r#"UPDATE "User" SET "lastSeen" = $1 WHERE id = $2"#)
+            .bind(last_seen)
+            .bind(user_id)
+            .execute(&*self.pool)
+            .await;
+    }
+
+    async fn clear_pending_verification(&self, user_id: i32) {
+        let _ = sqlx::query(r#"UPDATE "User" SET permissions = 0, flags = 0 WHERE "id" = $1"#)
+            .bind(user_id)
+            .execute(&*self.pool)
+            .await;
+    }
+
+    async fn restrict(&self, user_id: i32) {
+        restrict_user(&self.pool, user_id).await;
+        invalidate_auth_cache(&self.redis, user_id).await;
+    }
+
+    async fn unrestrict(&self, user_id: i32) {
+        unrestrict_user(&self.pool, user_id).await
+    }
+
+    async fn increase_score(&self, user_id: i32, mode: &OsuMode, score: i64) {
+        increase_user_score(&self.pool, mode.clone(), score, &user_id).await
+    }
+
+    async fn increase_playcount(&self, user_id: i32, mode: &OsuMode) {
+        increase_user_playcount(&self.pool, mode.clone(), &user_id).await
+    }
+
+    async fn update_max_combo(&self, user_id: i32, mode: &OsuMode, max_combo: i16) {
+        update_user_max_combo(&self.pool, mode, &user_id, max_combo).await
+    }
+}
+
+pub struct PostgresScoreRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresScoreRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ScoreRepository for PostgresScoreRepository {
+    async fn get_user_best(
+        &self,
+        beatmap_checksum: String,
+        user_id: i32,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Option<UserScore>, OsuServerError> {
+        get_user_best(&self.pool, beatmap_checksum, user_id, mode, mods, 0, status).await
+    }
+
+    async fn get_beatmap_leaderboard(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+        mods: i32,
+        status: Option<i32>,
+    ) -> Result<Vec<UserScore>, OsuServerError> {
+        get_beatmap_leaderboard(&self.pool, beatmap_checksum, mode, mods, status).await
+    }
+
+    async fn get_first_place(
+        &self,
+        beatmap_checksum: String,
+        mode: OsuMode,
+    ) -> Option<UserScoreWithBeatmap> {
+        get_first_place_on_beatmap(&self.pool, beatmap_checksum, mode).await
+    }
+
+    async fn get_by_id(
+        &self,
+        score_id: i32,
+        beatmap_checksum: String,
+        mode: &OsuMode,
+        status: Option<i32>,
+    ) -> Result<Option<UserScoreWithBeatmap>, OsuServerError> {
+        get_score_by_id(&self.pool, score_id, beatmap_checksum, mode, status).await
+    }
+
+    async fn insert(&self, score: &PlayerScore, user_id: i32) -> Option<i32> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO "Score" (
+                "beatmapChecksum",
+                "playMode",
+                "totalScore",
+                "maxCombo",
+                "count300",
+                "count100",
+                "count50",
+                "countGeKi",
+                "countKatu",
+                "countMiss",
+                "mods",
+                "perfect",
+                "status",
+                "submittedAt",
+                "userId",
+                "performance"
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            RETURNING "id"
+        "#,
+        )
+        .bind(score.beatmap_md5.clone())
+        .bind(
+            128.bitand(score.mods)
+                .eq(&128)
+                .then(|| 4)
+                .unwrap_or(score.playmode as i32),
+        )
+        .bind(score.total_score)
+        .bind(score.max_combo)
+        .bind(score.count_300)
+        .bind(score.count_100)
+        .bind(score.count_50)
+        .bind(score.count_geki)
+        .bind(score.count_katu)
+        .bind(score.count_miss)
+        .bind(score.mods)
+        .bind(score.perfect)
+        .bind(score.status.to_db())
+        .bind(score.submitted_at)
+        .bind(user_id)
+        .bind(score.performance.unwrap_or(0.0))
+        .fetch_one(&*self.pool)
+        .await;
+
+        if let Err(error) = id {
+            warn!("{:#?}", error);
+            return None;
+        }
+
+        id.unwrap().try_get::<i32, _>("id").ok()
+    }
+
+    async fn update_status(&self, score_id: i32, status: ScoreStatus) {
+        sqlx::query(
+            r#"
+                UPDATE "Score" SET "status" = $1 WHERE "id" = $2
+            "#,
+        )
+        .bind(status.to_db())
+        .bind(score_id)
+        .execute(&*self.pool)
+        .await
+        .unwrap_or_default();
+    }
+}
+
+pub struct PostgresBeatmapRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresBeatmapRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BeatmapRepository for PostgresBeatmapRepository {
+    async fn get_by_hash(&self, checksum: String) -> Result<Option<Beatmap>, OsuServerError> {
+        get_beatmap_by_hash(&self.pool, checksum).await
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<Beatmap>, OsuServerError> {
+        get_beatmap_by_id(&self.pool, id).await
+    }
+
+    async fn insert(&self, beatmap: Beatmap) {
+        beatmap.insert_in_db(&self.pool).await
+    }
+}
+
+pub struct PostgresPunishmentRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresPunishmentRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PunishmentRepository for PostgresPunishmentRepository {
+    async fn insert(
+        &self,
+        level: String,
+        applied_by: i32,
+        applied_to: i32,
+        punishment_type: String,
+        expires: bool,
+        expires_at: Option<NaiveDateTime>,
+        note: String,
+    ) -> Option<Punishment> {
+        insert_user_punishment(
+            &self.pool,
+            level,
+            applied_by,
+            applied_to,
+            punishment_type,
+            expires,
+            expires_at,
+            note,
+        )
+        .await
+    }
+
+    async fn get_by_id(&self, id: String) -> Option<Punishment> {
+        get_punishment_by_id(&self.pool, id).await
+    }
+
+    async fn get_silenced_until(&self, user_id: i32) -> i64 {
+        get_silenced_until(&self.pool, user_id).await
+    }
+
+    async fn get_recent_violations(&self, user: &User) -> Result<Vec<Punishment>, OsuServerError> {
+        get_user_recent_vilations(&self.pool, user).await
+    }
+
+    async fn get_expired(&self) -> Result<Vec<Punishment>, OsuServerError> {
+        get_expired_punishments(&self.pool).await
+    }
+
+    async fn has_active(
+        &self,
+        user_id: i32,
+        punishment_type: &str,
+    ) -> Result<bool, OsuServerError> {
+        has_active_punishment(&self.pool, user_id, punishment_type).await
+    }
+
+    async fn mark_lifted(&self, id: &str) {
+        mark_punishment_lifted(&self.pool, id).await
+    }
+}
+
+pub struct RedisRankingRepository {
+    pool: Arc<Pool<Postgres>>,
+    redis: ConnectionManager,
+}
+
+impl RedisRankingRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>, redis: ConnectionManager) -> Self {
+        Self { pool, redis }
+    }
+}
+
+#[async_trait]
+impl RankingRepository for RedisRankingRepository {
+    async fn get_rank(&self, user: &User, mode: &OsuMode) -> Result<Option<i32>, OsuServerError> {
+        get_rank(&self.redis, user, mode).await
+    }
+
+    async fn get_country_rank(
+        &self,
+        user: &User,
+        mode: &OsuMode,
+    ) -> Result<Option<i32>, OsuServerError> {
+        get_country_rank(&self.redis, user, mode).await
+    }
+
+    async fn get_leaderboard(
+        &self,
+        mode: Option<OsuMode>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> Result<Vec<i32>, OsuServerError> {
+        get_leaderboard(&self.redis, mode, offset, limit).await
+    }
+
+    async fn get_leaderboard_count(&self, mode: Option<OsuMode>) -> Result<i32, OsuServerError> {
+        get_leaderboard_count(&self.redis, mode).await
+    }
+
+    async fn remove(&self, user: &User) -> Result<(), OsuServerError> {
+        remove_ranking(&self.redis, user).await
+    }
+
+    async fn recalculate(&self, user: &User, mode: &OsuMode) -> Result<(), OsuServerError> {
+        recalculate_user_stats(&self.pool, &self.redis, user, mode).await
+    }
+}
+
+pub struct PostgresGroupRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresGroupRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl GroupRepository for PostgresGroupRepository {
+    async fn get_user_groups(&self, user_id: i32) -> Result<Vec<Group>, OsuServerError> {
+        get_user_groups(&self.pool, user_id).await
+    }
+}
+
+pub struct PostgresPaymentRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresPaymentRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PaymentRepository for PostgresPaymentRepository {
+    async fn record(
+        &self,
+        provider: &str,
+        payment: &CompletedPayment,
+    ) -> Result<Option<PaymentEntry>, OsuServerError> {
+        record_payment(&self.pool, provider, payment).await
+    }
+}
+
+pub struct PostgresPasswordRepository {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresPasswordRepository {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PasswordRepository for PostgresPasswordRepository {
+    async fn update_password(&self, user_id: i32, password: &str) -> Result<(), OsuServerError> {
+        update_user_password(&self.pool, user_id, password).await
+    }
+
+    async fn has_recent_reset(&self, user_id: i32) -> Result<bool, OsuServerError> {
+        has_recent_reset_token(&self.pool, user_id).await
+    }
+
+    async fn create_reset(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        ttl_minutes: i32,
+    ) -> Result<(), OsuServerError> {
+        insert_reset_token(&self.pool, user_id, token_hash, ttl_minutes).await
+    }
+
+    async fn get_reset_user(&self, token_hash: &str) -> Result<Option<User>, OsuServerError> {
+        get_reset_token_user(&self.pool, token_hash).await
+    }
+
+    async fn redeem_reset(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<Option<User>, OsuServerError> {
+        redeem_reset_token(&self.pool, token_hash, password).await
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::error;
+
+use super::score_utils::OsuServerError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActivityType {
+    FirstPlaceLost,
+}
+
+impl ActivityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityType::FirstPlaceLost => "first_place_lost",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserActivity {
+    pub id: i32,
+    #[sqlx(rename = "type")]
+    pub activity_type: String,
+    #[sqlx(rename = "playMode")]
+    pub play_mode: Option<i32>,
+    #[sqlx(rename = "beatmapId")]
+    pub beatmap_id: Option<i32>,
+    #[sqlx(rename = "beatmapTitle")]
+    pub beatmap_title: Option<String>,
+    #[sqlx(rename = "relatedUserId")]
+    pub related_user_id: Option<i32>,
+    #[sqlx(rename = "relatedUsername")]
+    pub related_username: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn insert_activity(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    activity_type: ActivityType,
+    beatmap_checksum: Option<&str>,
+    play_mode: Option<i32>,
+    related_user_id: Option<i32>,
+) {
+    let result = sqlx::query(
+        r#"
+INSERT INTO "UserActivity" ("userId", type, "beatmapChecksum", "playMode", "relatedUserId")
+VALUES ($1, $2, $3, $4, $5)
+"#,
+    )
+    .bind(user_id)
+    .bind(activity_type.as_str())
+    .bind(beatmap_checksum)
+    .bind(play_mode)
+    .bind(related_user_id)
+    .execute(connection)
+    .await;
+
+    if let Err(error) = result {
+        error!("Failed to store activity of {}: {}", user_id, error);
+    }
+}
+
+pub async fn get_user_activity(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<UserActivity>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "UserActivity".id,
+    "UserActivity".type,
+    "UserActivity"."playMode",
+    "UserActivity"."relatedUserId",
+    "UserActivity"."createdAt",
+    "Beatmap"."beatmapId",
+    "Beatmap".artist || ' - ' || "Beatmap".title || ' [' || "Beatmap".version || ']' AS "beatmapTitle",
+    "User".username AS "relatedUsername"
+FROM
+    "UserActivity"
+LEFT JOIN
+    "Beatmap" ON "Beatmap".checksum = "UserActivity"."beatmapChecksum"
+LEFT JOIN
+    "User" ON "User".id = "UserActivity"."relatedUserId"
+WHERE
+    "UserActivity"."userId" = $1
+ORDER BY
+    "UserActivity"."createdAt" DESC
+OFFSET $2
+LIMIT $3
+"#,
+    )
+    .bind(user_id)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch activity: {}", error)))
+}
@@ -0,0 +1,110 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{FromRow, Pool, Postgres};
+use tracing::error;
+
+use super::score_utils::OsuServerError;
+
+#[derive(Debug, Default)]
+pub struct AuditRecord<'a> {
+    //None for actions taken by the server
+    pub actor_id: Option<i32>,
+    pub action: &'a str,
+    pub target_id: Option<i32>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub note: &'a str,
+}
+
+pub async fn insert_audit_log(connection: &Pool<Postgres>, record: AuditRecord<'_>) {
+    let result = sqlx::query(
+        r#"
+INSERT INTO "AuditLog" ("actorId", action, "targetId", before, after, note)
+VALUES ($1, $2, $3, $4, $5, $6)
+"#,
+    )
+    .bind(record.actor_id)
+    .bind(record.action)
+    .bind(record.target_id)
+    .bind(record.before)
+    .bind(record.after)
+    .bind(record.note)
+    .execute(connection)
+    .await;
+
+    if let Err(error) = result {
+        error!("Failed to store audit log {}: {}", record.action, error);
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AuditEntry {
+    pub id: i32,
+    #[sqlx(rename = "actorId")]
+    pub actor_id: Option<i32>,
+    #[sqlx(rename = "actorUsername")]
+    pub actor_username: Option<String>,
+    pub action: String,
+    #[sqlx(rename = "targetId")]
+    pub target_id: Option<i32>,
+    #[sqlx(rename = "targetUsername")]
+    pub target_username: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub note: String,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor_id: Option<i32>,
+    pub target_id: Option<i32>,
+    //Matches exact action or whole group, e.g. "punishment"
+    pub action: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+pub async fn get_audit_log(
+    connection: &Pool<Postgres>,
+    filter: &AuditFilter,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<AuditEntry>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "AuditLog".*,
+    actor.username as "actorUsername",
+    target.username as "targetUsername"
+FROM
+    "AuditLog"
+LEFT JOIN
+    "User" actor ON actor.id = "AuditLog"."actorId"
+LEFT JOIN
+    "User" target ON target.id = "AuditLog"."targetId"
+WHERE
+    ($1::integer IS NULL OR "AuditLog"."actorId" = $1) AND
+    ($2::integer IS NULL OR "AuditLog"."targetId" = $2) AND
+    ($3::text IS NULL OR "AuditLog".action = $3 OR "AuditLog".action LIKE $3 || '.%') AND
+    ($4::timestamp IS NULL OR "AuditLog"."createdAt" >= $4) AND
+    ($5::timestamp IS NULL OR "AuditLog"."createdAt" < $5)
+ORDER BY
+    "AuditLog"."createdAt" DESC, "AuditLog".id DESC
+OFFSET $6
+LIMIT $7
+"#,
+    )
+    .bind(filter.actor_id)
+    .bind(filter.target_id)
+    .bind(&filter.action)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch audit log: {}", error)))
+}
@@ -0,0 +1,259 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::info;
+
+use crate::context::Context;
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    score_utils::OsuServerError,
+};
+
+pub const DONOR_BADGE: &str = "donor";
+pub const TOURNAMENT_WINNER_BADGE: &str = "tournament_winner";
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BadgeDefinition {
+    pub id: i32,
+    //Set on badges granted automatically
+    pub key: Option<String>,
+    pub name: String,
+    pub icon: String,
+    pub color: String,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BadgeHolder {
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    pub username: String,
+    #[sqlx(rename = "expiresAt")]
+    pub expires_at: Option<NaiveDateTime>,
+    #[sqlx(rename = "grantedBy")]
+    pub granted_by: Option<i32>,
+    pub note: String,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Failed to query badges: {}", error))
+}
+
+pub async fn get_badges(
+    connection: &Pool<Postgres>,
+) -> Result<Vec<BadgeDefinition>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "Badge" ORDER BY id"#)
+        .fetch_all(connection)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get_badge(
+    connection: &Pool<Postgres>,
+    id: i32,
+) -> Result<Option<BadgeDefinition>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "Badge" WHERE id = $1"#)
+        .bind(id)
+        .fetch_optional(connection)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get_badge_by_key(
+    connection: &Pool<Postgres>,
+    key: &str,
+) -> Result<Option<BadgeDefinition>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "Badge" WHERE key = $1"#)
+        .bind(key)
+        .fetch_optional(connection)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn create_badge(
+    connection: &Pool<Postgres>,
+    name: &str,
+    icon: &str,
+    color: &str,
+) -> Result<BadgeDefinition, OsuServerError> {
+    sqlx::query_as(r#"INSERT INTO "Badge" (name, icon, color) VALUES ($1, $2, $3) RETURNING *"#)
+        .bind(name)
+        .bind(icon)
+        .bind(color)
+        .fetch_one(connection)
+        .await
+        .map_err(database_error)
+}
+
+//Only active grants, expired ones are kept until badge is granted again
+pub async fn get_badge_holders(
+    connection: &Pool<Postgres>,
+    badge_id: i32,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<BadgeHolder>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "UserBadge"."userId",
+    "UserBadge"."expiresAt",
+    "UserBadge"."grantedBy",
+    "UserBadge".note,
+    "UserBadge"."createdAt",
+    "User".username
+FROM
+    "UserBadge"
+JOIN
+    "User" ON "User".id = "UserBadge"."userId"
+WHERE
+    "UserBadge"."badgeId" = $1 AND
+    ("UserBadge"."expiresAt" IS NULL OR "UserBadge"."expiresAt" > NOW())
+ORDER BY "UserBadge"."createdAt"
+OFFSET $2
+LIMIT $3
+"#,
+    )
+    .bind(badge_id)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+//Granting a badge user already holds replaces its expiry
+pub async fn grant_badge(
+    ctx: &Context,
+    badge: &BadgeDefinition,
+    user_id: i32,
+    actor_id: Option<i32>,
+    expires_at: Option<NaiveDateTime>,
+    note: &str,
+) -> Result<(), OsuServerError> {
+    sqlx::query(
+        r#"
+INSERT INTO "UserBadge" (name, icon, color, "userId", "badgeId", "expiresAt", "grantedBy", note)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+ON CONFLICT ("userId", "badgeId") DO UPDATE SET
+    name = EXCLUDED.name,
+    icon = EXCLUDED.icon,
+    color = EXCLUDED.color,
+    "expiresAt" = EXCLUDED."expiresAt",
+    "grantedBy" = EXCLUDED."grantedBy",
+    note = EXCLUDED.note
+"#,
+    )
+    .bind(&badge.name)
+    .bind(&badge.icon)
+    .bind(&badge.color)
+    .bind(user_id)
+    .bind(badge.id)
+    .bind(expires_at)
+    .bind(actor_id)
+    .bind(note)
+    .execute(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id,
+            action: "badge.granted",
+            target_id: Some(user_id),
+            after: Some(json!({ "badgeId": badge.id, "expiresAt": expires_at })),
+            note,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn revoke_badge(
+    ctx: &Context,
+    badge: &BadgeDefinition,
+    user_id: i32,
+    actor_id: i32,
+) -> Result<bool, OsuServerError> {
+    let result = sqlx::query(r#"DELETE FROM "UserBadge" WHERE "badgeId" = $1 AND "userId" = $2"#)
+        .bind(badge.id)
+        .bind(user_id)
+        .execute(&*ctx.pool)
+        .await
+        .map_err(database_error)?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(actor_id),
+            action: "badge.revoked",
+            target_id: Some(user_id),
+            before: Some(json!({ "badgeId": badge.id })),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(true)
+}
+
+//Grants one of automatic badges, missing definition is not an error
+pub async fn grant_automatic_badge(
+    ctx: &Context,
+    key: &str,
+    user_id: i32,
+    expires_at: Option<NaiveDateTime>,
+    note: &str,
+) -> Result<(), OsuServerError> {
+    let badge = get_badge_by_key(&ctx.pool, key).await?;
+
+    if badge.is_none() {
+        return Ok(());
+    }
+
+    grant_badge(ctx, &badge.unwrap(), user_id, None, expires_at, note).await
+}
+
+//Keeps donor badge expiry in line with "donorUntil"
+pub async fn sync_donor_badges(ctx: &Context) -> Result<(), OsuServerError> {
+    let changed: Vec<(i32, Option<NaiveDateTime>)> = sqlx::query_as(
+        r#"
+SELECT
+    "User".id, "User"."donorUntil"
+FROM
+    "User"
+JOIN
+    "Badge" ON "Badge".key = $1
+LEFT JOIN
+    "UserBadge" ON "UserBadge"."badgeId" = "Badge".id AND "UserBadge"."userId" = "User".id
+WHERE
+    "User"."donorUntil" > NOW() AND
+    "UserBadge"."expiresAt" IS DISTINCT FROM "User"."donorUntil"
+"#,
+    )
+    .bind(DONOR_BADGE)
+    .fetch_all(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    if !changed.is_empty() {
+        info!("updating donor badge of {} users", changed.len());
+    }
+
+    for (user_id, donor_until) in changed {
+        grant_automatic_badge(ctx, DONOR_BADGE, user_id, donor_until, "Automatic: donor").await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+
+use super::score_utils::OsuServerError;
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    //Capability bits, see permission_utils
+    pub permissions: i32,
+    #[sqlx(rename = "badgeId")]
+    pub badge_id: Option<i32>,
+    #[sqlx(rename = "requireTwoFactor")]
+    pub require_two_factor: bool,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct GroupMember {
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    pub username: String,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Failed to query groups: {}", error))
+}
+
+pub async fn get_groups(connection: &Pool<Postgres>) -> Result<Vec<Group>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "Group" ORDER BY permissions DESC, id"#)
+        .fetch_all(connection)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get_group(
+    connection: &Pool<Postgres>,
+    id: &str,
+) -> Result<Option<Group>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "Group" WHERE id = $1"#)
+        .bind(id)
+        .fetch_optional(connection)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get_user_groups(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<Group>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "Group".*
+FROM
+    "UserGroup"
+JOIN
+    "Group" ON "Group".id = "UserGroup"."groupId"
+WHERE
+    "UserGroup"."userId" = $1
+ORDER BY "Group".permissions DESC
+"#,
+    )
+    .bind(user_id)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn get_group_members(
+    connection: &Pool<Postgres>,
+    group_id: &str,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<GroupMember>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "UserGroup"."userId", "UserGroup"."createdAt", "User".username
+FROM
+    "UserGroup"
+JOIN
+    "User" ON "User".id = "UserGroup"."userId"
+WHERE
+    "UserGroup"."groupId" = $1
+ORDER BY "UserGroup"."createdAt"
+OFFSET $2
+LIMIT $3
+"#,
+    )
+    .bind(group_id)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+//Returns false when user already was a member
+pub async fn add_group_member(
+    connection: &Pool<Postgres>,
+    group_id: &str,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    let result = sqlx::query(
+        r#"INSERT INTO "UserGroup" ("userId", "groupId") VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+    )
+    .bind(user_id)
+    .bind(group_id)
+    .execute(connection)
+    .await
+    .map_err(database_error)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_group_member(
+    connection: &Pool<Postgres>,
+    group_id: &str,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    let result = sqlx::query(r#"DELETE FROM "UserGroup" WHERE "userId" = $1 AND "groupId" = $2"#)
+        .bind(user_id)
+        .bind(group_id)
+        .execute(connection)
+        .await
+        .map_err(database_error)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_group_two_factor(
+    connection: &Pool<Postgres>,
+    group_id: &str,
+    required: bool,
+) -> Result<bool, OsuServerError> {
+    let result = sqlx::query(r#"UPDATE "Group" SET "requireTwoFactor" = $2 WHERE id = $1"#)
+        .bind(group_id)
+        .bind(required)
+        .execute(connection)
+        .await
+        .map_err(database_error)?;
+
+    Ok(result.rows_affected() > 0)
+}
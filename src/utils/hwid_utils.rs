@@ -0,0 +1,795 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{prelude::FromRow, Pool, Postgres, Row};
+use tracing::{error, info, warn};
+use webhook::client::WebhookClient;
+
+use crate::{bancho::client::HWID, context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    score_utils::OsuServerError,
+    user_utils::{
+        is_restricted, send_bancho_message, PENDING_VERIFICATION_FLAG, RESTRICTED_PERMISSION,
+    },
+    DatabaseHwid, UserHwid,
+};
+
+//Linked accounts are walked breadth first, this keeps huge internet cafe clusters bounded
+const MAX_CLUSTER_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HwidComponent {
+    Mac,
+    UniqueId,
+    DiskId,
+}
+
+impl HwidComponent {
+    pub const ALL: [HwidComponent; 3] = [
+        HwidComponent::Mac,
+        HwidComponent::UniqueId,
+        HwidComponent::DiskId,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mac" => Some(HwidComponent::Mac),
+            "unique_id" => Some(HwidComponent::UniqueId),
+            "disk_id" => Some(HwidComponent::DiskId),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HwidComponent::Mac => "mac",
+            HwidComponent::UniqueId => "unique_id",
+            HwidComponent::DiskId => "disk_id",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            HwidComponent::Mac => "mac",
+            HwidComponent::UniqueId => "uniqueId",
+            HwidComponent::DiskId => "diskId",
+        }
+    }
+
+    pub fn of_client<'a>(&self, hwid: &'a HWID) -> &'a str {
+        match self {
+            HwidComponent::Mac => &hwid.mac,
+            HwidComponent::UniqueId => &hwid.uid,
+            HwidComponent::DiskId => &hwid.disk,
+        }
+    }
+
+    pub fn of_stored<'a>(&self, hwid: &'a DatabaseHwid) -> &'a str {
+        match self {
+            HwidComponent::Mac => &hwid.mac,
+            HwidComponent::UniqueId => &hwid.unique_id,
+            HwidComponent::DiskId => &hwid.disk_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HwidAction {
+    Log,
+    Flag,
+    Block,
+    Restrict,
+}
+
+impl HwidAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "log" => Some(HwidAction::Log),
+            "flag" => Some(HwidAction::Flag),
+            "block" => Some(HwidAction::Block),
+            "restrict" => Some(HwidAction::Restrict),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HwidAction::Log => "log",
+            HwidAction::Flag => "flag",
+            HwidAction::Block => "block",
+            HwidAction::Restrict => "restrict",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct HwidRule {
+    pub id: i32,
+    pub component: String,
+    pub action: String,
+    #[sqlx(rename = "minAccounts")]
+    pub min_accounts: i32,
+    pub enabled: bool,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct HwidWhitelistEntry {
+    pub id: i32,
+    pub component: String,
+    pub value: String,
+    pub note: String,
+    #[sqlx(rename = "createdBy")]
+    pub created_by: i32,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct HwidFlag {
+    pub id: i32,
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    #[sqlx(rename = "linkedUserIds")]
+    pub linked_user_ids: Vec<i32>,
+    pub component: String,
+    pub value: String,
+    pub action: String,
+    pub resolved: bool,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+//Single rule that matched at login, with accounts sharing the component
+#[derive(Debug, Clone)]
+pub struct HwidHit {
+    pub component: HwidComponent,
+    pub action: HwidAction,
+    pub value: String,
+    pub users: Vec<User>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedHwid {
+    pub component: String,
+    pub value: String,
+    pub user_ids: Vec<i32>,
+    pub usernames: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HwidLink {
+    pub component: HwidComponent,
+    pub value: String,
+    pub user_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterMember {
+    pub id: i32,
+    pub username: String,
+    pub restricted: bool,
+    pub created_at: NaiveDateTime,
+    pub links: Vec<HwidLink>,
+}
+
+pub async fn get_hwid_rules(connection: &Pool<Postgres>) -> Result<Vec<HwidRule>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "HwidPolicyRule" ORDER BY id"#)
+        .fetch_all(connection)
+        .await
+        .map_err(|error| OsuServerError::Internal(format!("Failed to fetch hwid rules: {}", error)))
+}
+
+pub async fn update_hwid_rule(
+    connection: &Pool<Postgres>,
+    id: i32,
+    action: Option<HwidAction>,
+    min_accounts: Option<i32>,
+    enabled: Option<bool>,
+) -> Result<Option<HwidRule>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+UPDATE
+    "HwidPolicyRule"
+SET
+    action = COALESCE($2, action),
+    "minAccounts" = COALESCE($3, "minAccounts"),
+    enabled = COALESCE($4, enabled)
+WHERE
+    id = $1
+RETURNING *
+"#,
+    )
+    .bind(id)
+    .bind(action.map(|action| action.as_str()))
+    .bind(min_accounts)
+    .bind(enabled)
+    .fetch_optional(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to update hwid rule: {}", error)))
+}
+
+pub async fn get_hwid_whitelist(
+    connection: &Pool<Postgres>,
+) -> Result<Vec<HwidWhitelistEntry>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "HwidWhitelist" ORDER BY id"#)
+        .fetch_all(connection)
+        .await
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to fetch hwid whitelist: {}", error))
+        })
+}
+
+pub async fn add_hwid_whitelist(
+    connection: &Pool<Postgres>,
+    component: HwidComponent,
+    value: String,
+    note: String,
+    created_by: i32,
+) -> Result<HwidWhitelistEntry, OsuServerError> {
+    sqlx::query_as(
+        r#"
+INSERT INTO "HwidWhitelist" (component, value, note, "createdBy")
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (component, value) DO UPDATE SET note = EXCLUDED.note
+RETURNING *
+"#,
+    )
+    .bind(component.as_str())
+    .bind(value)
+    .bind(note)
+    .bind(created_by)
+    .fetch_one(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to whitelist hwid: {}", error)))
+}
+
+pub async fn remove_hwid_whitelist(
+    connection: &Pool<Postgres>,
+    id: i32,
+) -> Result<bool, OsuServerError> {
+    sqlx::query(r#"DELETE FROM "HwidWhitelist" WHERE id = $1"#)
+        .bind(id)
+        .execute(connection)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to remove hwid whitelist: {}", error))
+        })
+}
+
+pub async fn get_hwid_flags(
+    connection: &Pool<Postgres>,
+    resolved: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<HwidFlag>, OsuServerError> {
+    sqlx::query_as(
+        r#"SELECT * FROM "HwidFlag" WHERE resolved = $1 ORDER BY "createdAt" DESC OFFSET $2 LIMIT $3"#,
+    )
+    .bind(resolved)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch hwid flags: {}", error)))
+}
+
+pub async fn resolve_hwid_flag(
+    connection: &Pool<Postgres>,
+    id: i32,
+) -> Result<bool, OsuServerError> {
+    sqlx::query(r#"UPDATE "HwidFlag" SET resolved = true WHERE id = $1"#)
+        .bind(id)
+        .execute(connection)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to resolve hwid flag: {}", error))
+        })
+}
+
+//Returns true only for a new flag, repeated logins don't stack flags staff already has to review
+async fn insert_hwid_flag(connection: &Pool<Postgres>, user: &User, hit: &HwidHit) -> bool {
+    let result = sqlx::query(
+        r#"
+INSERT INTO "HwidFlag" ("userId", "linkedUserIds", component, value, action)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT ("userId", component, value, action) WHERE NOT resolved DO NOTHING
+"#,
+    )
+    .bind(user.id)
+    .bind(hit.users.iter().map(|user| user.id).collect::<Vec<i32>>())
+    .bind(hit.component.as_str())
+    .bind(&hit.value)
+    .bind(hit.action.as_str())
+    .execute(connection)
+    .await;
+
+    match result {
+        Ok(result) => result.rows_affected() > 0,
+        Err(error) => {
+            error!("Failed to flag hwid of {}: {}", user.id, error);
+            false
+        }
+    }
+}
+
+//Values shared by more than one account, whitelisted machines are skipped
+pub async fn get_shared_hwids(
+    connection: &Pool<Postgres>,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<SharedHwid>, OsuServerError> {
+    let rows = sqlx::query(
+        r#"
+SELECT
+    shared.component,
+    shared.value,
+    array_agg(shared."userId" ORDER BY shared."userId") AS user_ids,
+    array_agg("User".username ORDER BY shared."userId") AS usernames
+FROM (
+    SELECT 'mac' AS component, mac AS value, "userId" FROM "Hwid"
+    UNION ALL
+    SELECT 'unique_id' AS component, "uniqueId" AS value, "userId" FROM "Hwid"
+    UNION ALL
+    SELECT 'disk_id' AS component, "diskId" AS value, "userId" FROM "Hwid"
+) shared
+JOIN
+    "User" ON "User".id = shared."userId"
+WHERE
+    shared.value IS NOT NULL AND shared.value <> '' AND
+    NOT EXISTS (
+        SELECT 1 FROM "HwidWhitelist"
+        WHERE "HwidWhitelist".component = shared.component AND "HwidWhitelist".value = shared.value
+    )
+GROUP BY
+    shared.component, shared.value
+HAVING
+    COUNT(*) > 1
+ORDER BY
+    COUNT(*) DESC, shared.component, shared.value
+OFFSET $1
+LIMIT $2
+"#,
+    )
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await;
+
+    if let Err(error) = rows {
+        return Err(OsuServerError::Internal(format!(
+            "Failed to fetch shared hwids: {}",
+            error
+        )));
+    }
+
+    Ok(rows
+        .unwrap()
+        .iter()
+        .map(|row| SharedHwid {
+            component: row.get("component"),
+            value: row.get("value"),
+            user_ids: row.get("user_ids"),
+            usernames: row.get("usernames"),
+        })
+        .collect())
+}
+
+//Every account reachable from user through shared, non whitelisted components
+pub async fn get_linked_cluster(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<ClusterMember>, OsuServerError> {
+    let whitelist: HashSet<(String, String)> = get_hwid_whitelist(connection)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.component, entry.value))
+        .collect();
+
+    let mut members: HashMap<i32, ClusterMember> = HashMap::new();
+    let mut visited_values: HashSet<(HwidComponent, String)> = HashSet::new();
+    let mut queue = VecDeque::from([user_id]);
+
+    while let Some(current) = queue.pop_front() {
+        if members.len() >= MAX_CLUSTER_SIZE {
+            break;
+        }
+
+        let hwid =
+            sqlx::query(r#"SELECT mac, "uniqueId", "diskId" FROM "Hwid" WHERE "userId" = $1"#)
+                .bind(current)
+                .fetch_optional(connection)
+                .await
+                .map_err(|error| {
+                    OsuServerError::Internal(format!("Failed to fetch hwid: {}", error))
+                })?;
+
+        if let Some(hwid) = hwid {
+            for component in HwidComponent::ALL {
+                let value: Option<String> = hwid.get(component.column());
+                let value = value.unwrap_or_default();
+
+                if value.is_empty()
+                    || whitelist.contains(&(component.as_str().to_string(), value.clone()))
+                    || !visited_values.insert((component, value.clone()))
+                {
+                    continue;
+                }
+
+                let linked = sqlx::query(&format!(
+                    r#"
+SELECT
+    "User".id, "User".username, "User".permissions, "User".flags, "User"."createdAt"
+FROM
+    "Hwid"
+JOIN
+    "User" ON "Hwid"."userId" = "User".id
+WHERE
+    "Hwid"."{}" = $1
+LIMIT $2
+"#,
+                    component.column()
+                ))
+                .bind(&value)
+                .bind(MAX_CLUSTER_SIZE as i64)
+                .fetch_all(connection)
+                .await
+                .map_err(|error| {
+                    OsuServerError::Internal(format!("Failed to fetch linked users: {}", error))
+                })?;
+
+                for row in linked {
+                    let id: i32 = row.get("id");
+                    let permissions: i32 = row.get("permissions");
+                    let flags: i32 = row.get("flags");
+
+                    if !members.contains_key(&id) {
+                        queue.push_back(id);
+                    }
+
+                    let member = members.entry(id).or_insert_with(|| ClusterMember {
+                        id,
+                        username: row.get("username"),
+                        restricted: permissions & RESTRICTED_PERMISSION > 0
+                            && flags & PENDING_VERIFICATION_FLAG == 0,
+                        created_at: row.get("createdAt"),
+                        links: Vec::new(),
+                    });
+
+                    if id != current {
+                        member.links.push(HwidLink {
+                            component,
+                            value: value.clone(),
+                            user_id: current,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    let mut members: Vec<ClusterMember> = members.into_values().collect();
+    members.sort_by_key(|member| member.created_at);
+
+    Ok(members)
+}
+
+pub async fn evaluate_hwid_policy(
+    ctx: &Context,
+    user: &User,
+    hwid: &HWID,
+) -> Result<Vec<HwidHit>, OsuServerError> {
+    let others: Vec<_> = ctx
+        .users
+        .find_hwids(hwid)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.user.id != user.id)
+        .collect();
+
+    //Nothing shared, skipping rules lookup entirely
+    if others.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rules = get_hwid_rules(&ctx.pool).await?;
+    let whitelist = get_hwid_whitelist(&ctx.pool).await?;
+
+    Ok(match_hwid_rules(hwid, &others, &rules, &whitelist))
+}
+
+fn match_hwid_rules(
+    hwid: &HWID,
+    others: &[UserHwid],
+    rules: &[HwidRule],
+    whitelist: &[HwidWhitelistEntry],
+) -> Vec<HwidHit> {
+    let mut hits = Vec::new();
+
+    for component in HwidComponent::ALL {
+        let value = component.of_client(hwid);
+
+        if value.is_empty()
+            || whitelist
+                .iter()
+                .any(|entry| entry.component == component.as_str() && entry.value == value)
+        {
+            continue;
+        }
+
+        let users: Vec<User> = others
+            .iter()
+            .filter(|entry| component.of_stored(&entry.hwid) == value)
+            .map(|entry| entry.user.clone())
+            .collect();
+
+        if users.is_empty() {
+            continue;
+        }
+
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            if HwidComponent::parse(&rule.component) != Some(component)
+                || (users.len() as i32) < rule.min_accounts
+            {
+                continue;
+            }
+
+            let action = HwidAction::parse(&rule.action);
+
+            if action.is_none() {
+                warn!("Unknown action in hwid rule #{}: {}", rule.id, rule.action);
+                continue;
+            }
+
+            hits.push(HwidHit {
+                component,
+                action: action.unwrap(),
+                value: value.to_string(),
+                users: users.clone(),
+            });
+        }
+    }
+
+    hits
+}
+
+//Returns false when the newest account was already restricted
+async fn restrict_newest_account(ctx: &Context, user: &User, hit: &HwidHit) -> bool {
+    let newest = std::iter::once(user)
+        .chain(hit.users.iter())
+        .max_by_key(|user| user.created_at)
+        .unwrap();
+
+    if is_restricted(newest).await {
+        return false;
+    }
+
+    let linked = std::iter::once(user)
+        .chain(hit.users.iter())
+        .filter(|linked| linked.id != newest.id)
+        .map(|linked| format!("{} ({})", linked.username, linked.id))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let note = format!(
+        "Lia: multiaccounting, shares {} with {}.",
+        hit.component.as_str(),
+        linked
+    );
+
+    ctx.punishments
+        .insert(
+            "CRITICAL".to_string(),
+            1,
+            newest.id,
+            "RESTRICTION".to_string(),
+            false,
+            None,
+            note.clone(),
+        )
+        .await;
+    ctx.users.restrict(newest.id).await;
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: None,
+            action: "punishment.auto_restrict",
+            target_id: Some(newest.id),
+            before: Some(json!({ "permissions": newest.permissions })),
+            after: Some(json!({ "permissions": RESTRICTED_PERMISSION })),
+            note: &note,
+        },
+    )
+    .await;
+
+    if let Err(error) = ctx.rankings.remove(newest).await {
+        error!("Failed to remove {} from rankings: {:#?}", newest.id, error);
+    }
+
+    if newest.id != user.id {
+        send_bancho_message(&newest.id, "user:restricted".to_string(), None).await;
+    }
+
+    true
+}
+
+//Runs every matched action, returns false when login has to be refused.
+//Alert only covers what changed, so the same shared machine isn't reported on every login
+pub async fn apply_hwid_policy(ctx: &Context, user: &User, hits: &[HwidHit], host: &str) -> bool {
+    let mut allowed = true;
+    let mut reported = Vec::new();
+
+    for hit in hits {
+        info!(
+            "HWID {} of {} ({}) shared with {} accounts, action: {}",
+            hit.component.as_str(),
+            user.username,
+            user.id,
+            hit.users.len(),
+            hit.action.as_str()
+        );
+
+        let report = match hit.action {
+            HwidAction::Log => false,
+            HwidAction::Flag => insert_hwid_flag(&ctx.pool, user, hit).await,
+            HwidAction::Block => {
+                allowed = false;
+                true
+            }
+            HwidAction::Restrict => restrict_newest_account(ctx, user, hit).await,
+        };
+
+        if report {
+            reported.push(hit.clone());
+        }
+    }
+
+    if !reported.is_empty() {
+        hwid_alert(ctx, user, &reported, host).await;
+    }
+
+    allowed
+}
+
+async fn hwid_alert(ctx: &Context, user: &User, hits: &[HwidHit], host: &str) {
+    if let Some(webhook) = ctx.config.alert_discord_webhook.clone() {
+        let client = WebhookClient::new(webhook.as_str());
+
+        let formatted_report = hits
+            .iter()
+            .map(|hit| {
+                format!(
+                    "`{}` -> `{}`\n{}",
+                    hit.component.as_str(),
+                    hit.action.as_str(),
+                    hit.users
+                        .iter()
+                        .map(|entry| format!(
+                            "{} -> https://{}/users/{}",
+                            entry.username, ctx.config.server_url, entry.id
+                        ))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        let is_report_ok = client
+            .send(|message| {
+                message
+                    .content("Multiple users with the same HWID found!")
+                    .embed(|embed| {
+                        embed
+                            .author(
+                                user.username.as_str(),
+                                Some(format!(
+                                    "https://{}/users/{}",
+                                    ctx.config.server_url, user.id
+                                )),
+                                Some(format!("https://a.{}/{}", ctx.config.server_url, user.id)),
+                            )
+                            .description(formatted_report.as_str())
+                            .footer(format!("Host: {}", host).as_str(), None)
+                    })
+            })
+            .await;
+
+        if let Err(error) = is_report_ok {
+            error!("Report of hwid was unsuccessful: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_user;
+
+    fn rule(id: i32, component: HwidComponent, action: &str, min_accounts: i32) -> HwidRule {
+        HwidRule {
+            id,
+            component: component.as_str().to_string(),
+            action: action.to_string(),
+            min_accounts,
+            enabled: true,
+            note: String::new(),
+        }
+    }
+
+    fn stored(user_id: i32, mac: &str, unique_id: &str, disk_id: &str) -> UserHwid {
+        UserHwid {
+            hwid: DatabaseHwid {
+                id: user_id,
+                user_id,
+                mac: mac.to_string(),
+                unique_id: unique_id.to_string(),
+                disk_id: disk_id.to_string(),
+            },
+            user: test_user(user_id, &format!("user{}", user_id)),
+        }
+    }
+
+    fn client(mac: &str, uid: &str, disk: &str) -> HWID {
+        HWID {
+            mac: mac.to_string(),
+            uid: uid.to_string(),
+            disk: disk.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_components_separately() {
+        let others = [stored(3, "mac", "other", "disk"), stored(4, "mac", "", "")];
+        let rules = [
+            rule(1, HwidComponent::Mac, "log", 1),
+            rule(2, HwidComponent::DiskId, "block", 1),
+            rule(3, HwidComponent::UniqueId, "flag", 1),
+        ];
+
+        let hits = match_hwid_rules(&client("mac", "uid", "disk"), &others, &rules, &[]);
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].component, HwidComponent::Mac);
+        assert_eq!(hits[0].users.len(), 2);
+        assert_eq!(hits[1].component, HwidComponent::DiskId);
+        assert_eq!(hits[1].action, HwidAction::Block);
+        assert_eq!(hits[1].users[0].id, 3);
+    }
+
+    #[test]
+    fn respects_minimum_accounts_and_disabled_rules() {
+        let others = [stored(3, "", "uid", "")];
+        let mut disabled = rule(2, HwidComponent::UniqueId, "restrict", 1);
+        disabled.enabled = false;
+        let rules = [rule(1, HwidComponent::UniqueId, "flag", 2), disabled];
+
+        assert!(match_hwid_rules(&client("", "uid", ""), &others, &rules, &[]).is_empty());
+    }
+
+    #[test]
+    fn skips_whitelisted_values() {
+        let others = [stored(3, "", "cafe", "")];
+        let rules = [rule(1, HwidComponent::UniqueId, "block", 1)];
+        let whitelist = [HwidWhitelistEntry {
+            id: 1,
+            component: HwidComponent::UniqueId.as_str().to_string(),
+            value: "cafe".to_string(),
+            note: String::new(),
+            created_by: 1,
+            created_at: NaiveDateTime::UNIX_EPOCH,
+        }];
+
+        assert!(match_hwid_rules(&client("", "cafe", ""), &others, &rules, &whitelist).is_empty());
+    }
+}
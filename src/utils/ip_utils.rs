@@ -1,5 +1,24 @@
-use serde::Deserialize;
-use tracing::error;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+use ipnet::IpNet;
+use lru::LruCache;
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{config::RunConfiguration, context::Context};
 
 macro_rules! country_enum {
     ($($country:ident = $value:expr),*) => {
@@ -243,7 +262,7 @@ country_enum!(
     MF = 252
 );
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct IpApiResponse {
     #[serde(rename = "countryCode")]
     pub code: String,
@@ -251,31 +270,415 @@ pub struct IpApiResponse {
     pub lon: f32,
 }
 
-#[allow(unreachable_code)]
-#[allow(unused_variables)]
-pub async fn get_ip_info(ip: Option<String>) -> Option<IpApiResponse> {
-    #[cfg(debug_assertions)]
-    return Some(IpApiResponse {
-        code: "DE".to_string(),
-        lat: 0.0,
-        lon: 0.0,
-    });
-
-    let resp = reqwest::get(format!(
-        "http://ip-api.com/json/{}?fields=countryCode,lat,lon",
-        ip.clone().unwrap_or("".to_string())
-    ))
-    .await
-    .unwrap()
-    .json::<IpApiResponse>()
-    .await;
-
-    if let Err(_error) = resp {
-        error!("Error looking up for ip: {:#?}", ip.unwrap_or_default());
-        return None;
+//Only one header is ever read, so a proxy that passes client supplied headers through
+//untouched can't be used to pick another address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientIpHeader {
+    ForwardedFor,
+    RealIp,
+    CfConnectingIp,
+    None,
+}
+
+impl ClientIpHeader {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "x-forwarded-for" => Some(ClientIpHeader::ForwardedFor),
+            "x-real-ip" => Some(ClientIpHeader::RealIp),
+            "cf-connecting-ip" => Some(ClientIpHeader::CfConnectingIp),
+            "none" | "" => Some(ClientIpHeader::None),
+            _ => None,
+        }
+    }
+}
+
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+    header: ClientIpHeader,
+}
+
+//Older releases always read Cf-Connecting-Ip, upgrades keep resolving the same addresses
+pub const DEFAULT_CLIENT_IP_HEADER: &str = "cf-connecting-ip";
+
+impl TrustedProxies {
+    pub fn from_config(config: &RunConfiguration) -> Self {
+        let header = config
+            .client_ip_header
+            .as_deref()
+            .filter(|header| !header.is_empty())
+            .unwrap_or_else(|| {
+                warn!(
+                    "CLIENT_IP_HEADER is not set, reading {} from trusted proxies",
+                    DEFAULT_CLIENT_IP_HEADER
+                );
+                DEFAULT_CLIENT_IP_HEADER
+            });
+
+        Self::new(&config.trusted_proxies, header)
+    }
+
+    //Comma separated networks, plain addresses are accepted as single host networks
+    pub fn new(list: &str, header: &str) -> Self {
+        let mut networks = Vec::new();
+
+        for entry in list.split(',').map(|entry| entry.trim()) {
+            if entry.is_empty() {
+                continue;
+            }
+
+            let network = entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+
+            match network {
+                Ok(network) => networks.push(network),
+                Err(_) => warn!("Invalid trusted proxy: {}", entry),
+            }
+        }
+
+        let header = ClientIpHeader::parse(header).unwrap_or_else(|| {
+            warn!(
+                "Unknown client ip header: {}, ignoring forwarded addresses",
+                header
+            );
+            ClientIpHeader::None
+        });
+
+        Self { networks, header }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    //Configured header is only honored when request came through a trusted proxy
+    pub fn resolve(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        let peer = peer?;
+
+        if !self.contains(&peer) {
+            return Some(peer);
+        }
+
+        let forwarded = match self.header {
+            ClientIpHeader::ForwardedFor => self.walk_forwarded_for(headers),
+            ClientIpHeader::RealIp => header_ip(headers, "X-Real-Ip"),
+            ClientIpHeader::CfConnectingIp => header_ip(headers, "Cf-Connecting-Ip"),
+            ClientIpHeader::None => None,
+        };
+
+        Some(forwarded.unwrap_or(peer))
+    }
+
+    //Every proxy appends the address it got the request from, so entries are walked from
+    //the right and the first address that isn't one of our proxies is the client.
+    //Anything left of it was sent by the client and can't be trusted.
+    fn walk_forwarded_for(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let entries: Vec<&str> = headers
+            .get_all("X-Forwarded-For")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+
+        let mut client = None;
+
+        for entry in entries.into_iter().rev() {
+            //Malformed hop can't be vouched for, stopping at last address we know
+            let hop = match entry.trim().parse::<IpAddr>() {
+                Ok(hop) => hop,
+                Err(_) => break,
+            };
+
+            client = Some(hop);
+
+            if !self.contains(&hop) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+fn header_ip(headers: &HeaderMap, name: &str) -> Option<IpAddr> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+//Local requests are treated as unknown
+pub fn get_client_ip(ctx: &Context, parts: &Parts) -> Option<String> {
+    let peer = parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+
+    ctx.proxies
+        .resolve(&parts.headers, peer)
+        .filter(|ip| !ip.is_loopback())
+        .map(|ip| ip.to_string())
+}
+
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = parts.extensions.get::<Arc<Context>>().cloned();
+
+        Ok(ClientIp(ctx.and_then(|ctx| get_client_ip(&ctx, parts))))
+    }
+}
+
+const GEOIP_LRU_SIZE: usize = 4096;
+const GEOIP_CACHE_TTL: u64 = 60 * 60 * 24;
+//Addresses no resolver knows about are retried sooner
+const GEOIP_MISS_TTL: u64 = 60 * 60;
+
+#[async_trait]
+pub trait GeoIpResolver: Send + Sync {
+    fn name(&self) -> &'static str;
+    //Ok(None) means resolver works but doesn't know this address
+    async fn resolve(&self, ip: IpAddr) -> Result<Option<IpApiResponse>, String>;
+}
+
+pub struct MaxMindResolver {
+    reader: Reader<Vec<u8>>,
+}
+
+impl MaxMindResolver {
+    pub fn open(path: &str) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            reader: Reader::open_readfile(path)?,
+        })
+    }
+}
+
+#[async_trait]
+impl GeoIpResolver for MaxMindResolver {
+    fn name(&self) -> &'static str {
+        "mmdb"
+    }
+
+    async fn resolve(&self, ip: IpAddr) -> Result<Option<IpApiResponse>, String> {
+        let city = self.reader.lookup::<geoip2::City>(ip);
+
+        if let Err(MaxMindDBError::AddressNotFoundError(_)) = city {
+            return Ok(None);
+        }
+
+        let city = city.map_err(|error| error.to_string())?;
+
+        let code = city
+            .country
+            .and_then(|country| country.iso_code)
+            .map(|code| code.to_string());
+
+        if code.is_none() {
+            return Ok(None);
+        }
+
+        let location = city.location;
+
+        Ok(Some(IpApiResponse {
+            code: code.unwrap(),
+            lat: location
+                .as_ref()
+                .and_then(|location| location.latitude)
+                .unwrap_or(0.0) as f32,
+            lon: location
+                .as_ref()
+                .and_then(|location| location.longitude)
+                .unwrap_or(0.0) as f32,
+        }))
+    }
+}
+
+#[derive(Default)]
+pub struct IpApiResolver {
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct IpApiStatus {
+    status: String,
+    message: Option<String>,
+}
+
+#[async_trait]
+impl GeoIpResolver for IpApiResolver {
+    fn name(&self) -> &'static str {
+        "ip-api"
+    }
+
+    async fn resolve(&self, ip: IpAddr) -> Result<Option<IpApiResponse>, String> {
+        let body = self
+            .client
+            .get(format!(
+                "http://ip-api.com/json/{}?fields=status,message,countryCode,lat,lon",
+                ip
+            ))
+            .send()
+            .await
+            .map_err(|error| error.to_string())?
+            .error_for_status()
+            .map_err(|error| error.to_string())?
+            .bytes()
+            .await
+            .map_err(|error| error.to_string())?;
+
+        let status: IpApiStatus =
+            serde_json::from_slice(&body).map_err(|error| error.to_string())?;
+
+        if status.status != "success" {
+            //Private and reserved ranges are not an outage
+            return match status.message.as_deref() {
+                Some("private range") | Some("reserved range") => Ok(None),
+                message => Err(message.unwrap_or("unknown error").to_string()),
+            };
+        }
+
+        serde_json::from_slice::<IpApiResponse>(&body)
+            .map(Some)
+            .map_err(|error| error.to_string())
+    }
+}
+
+//Resolvers are asked in configured order until one of them answers,
+//answers are kept in memory and shared between components through redis
+pub struct GeoIpService {
+    resolvers: Vec<Box<dyn GeoIpResolver>>,
+    timeout: Duration,
+    //Only found addresses, misses are remembered by redis with shorter ttl
+    cache: Mutex<LruCache<IpAddr, IpApiResponse>>,
+    redis: ConnectionManager,
+}
+
+impl GeoIpService {
+    pub fn new(config: &RunConfiguration, redis: ConnectionManager) -> Self {
+        let mut resolvers: Vec<Box<dyn GeoIpResolver>> = Vec::new();
+
+        for name in config.geoip_resolvers.split(',').map(|name| name.trim()) {
+            match name {
+                "mmdb" => match config.geoip_database_path.as_deref() {
+                    Some(path) => match MaxMindResolver::open(path) {
+                        Ok(resolver) => resolvers.push(Box::new(resolver)),
+                        Err(error) => error!("Failed to open geoip database {}: {}", path, error),
+                    },
+                    None => warn!("mmdb resolver is enabled, but GEOIP_DATABASE_PATH is not set"),
+                },
+                "ip-api" => resolvers.push(Box::new(IpApiResolver::default())),
+                "" => {}
+                name => warn!("Unknown geoip resolver: {}", name),
+            }
+        }
+
+        info!(
+            "GeoIP resolvers: {}",
+            resolvers
+                .iter()
+                .map(|resolver| resolver.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        Self {
+            resolvers,
+            timeout: Duration::from_millis(config.geoip_timeout_ms),
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(GEOIP_LRU_SIZE).unwrap())),
+            redis,
+        }
+    }
+
+    async fn store(&self, key: &str, result: Option<&IpApiResponse>, ttl: u64) {
+        let encoded = serde_json::to_string(&result).unwrap_or_default();
+        let stored: Result<(), redis::RedisError> =
+            self.redis.clone().set_ex(key, encoded, ttl).await;
+
+        if let Err(error) = stored {
+            warn!("Failed to store geoip cache: {}", error);
+        }
     }
 
-    Some(resp.unwrap())
+    pub async fn lookup(&self, ip: Option<String>) -> Option<IpApiResponse> {
+        #[cfg(debug_assertions)]
+        if ip.is_none() {
+            return Some(IpApiResponse {
+                code: "DE".to_string(),
+                lat: 0.0,
+                lon: 0.0,
+            });
+        }
+
+        let ip = ip?.parse::<IpAddr>();
+
+        if let Err(error) = ip {
+            warn!("Invalid ip address for geoip lookup: {}", error);
+            return None;
+        }
+
+        let ip = ip.unwrap();
+
+        let cached = self.cache.lock().unwrap().get(&ip).cloned();
+
+        if cached.is_some() {
+            return cached;
+        }
+
+        let key = format!("geoip:{}", ip);
+        let mut redis = self.redis.clone();
+
+        let cached: Result<Option<String>, redis::RedisError> = redis.get(&key).await;
+
+        match cached {
+            Ok(Some(cached)) => {
+                let cached = serde_json::from_str::<Option<IpApiResponse>>(&cached).ok()?;
+
+                if let Some(ref cached) = cached {
+                    self.cache.lock().unwrap().put(ip, cached.clone());
+                }
+
+                return cached;
+            }
+            Ok(None) => {}
+            Err(error) => warn!("Failed to read geoip cache: {}", error),
+        }
+
+        let mut is_unknown = false;
+
+        for resolver in &self.resolvers {
+            let result = tokio::time::timeout(self.timeout, resolver.resolve(ip)).await;
+
+            match result {
+                Err(_) => warn!("GeoIP resolver {} timed out for {}", resolver.name(), ip),
+                Ok(Err(error)) => {
+                    warn!(
+                        "GeoIP resolver {} failed for {}: {}",
+                        resolver.name(),
+                        ip,
+                        error
+                    )
+                }
+                //Other resolvers may still know about this address
+                Ok(Ok(None)) => is_unknown = true,
+                Ok(Ok(Some(result))) => {
+                    self.store(&key, Some(&result), GEOIP_CACHE_TTL).await;
+                    self.cache.lock().unwrap().put(ip, result.clone());
+
+                    return Some(result);
+                }
+            }
+        }
+
+        if is_unknown {
+            self.store(&key, None, GEOIP_MISS_TTL).await;
+            return None;
+        }
+
+        //Every resolver failed, nothing is cached so next login tries again
+        error!("Error looking up for ip: {}", ip);
+        None
+    }
 }
 
 pub fn _code_from_string(country_code: String) -> u8 {
@@ -287,3 +690,112 @@ pub fn _code_from_string(country_code: String) -> u8 {
 
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use crate::testing::test_config;
+
+    use super::*;
+
+    const PRIVATE: &str = "127.0.0.1/32,10.0.0.0/8,192.168.0.0/16";
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in entries {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+
+        headers
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        value.parse().ok()
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peers() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        let headers = headers(&[("X-Forwarded-For", "1.1.1.1")]);
+
+        assert_eq!(
+            proxies.resolve(&headers, ip("203.0.113.9")),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn walks_forwarded_for_from_the_right() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        //Client sent its own X-Forwarded-For, our proxies appended to a second header
+        let headers = headers(&[
+            ("X-Forwarded-For", "1.1.1.1"),
+            ("X-Forwarded-For", "203.0.113.9, 10.0.0.7"),
+        ]);
+
+        assert_eq!(proxies.resolve(&headers, ip("10.0.0.1")), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn internal_client_behind_proxies() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        let headers = headers(&[("X-Forwarded-For", "192.168.1.20, 10.0.0.7")]);
+
+        assert_eq!(
+            proxies.resolve(&headers, ip("10.0.0.1")),
+            ip("192.168.1.20")
+        );
+    }
+
+    #[test]
+    fn spoofed_private_address_is_not_trusted_hop() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        let headers = headers(&[("X-Forwarded-For", "10.9.9.9, 203.0.113.9")]);
+
+        assert_eq!(proxies.resolve(&headers, ip("10.0.0.1")), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn stops_at_malformed_hop() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        let headers = headers(&[("X-Forwarded-For", "1.1.1.1, unknown, 10.0.0.7")]);
+
+        assert_eq!(proxies.resolve(&headers, ip("10.0.0.1")), ip("10.0.0.7"));
+    }
+
+    #[test]
+    fn only_configured_header_is_read() {
+        let proxies = TrustedProxies::new(PRIVATE, "x-forwarded-for");
+        let spoofed = headers(&[("Cf-Connecting-Ip", "1.1.1.1"), ("X-Real-Ip", "1.1.1.1")]);
+
+        assert_eq!(proxies.resolve(&spoofed, ip("10.0.0.1")), ip("10.0.0.1"));
+
+        let cloudflare = TrustedProxies::new("173.245.48.0/20", "cf-connecting-ip");
+        assert_eq!(
+            cloudflare.resolve(&spoofed, ip("173.245.48.1")),
+            ip("1.1.1.1")
+        );
+        assert_eq!(cloudflare.resolve(&spoofed, ip("10.0.0.1")), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn unknown_header_disables_forwarding() {
+        let proxies = TrustedProxies::new(PRIVATE, "forwarded");
+        let headers = headers(&[("X-Forwarded-For", "203.0.113.9")]);
+
+        assert_eq!(proxies.resolve(&headers, ip("10.0.0.1")), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn unset_header_reads_cloudflare_like_older_releases() {
+        let proxies = TrustedProxies::from_config(&test_config("redis://127.0.0.1:1"));
+        let headers = headers(&[
+            ("Cf-Connecting-Ip", "203.0.113.9"),
+            ("X-Forwarded-For", "1.1.1.1"),
+        ]);
+
+        assert_eq!(proxies.resolve(&headers, ip("10.0.0.1")), ip("203.0.113.9"));
+    }
+}
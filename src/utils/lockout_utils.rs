@@ -0,0 +1,386 @@
+use redis::{aio::ConnectionManager, AsyncCommands};
+use tracing::{error, warn};
+use webhook::client::WebhookClient;
+
+use crate::context::Context;
+
+use super::{
+    score_utils::OsuServerError,
+    user_utils::{redis_error, to_safe},
+};
+
+//Failed attempts are forgotten an hour after the first one
+const ATTEMPT_WINDOW: i64 = 60 * 60;
+const ACCOUNT_ATTEMPT_LIMIT: i64 = 5;
+const IP_ATTEMPT_LIMIT: i64 = 20;
+//Failures from every ip together are only reported, every time this many more pile up
+const USER_ALERT_LIMIT: i64 = 20;
+//Every attempt past the limit doubles lockout, capped at a day
+const BASE_LOCKOUT: i64 = 60;
+const MAX_LOCKOUT: i64 = 60 * 60 * 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockoutTarget {
+    //Username from a single ip, so failures elsewhere can't lock the owner out
+    Account,
+    Ip,
+    //Username from every ip, never locks
+    User,
+}
+
+impl LockoutTarget {
+    fn name(&self) -> &'static str {
+        match self {
+            LockoutTarget::Account => "account",
+            LockoutTarget::Ip => "ip",
+            LockoutTarget::User => "user",
+        }
+    }
+
+    fn limit(&self) -> i64 {
+        match self {
+            LockoutTarget::Account => ACCOUNT_ATTEMPT_LIMIT,
+            LockoutTarget::Ip => IP_ATTEMPT_LIMIT,
+            LockoutTarget::User => USER_ALERT_LIMIT,
+        }
+    }
+
+    fn locks(&self) -> bool {
+        *self != LockoutTarget::User
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginCheck {
+    Ok,
+    InvalidCredentials,
+    //Seconds until lockout expires
+    LockedOut(i64),
+}
+
+fn attempts_key(target: LockoutTarget, value: &str) -> String {
+    format!("login:{}:{}:attempts", target.name(), value)
+}
+
+fn lockout_key(target: LockoutTarget, value: &str) -> String {
+    format!("login:{}:{}:lockout", target.name(), value)
+}
+
+//Account subjects of a user, so staff can clear all of them without knowing the ips
+fn account_index_key(username: &str) -> String {
+    format!("login:account:{}:index", to_safe(username))
+}
+
+fn account_subject(username: &str, ip: Option<&str>) -> String {
+    format!("{}@{}", to_safe(username), ip.unwrap_or("unknown"))
+}
+
+fn subjects(username: &str, ip: Option<&str>) -> Vec<(LockoutTarget, String)> {
+    let mut subjects = vec![
+        (LockoutTarget::Account, account_subject(username, ip)),
+        (LockoutTarget::User, to_safe(username)),
+    ];
+
+    if let Some(ip) = ip {
+        subjects.push((LockoutTarget::Ip, ip.to_string()));
+    }
+
+    subjects
+}
+
+pub fn lockout_duration(attempts: i64, limit: i64) -> Option<i64> {
+    if attempts < limit {
+        return None;
+    }
+
+    let exponent = (attempts - limit).min(16) as u32;
+
+    Some((BASE_LOCKOUT * 2_i64.pow(exponent)).min(MAX_LOCKOUT))
+}
+
+//Lockouts are best effort, if redis is unavailable logins are not blocked
+pub async fn get_lockout(
+    redis: &ConnectionManager,
+    username: &str,
+    ip: Option<&str>,
+) -> Option<i64> {
+    let mut connection = redis.clone();
+    let mut remaining = 0;
+
+    for (target, value) in subjects(username, ip) {
+        if !target.locks() {
+            continue;
+        }
+
+        let ttl: Result<i64, redis::RedisError> = connection.ttl(lockout_key(target, &value)).await;
+
+        match ttl {
+            Ok(ttl) => remaining = remaining.max(ttl),
+            Err(error) => warn!("Failed to read lockout: {}", error),
+        }
+    }
+
+    (remaining > 0).then_some(remaining)
+}
+
+pub async fn register_failed_login(ctx: &Context, username: &str, ip: Option<&str>, source: &str) {
+    let mut connection = ctx.redis.clone();
+
+    for (target, value) in subjects(username, ip) {
+        let key = attempts_key(target, &value);
+        let attempts: Result<i64, redis::RedisError> = connection.incr(&key, 1).await;
+
+        if let Err(error) = attempts {
+            warn!("Failed to count login attempt: {}", error);
+            continue;
+        }
+
+        let attempts = attempts.unwrap();
+
+        if attempts == 1 {
+            let _: Result<bool, redis::RedisError> = connection.expire(&key, ATTEMPT_WINDOW).await;
+        }
+
+        if target == LockoutTarget::Account {
+            let index = account_index_key(username);
+            let _: Result<i32, redis::RedisError> = connection.sadd(&index, &value).await;
+            let _: Result<bool, redis::RedisError> = connection.expire(&index, MAX_LOCKOUT).await;
+        }
+
+        if !target.locks() {
+            if attempts % target.limit() == 0 {
+                lockout_alert(ctx, target, &value, attempts, None, source).await;
+            }
+
+            continue;
+        }
+
+        if let Some(duration) = lockout_duration(attempts, target.limit()) {
+            let result: Result<(), redis::RedisError> = connection
+                .set_ex(lockout_key(target, &value), attempts, duration as u64)
+                .await;
+
+            if let Err(error) = result {
+                warn!("Failed to apply lockout: {}", error);
+                continue;
+            }
+
+            //Keeping counter alive while lockouts are still escalating
+            let _: Result<bool, redis::RedisError> =
+                connection.expire(&key, ATTEMPT_WINDOW.max(duration)).await;
+
+            lockout_alert(ctx, target, &value, attempts, Some(duration), source).await;
+        }
+    }
+}
+
+//Successful login only forgives failures of the same ip
+pub async fn reset_failed_logins(redis: &ConnectionManager, username: &str, ip: Option<&str>) {
+    let result: Result<i32, redis::RedisError> = redis
+        .clone()
+        .del(attempts_key(
+            LockoutTarget::Account,
+            &account_subject(username, ip),
+        ))
+        .await;
+
+    if let Err(error) = result {
+        warn!("Failed to reset login attempts: {}", error);
+    }
+}
+
+//Clearing a user lifts lockouts of every ip that tried to log in as them
+pub async fn clear_lockout(
+    redis: &ConnectionManager,
+    target: LockoutTarget,
+    value: &str,
+) -> Result<bool, OsuServerError> {
+    let mut connection = redis.clone();
+
+    let keys = match target {
+        LockoutTarget::Ip => vec![attempts_key(target, value), lockout_key(target, value)],
+        LockoutTarget::Account | LockoutTarget::User => {
+            let index = account_index_key(value);
+            let accounts: Vec<String> = connection.smembers(&index).await.map_err(redis_error)?;
+
+            let mut keys = vec![index, attempts_key(LockoutTarget::User, &to_safe(value))];
+
+            for account in accounts {
+                keys.push(attempts_key(LockoutTarget::Account, &account));
+                keys.push(lockout_key(LockoutTarget::Account, &account));
+            }
+
+            keys
+        }
+    };
+
+    let removed: i32 = connection.del(&keys).await.map_err(redis_error)?;
+
+    Ok(removed > 0)
+}
+
+//Wrong guesses of a single issued secret, like a verification code. Counter lives as long
+//as the secret does, caller invalidates the secret once limit is reached
+pub async fn register_failed_attempt(
+    redis: &ConnectionManager,
+    scope: &str,
+    subject: &str,
+    window: i64,
+) -> Result<i64, OsuServerError> {
+    let mut connection = redis.clone();
+    let key = format!("attempts:{}:{}", scope, subject);
+
+    let attempts: i64 = connection.incr(&key, 1).await.map_err(redis_error)?;
+
+    if attempts == 1 {
+        let _: bool = connection.expire(&key, window).await.map_err(redis_error)?;
+    }
+
+    Ok(attempts)
+}
+
+pub async fn clear_failed_attempts(redis: &ConnectionManager, scope: &str, subject: &str) {
+    let result: Result<i32, redis::RedisError> = redis
+        .clone()
+        .del(format!("attempts:{}:{}", scope, subject))
+        .await;
+
+    if let Err(error) = result {
+        warn!("Failed to clear {} attempts: {}", scope, error);
+    }
+}
+
+pub async fn check_login(
+    ctx: &Context,
+    username: &str,
+    password: &str,
+    ip: Option<&str>,
+    source: &str,
+) -> LoginCheck {
+    let check = check_credentials(ctx, username, password, ip, source).await;
+
+    if check == LoginCheck::Ok {
+        reset_failed_logins(&ctx.redis, username, ip).await;
+    }
+
+    check
+}
+
+//Failures are kept on success, logins with a second factor reset them only after it passes
+pub async fn check_credentials(
+    ctx: &Context,
+    username: &str,
+    password: &str,
+    ip: Option<&str>,
+    source: &str,
+) -> LoginCheck {
+    if let Some(remaining) = get_lockout(&ctx.redis, username, ip).await {
+        return LoginCheck::LockedOut(remaining);
+    }
+
+    if !ctx.users.validate_auth(username, password).await {
+        register_failed_login(ctx, username, ip, source).await;
+        return LoginCheck::InvalidCredentials;
+    }
+
+    LoginCheck::Ok
+}
+
+async fn lockout_alert(
+    ctx: &Context,
+    target: LockoutTarget,
+    value: &str,
+    attempts: i64,
+    duration: Option<i64>,
+    source: &str,
+) {
+    if let Some(webhook) = ctx.config.alert_discord_webhook.clone() {
+        let client = WebhookClient::new(webhook.as_str());
+
+        let (content, locked_for) = match duration {
+            Some(duration) => ("Login lockout applied!", format!("{}s", duration)),
+            None => ("Repeated failed logins!", "not locked".to_string()),
+        };
+
+        let formatted_report = format!(
+            r#"
+Target: `{} {}`
+Failed attempts: `{}`
+Locked for: `{}`
+Source: `{}`
+"#,
+            target.name(),
+            value,
+            attempts,
+            locked_for,
+            source
+        );
+
+        if let Err(error) = client
+            .send(|message| {
+                message
+                    .content(content)
+                    .embed(|embed| embed.description(formatted_report.as_str()))
+            })
+            .await
+        {
+            error!("Failed to send lockout alert: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_starts_at_limit_and_doubles() {
+        assert_eq!(lockout_duration(4, ACCOUNT_ATTEMPT_LIMIT), None);
+        assert_eq!(lockout_duration(5, ACCOUNT_ATTEMPT_LIMIT), Some(60));
+        assert_eq!(lockout_duration(6, ACCOUNT_ATTEMPT_LIMIT), Some(120));
+        assert_eq!(lockout_duration(8, ACCOUNT_ATTEMPT_LIMIT), Some(480));
+    }
+
+    #[test]
+    fn lockout_is_capped_at_a_day() {
+        assert_eq!(
+            lockout_duration(40, ACCOUNT_ATTEMPT_LIMIT),
+            Some(MAX_LOCKOUT)
+        );
+        assert_eq!(lockout_duration(1000, IP_ATTEMPT_LIMIT), Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn account_lockout_is_scoped_to_ip() {
+        let attacker = subjects("Some Player", Some("203.0.113.7"));
+        let owner = subjects("some_player", Some("198.51.100.1"));
+
+        let locking = |subjects: &[(LockoutTarget, String)]| {
+            subjects
+                .iter()
+                .filter(|(target, _)| target.locks())
+                .map(|(target, value)| lockout_key(*target, value))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            locking(&attacker),
+            vec![
+                "login:account:some_player@203.0.113.7:lockout",
+                "login:ip:203.0.113.7:lockout"
+            ]
+        );
+        assert!(locking(&owner)
+            .iter()
+            .all(|key| !locking(&attacker).contains(key)));
+    }
+
+    #[test]
+    fn user_wide_counter_never_locks() {
+        let subjects = subjects("player", None);
+
+        assert!(subjects.contains(&(LockoutTarget::User, "player".to_string())));
+        assert!(subjects.contains(&(LockoutTarget::Account, "player@unknown".to_string())));
+        assert!(!LockoutTarget::User.locks());
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use tracing::info;
+
+use crate::config::RunConfiguration;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, mail: Mail) -> Result<(), String>;
+}
+
+//Used when SMTP_HOST is not set, mails are dropped. Body may carry reset links, so
+//only recipient and subject are logged
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn send(&self, mail: Mail) -> Result<(), String> {
+        info!("Mail to {} not sent: {}", mail.to, mail.subject);
+
+        Ok(())
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &RunConfiguration, host: &str) -> Result<Self, String> {
+        let mut builder = match config.smtp_security.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            //Plain connection is meant for local stand-ins like mailpit
+            "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+            security => return Err(format!("Unknown smtp security: {}", security)),
+        }
+        .map_err(|error| error.to_string())?;
+
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = config
+            .smtp_from
+            .clone()
+            .unwrap_or(format!("noreply@{}", config.server_url))
+            .parse::<Mailbox>()
+            .map_err(|error| error.to_string())?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, mail: Mail) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail
+                .to
+                .parse::<Mailbox>()
+                .map_err(|error| error.to_string())?)
+            .subject(mail.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(mail.body)
+            .map_err(|error| error.to_string())?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_response| ())
+            .map_err(|error| error.to_string())
+    }
+}
+
+//SMTP which is configured but can't be set up fails startup, reset links must not go missing
+pub fn create_mailer(config: &RunConfiguration) -> Arc<dyn Mailer> {
+    match config.smtp_host.as_deref().filter(|host| !host.is_empty()) {
+        Some(host) => match SmtpMailer::new(config, host) {
+            Ok(mailer) => Arc::new(mailer),
+            Err(error) => panic!("Failed to set up smtp mailer: {}", error),
+        },
+        None => Arc::new(LogMailer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::oneshot,
+    };
+
+    use crate::testing::test_config;
+
+    use super::*;
+
+    //Speaks just enough SMTP to accept a single mail, sends back everything after DATA
+    async fn smtp_server() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+            let mut sender = Some(sender);
+            let mut data: Option<String> = None;
+            let mut line = String::new();
+
+            write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+            while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+                let reply = match data.as_mut() {
+                    Some(_) if line == ".\r\n" => {
+                        if let Some(sender) = sender.take() {
+                            let _ = sender.send(data.take().unwrap());
+                        }
+                        Some("250 queued")
+                    }
+                    Some(data) => {
+                        data.push_str(&line);
+                        None
+                    }
+                    None => {
+                        let command = line.to_ascii_uppercase();
+
+                        if command.starts_with("EHLO") {
+                            Some("250 localhost")
+                        } else if command.starts_with("DATA") {
+                            data = Some(String::new());
+                            Some("354 end with .")
+                        } else if command.starts_with("QUIT") {
+                            Some("221 bye")
+                        } else {
+                            Some("250 ok")
+                        }
+                    }
+                };
+
+                if let Some(reply) = reply {
+                    write
+                        .write_all(format!("{}\r\n", reply).as_bytes())
+                        .await
+                        .unwrap();
+                }
+
+                line.clear();
+            }
+        });
+
+        (port, receiver)
+    }
+
+    fn smtp_config(port: u16, security: &str) -> RunConfiguration {
+        let mut config = test_config("redis://127.0.0.1:1");
+        config.smtp_host = Some("127.0.0.1".to_string());
+        config.smtp_port = Some(port);
+        config.smtp_security = security.to_string();
+
+        config
+    }
+
+    #[tokio::test]
+    async fn smtp_mailer_delivers_mail() {
+        let (port, received) = smtp_server().await;
+        let mailer = create_mailer(&smtp_config(port, "none"));
+
+        assert_eq!(mailer.name(), "smtp");
+
+        mailer
+            .send(Mail {
+                to: "player@example.com".to_string(),
+                subject: "Password reset".to_string(),
+                body: "Reset link".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let data = received.await.unwrap();
+        assert!(data.contains("From: noreply@localhost"));
+        assert!(data.contains("To: player@example.com"));
+        assert!(data.contains("Subject: Password reset"));
+        assert!(data.contains("Reset link"));
+    }
+
+    #[test]
+    fn mails_are_only_logged_without_smtp_host() {
+        let mut config = test_config("redis://127.0.0.1:1");
+        assert_eq!(create_mailer(&config).name(), "log");
+
+        config.smtp_host = Some(String::new());
+        assert_eq!(create_mailer(&config).name(), "log");
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to set up smtp mailer")]
+    fn broken_smtp_setup_fails_startup() {
+        create_mailer(&smtp_config(25, "ssl"));
+    }
+}
@@ -4,16 +4,36 @@ use sqlx::prelude::FromRow;
 
 use crate::db::user::User;
 
+pub mod activity_utils;
+pub mod audit_utils;
+pub mod badge_utils;
 pub mod beatmap_utils;
 pub mod channel_utils;
 pub mod chart;
 pub mod general_utils;
+pub mod group_utils;
 pub mod http_utils;
+pub mod hwid_utils;
 pub mod ip_utils;
+pub mod lockout_utils;
+pub mod mail_utils;
+pub mod notification_utils;
 pub mod oauth_utils;
+pub mod passkey_utils;
+pub mod password_utils;
+pub mod payment_utils;
 pub mod performance_utils;
+pub mod permission_utils;
+pub mod preference_utils;
+pub mod registration_utils;
+pub mod report_utils;
 pub mod score_utils;
+pub mod session_utils;
+pub mod shop_utils;
+pub mod two_factor_utils;
 pub mod user_utils;
+pub mod username_utils;
+pub mod verification_utils;
 
 //ranked, total, accuracy, playcount, rank, pp
 #[derive(FromRow, Debug, Clone, Serialize)]
@@ -49,7 +69,7 @@ pub struct Badge {
     pub color: String,
 }
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct DatabaseHwid {
     pub id: i32,
     #[sqlx(rename = "userId")]
@@ -67,7 +87,7 @@ pub struct UserHwid {
     pub user: User,
 }
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct Punishment {
     pub id: String,
     pub date: NaiveDateTime,
@@ -0,0 +1,184 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{score_utils::OsuServerError, user_utils::send_bancho_message};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    NewFollower,
+    FirstPlaceLost,
+    BeatmapStatus,
+    PunishmentApplied,
+    PunishmentLifted,
+    ReportResolved,
+    Supporter,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::NewFollower => "new_follower",
+            NotificationKind::FirstPlaceLost => "first_place_lost",
+            NotificationKind::BeatmapStatus => "beatmap_status",
+            NotificationKind::PunishmentApplied => "punishment_applied",
+            NotificationKind::PunishmentLifted => "punishment_lifted",
+            NotificationKind::ReportResolved => "report_resolved",
+            NotificationKind::Supporter => "supporter",
+        }
+    }
+
+    //Font Awesome icon names rendered by the website
+    fn icon(&self) -> &'static str {
+        match self {
+            NotificationKind::NewFollower => "faUserPlus",
+            NotificationKind::FirstPlaceLost => "faTrophy",
+            NotificationKind::BeatmapStatus => "faCheck",
+            NotificationKind::PunishmentApplied => "faGavel",
+            NotificationKind::PunishmentLifted => "faUnlock",
+            NotificationKind::ReportResolved => "faFlag",
+            NotificationKind::Supporter => "faHeart",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserNotification {
+    pub id: String,
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    pub icon: Option<String>,
+    #[sqlx(rename = "isIconAvatar")]
+    pub is_icon_avatar: bool,
+    //Kind and subject of notification, e.g. new_follower:1000
+    #[sqlx(rename = "actionId")]
+    pub action_id: String,
+    pub text: String,
+    pub seen: bool,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    //Id of whatever notification points to (user, beatmap, punishment)
+    pub subject: String,
+    pub text: String,
+    //Shows avatar of this user instead of kind icon
+    pub avatar_of: Option<i32>,
+}
+
+pub async fn insert_notification(
+    connection: &Pool<Postgres>,
+    notification: &NewNotification,
+) -> Result<UserNotification, OsuServerError> {
+    let icon = match notification.avatar_of {
+        Some(user_id) => user_id.to_string(),
+        None => notification.kind.icon().to_string(),
+    };
+
+    sqlx::query_as(
+        r#"
+INSERT INTO "Notifications" (id, "userId", icon, "isIconAvatar", "actionId", text)
+VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING *
+"#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(notification.user_id)
+    .bind(icon)
+    .bind(notification.avatar_of.is_some())
+    .bind(format!(
+        "{}:{}",
+        notification.kind.as_str(),
+        notification.subject
+    ))
+    .bind(&notification.text)
+    .fetch_one(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to insert notification: {}", error)))
+}
+
+//Stores notification and mirrors it in game, bancho drops it when user is offline
+pub async fn notify(connection: &Pool<Postgres>, notification: NewNotification) {
+    if let Err(error) = insert_notification(connection, &notification).await {
+        error!("{:#?}", error);
+        return;
+    }
+
+    send_bancho_message(
+        &notification.user_id,
+        "user:notification".to_string(),
+        Some(vec![notification.text]),
+    )
+    .await;
+}
+
+pub async fn get_notifications(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    unseen_only: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<UserNotification>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    *
+FROM
+    "Notifications"
+WHERE
+    "userId" = $1 AND
+    (NOT $2 OR NOT seen)
+ORDER BY
+    "createdAt" DESC
+OFFSET $3
+LIMIT $4
+"#,
+    )
+    .bind(user_id)
+    .bind(unseen_only)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch notifications: {}", error)))
+}
+
+pub async fn count_unseen_notifications(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<i64, OsuServerError> {
+    sqlx::query_scalar(r#"SELECT COUNT(*) FROM "Notifications" WHERE "userId" = $1 AND NOT seen"#)
+        .bind(user_id)
+        .fetch_one(connection)
+        .await
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to count notifications: {}", error))
+        })
+}
+
+//Marks every notification of user when no ids are given
+pub async fn mark_notifications_seen(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    ids: Option<Vec<String>>,
+) -> Result<u64, OsuServerError> {
+    sqlx::query(
+        r#"
+UPDATE "Notifications"
+SET seen = true
+WHERE "userId" = $1 AND NOT seen AND ($2::text[] IS NULL OR id = ANY($2))
+"#,
+    )
+    .bind(user_id)
+    .bind(ids)
+    .execute(connection)
+    .await
+    .map(|result| result.rows_affected())
+    .map_err(|error| OsuServerError::Internal(format!("Failed to update notifications: {}", error)))
+}
@@ -1,6 +1,42 @@
+use axum::http::Method;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::info;
+use url::Url;
 
-use super::score_utils::OsuServerError;
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    general_utils::random_string,
+    score_utils::OsuServerError,
+    user_utils::{get_user_by_id, redis_error},
+};
+
+//Codes have to be exchanged right after the redirect
+const AUTHORIZATION_CODE_TTL: u64 = 60;
+const AUTHORIZATION_CODE_LENGTH: usize = 48;
+const DEFAULT_SCOPE: &str = "identify";
+//Scopes applications can ask for and GET routes they open, * matches one path segment.
+//Tokens of first party grants have no scope and aren't limited
+const SCOPE_ROUTES: [(&str, &[&str]); 2] = [
+    ("identify", &["/api/v2/users/@me"]),
+    (
+        "public",
+        &[
+            "/api/v2/users/*",
+            "/api/v2/users/*/best",
+            "/api/v2/users/*/recent",
+            "/api/v2/users/*/graph",
+            "/api/v2/beatmaps/*/leaderboard",
+            "/api/v2/rankings/leaderboard",
+        ],
+    ),
+];
 
 #[derive(Debug, FromRow)]
 pub struct App {
@@ -35,3 +71,397 @@ pub async fn get_app_by_id(
         Ok(app) => Ok(app),
     }
 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorizationRequest {
+    pub client_id: String,
+    pub response_type: String,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthorizationCode {
+    application_id: i32,
+    user_id: i32,
+    //Kept as sent, token request has to repeat the same value
+    redirect_uri: Option<String>,
+    scopes: Vec<String>,
+    //S256 of the verifier, plain method isn't accepted
+    code_challenge: String,
+}
+
+#[derive(Debug)]
+pub enum AuthorizationError {
+    UnknownClient,
+    RedirectMismatch,
+    UnauthorizedClient,
+    UnsupportedResponseType,
+    InvalidScope,
+    InvalidChallenge,
+    Internal(OsuServerError),
+}
+
+impl AuthorizationError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            AuthorizationError::UnknownClient => "Unknown application.",
+            AuthorizationError::RedirectMismatch => "redirect_uri doesn't match the application.",
+            AuthorizationError::UnauthorizedClient => {
+                "Application is not allowed to use authorization_code grant."
+            }
+            AuthorizationError::UnsupportedResponseType => "response_type must be code.",
+            AuthorizationError::InvalidScope => "Unknown scope requested.",
+            AuthorizationError::InvalidChallenge => {
+                "code_challenge is missing or invalid, only S256 method is supported."
+            }
+            AuthorizationError::Internal(_) => "Internal server error.",
+        }
+    }
+}
+
+impl From<OsuServerError> for AuthorizationError {
+    fn from(error: OsuServerError) -> Self {
+        AuthorizationError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("OAuth query failed: {}", error))
+}
+
+fn code_key(code: &str) -> String {
+    format!("oauth:code:{:x}", Sha256::digest(code.as_bytes()))
+}
+
+//RFC 7636, 43 to 128 characters of the unreserved set
+fn is_pkce_value(value: &str) -> bool {
+    (43..=128).contains(&value.len())
+        && value
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-._~".contains(character))
+}
+
+//Method has to be sent explicitly, RFC 7636 would default to plain
+fn is_valid_challenge(challenge: Option<&str>, method: Option<&str>) -> bool {
+    method == Some("S256") && challenge.is_some_and(is_pkce_value)
+}
+
+fn verify_pkce(challenge: &str, verifier: &str) -> bool {
+    is_pkce_value(verifier)
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+pub fn parse_scopes(scope: Option<&str>) -> Vec<String> {
+    let mut scopes = scope
+        .unwrap_or(DEFAULT_SCOPE)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    if scopes.is_empty() {
+        scopes.push(DEFAULT_SCOPE.to_string());
+    }
+
+    scopes
+}
+
+fn matches_route(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.split('/');
+    let path = path.trim_end_matches('/').split('/');
+
+    pattern.clone().count() == path.clone().count()
+        && pattern
+            .zip(path)
+            .all(|(expected, segment)| expected == "*" || expected == segment)
+}
+
+//Scoped tokens are read only, every route has to be listed by one of their scopes
+pub fn scope_allows(scopes: &[String], method: &Method, path: &str) -> bool {
+    if method != Method::GET && method != Method::HEAD {
+        return false;
+    }
+
+    SCOPE_ROUTES
+        .iter()
+        .filter(|(scope, _)| scopes.iter().any(|granted| granted == scope))
+        .flat_map(|(_, routes)| routes.iter())
+        .any(|pattern| matches_route(pattern, path))
+}
+
+//Redirect uri has to match the registered one exactly, prefixes or other paths aren't accepted
+pub async fn validate_authorization(
+    connection: &Pool<Postgres>,
+    request: &AuthorizationRequest,
+) -> Result<App, AuthorizationError> {
+    let app = get_app_by_id(connection, request.client_id.parse::<i32>().unwrap_or(0))
+        .await?
+        .ok_or(AuthorizationError::UnknownClient)?;
+
+    if request
+        .redirect_uri
+        .as_ref()
+        .is_some_and(|redirect_uri| *redirect_uri != app.redirect_uri)
+    {
+        return Err(AuthorizationError::RedirectMismatch);
+    }
+
+    if !app
+        .allowed_grant_type
+        .contains(&"authorization_code".to_string())
+    {
+        return Err(AuthorizationError::UnauthorizedClient);
+    }
+
+    if request.response_type != "code" {
+        return Err(AuthorizationError::UnsupportedResponseType);
+    }
+
+    if !parse_scopes(request.scope.as_deref())
+        .iter()
+        .all(|scope| SCOPE_ROUTES.iter().any(|(known, _)| known == scope))
+    {
+        return Err(AuthorizationError::InvalidScope);
+    }
+
+    if !is_valid_challenge(
+        request.code_challenge.as_deref(),
+        request.code_challenge_method.as_deref(),
+    ) {
+        return Err(AuthorizationError::InvalidChallenge);
+    }
+
+    Ok(app)
+}
+
+//Url the browser is sent back to, state is always passed through
+pub fn build_redirect(
+    app: &App,
+    request: &AuthorizationRequest,
+    params: &[(&str, &str)],
+) -> Result<String, OsuServerError> {
+    let mut url = Url::parse(request.redirect_uri.as_ref().unwrap_or(&app.redirect_uri)).map_err(
+        |error| {
+            OsuServerError::Internal(format!(
+                "Invalid redirect url of application {}: {}",
+                app.id, error
+            ))
+        },
+    )?;
+
+    {
+        let mut query = url.query_pairs_mut();
+
+        for (name, value) in params {
+            query.append_pair(name, value);
+        }
+
+        if let Some(state) = &request.state {
+            query.append_pair("state", state);
+        }
+    }
+
+    Ok(url.to_string())
+}
+
+pub async fn get_consent(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    application_id: i32,
+) -> Result<Option<Vec<String>>, OsuServerError> {
+    sqlx::query_scalar::<_, Option<Vec<String>>>(
+        r#"SELECT scopes FROM "AuthorizedUserApplication" WHERE "userId" = $1 AND "oauthApplicationId" = $2"#,
+    )
+    .bind(user_id)
+    .bind(application_id)
+    .fetch_optional(connection)
+    .await
+    .map(|scopes| scopes.map(Option::unwrap_or_default))
+    .map_err(database_error)
+}
+
+//Stores consent and returns redirect url carrying a single use code
+pub async fn authorize_application(
+    ctx: &Context,
+    user: &User,
+    app: &App,
+    request: &AuthorizationRequest,
+) -> Result<String, AuthorizationError> {
+    let scopes = parse_scopes(request.scope.as_deref());
+    let existing = get_consent(&ctx.pool, user.id, app.id).await?;
+
+    //Scopes approved earlier stay approved
+    sqlx::query(
+        r#"
+INSERT INTO "AuthorizedUserApplication" ("oauthApplicationId", "userId", scopes)
+VALUES ($1, $2, $3)
+ON CONFLICT ("userId", "oauthApplicationId") DO UPDATE
+SET scopes = ARRAY(SELECT DISTINCT unnest(COALESCE("AuthorizedUserApplication".scopes, '{}') || EXCLUDED.scopes)),
+    "updatedAt" = NOW()
+"#,
+    )
+    .bind(app.id)
+    .bind(user.id)
+    .bind(&scopes)
+    .execute(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    let code = random_string(AUTHORIZATION_CODE_LENGTH);
+    let pending = AuthorizationCode {
+        application_id: app.id,
+        user_id: user.id,
+        redirect_uri: request.redirect_uri.clone(),
+        scopes: scopes.clone(),
+        code_challenge: request.code_challenge.clone().unwrap_or_default(),
+    };
+
+    let pending = serde_json::to_string(&pending).map_err(|error| {
+        OsuServerError::Internal(format!("Failed to encode authorization code: {}", error))
+    })?;
+
+    let _: () = ctx
+        .redis
+        .clone()
+        .set_ex(code_key(&code), pending, AUTHORIZATION_CODE_TTL)
+        .await
+        .map_err(redis_error)?;
+
+    if existing.is_none() {
+        info!(
+            "{} ({}) authorized application {}",
+            user.username, user.id, app.id
+        );
+
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: Some(user.id),
+                action: "oauth.authorized",
+                target_id: Some(user.id),
+                after: Some(json!({ "application": app.id, "scopes": scopes })),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    build_redirect(app, request, &[("code", &code)]).map_err(AuthorizationError::from)
+}
+
+//Code is removed on first use, even when the rest of the request is wrong.
+//Returns the user with scopes they approved
+pub async fn redeem_authorization_code(
+    ctx: &Context,
+    code: &str,
+    application_id: i32,
+    redirect_uri: Option<&str>,
+    code_verifier: Option<&str>,
+) -> Result<Option<(User, Vec<String>)>, OsuServerError> {
+    let pending: Option<String> = ctx
+        .redis
+        .clone()
+        .get_del(code_key(code))
+        .await
+        .map_err(redis_error)?;
+
+    let pending =
+        pending.and_then(|pending| serde_json::from_str::<AuthorizationCode>(&pending).ok());
+
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return Ok(None),
+    };
+
+    if pending.application_id != application_id
+        || pending.redirect_uri.as_deref() != redirect_uri
+        || !code_verifier.is_some_and(|verifier| verify_pkce(&pending.code_challenge, verifier))
+    {
+        return Ok(None);
+    }
+
+    Ok(get_user_by_id(&ctx.pool, pending.user_id)
+        .await?
+        .map(|user| (user, pending.scopes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mJ92K9u3hjY9W9Gt1-6oIyLJiYzRzw";
+    //base64url of sha256 of VERIFIER
+    const CHALLENGE: &str = "rEcmEhjkssQNwKEJVe3ONVZmdhebmuKu8d2KqQ2DH0k";
+
+    fn scopes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn verifies_s256_challenge() {
+        assert!(verify_pkce(CHALLENGE, VERIFIER));
+        assert!(!verify_pkce(CHALLENGE, &VERIFIER.replace('d', "e")));
+        //Plain method would accept the challenge itself as verifier
+        assert!(!verify_pkce(CHALLENGE, CHALLENGE));
+        assert!(!verify_pkce(
+            &URL_SAFE_NO_PAD.encode(Sha256::digest(b"short")),
+            "short"
+        ));
+    }
+
+    #[test]
+    fn requires_explicit_s256_method() {
+        assert!(is_valid_challenge(Some(CHALLENGE), Some("S256")));
+        assert!(!is_valid_challenge(Some(CHALLENGE), Some("plain")));
+        assert!(!is_valid_challenge(Some(CHALLENGE), None));
+        assert!(!is_valid_challenge(None, Some("S256")));
+        assert!(!is_valid_challenge(Some("too-short"), Some("S256")));
+    }
+
+    #[test]
+    fn parses_scopes_with_default() {
+        assert_eq!(parse_scopes(None), scopes(&["identify"]));
+        assert_eq!(parse_scopes(Some("  ")), scopes(&["identify"]));
+        assert_eq!(
+            parse_scopes(Some("public identify public")),
+            scopes(&["identify", "public"])
+        );
+    }
+
+    #[test]
+    fn scopes_open_only_listed_read_routes() {
+        let identify = scopes(&["identify"]);
+        let public = scopes(&["public"]);
+
+        assert!(scope_allows(&identify, &Method::GET, "/api/v2/users/@me"));
+        assert!(!scope_allows(&identify, &Method::GET, "/api/v2/users/2"));
+        assert!(!scope_allows(&identify, &Method::POST, "/api/v2/users/@me"));
+
+        assert!(scope_allows(&public, &Method::GET, "/api/v2/users/2/best"));
+        assert!(scope_allows(
+            &public,
+            &Method::GET,
+            "/api/v2/rankings/leaderboard/"
+        ));
+        assert!(!scope_allows(
+            &public,
+            &Method::GET,
+            "/api/v2/users/2/sessions"
+        ));
+        assert!(!scope_allows(&public, &Method::GET, "/api/v2/hwid/flags"));
+        assert!(!scope_allows(&public, &Method::GET, "/api/v2/audit"));
+        assert!(!scope_allows(
+            &public,
+            &Method::POST,
+            "/api/v2/users/2/username"
+        ));
+
+        assert!(!scope_allows(&[], &Method::GET, "/api/v2/users/@me"));
+    }
+}
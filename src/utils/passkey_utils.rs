@@ -0,0 +1,386 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{prelude::FromRow, types::Json, Pool, Postgres};
+use tracing::{info, warn};
+use webauthn_rs::{
+    prelude::{
+        CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration,
+        PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, Url, Uuid,
+    },
+    Webauthn, WebauthnBuilder,
+};
+
+use crate::{config::RunConfiguration, context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    general_utils::random_string,
+    score_utils::OsuServerError,
+    user_utils::{find_user_by_id_or_username, get_user_by_id, redis_error},
+};
+
+//Ceremony has to be finished within this time
+const CEREMONY_TTL: u64 = 60 * 5;
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct PassKeyEntry {
+    pub id: i32,
+    pub name: String,
+    #[sqlx(rename = "authenticatorAttachment")]
+    pub authenticator_attachment: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+    #[sqlx(rename = "lastUsedAt")]
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, FromRow)]
+struct StoredPassKey {
+    id: i32,
+    credential: Json<Passkey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingRegistration {
+    handle: String,
+    challenge: String,
+    state: PasskeyRegistration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingAuthentication {
+    user_id: i32,
+    state: PasskeyAuthentication,
+}
+
+#[derive(Debug)]
+pub enum PasskeyError {
+    UserNotFound,
+    NoPasskeys,
+    CeremonyExpired,
+    Rejected(String),
+    Internal(OsuServerError),
+}
+
+impl PasskeyError {
+    pub fn message(&self) -> String {
+        match self {
+            PasskeyError::UserNotFound | PasskeyError::NoPasskeys => {
+                "No passkeys are registered for this account.".to_string()
+            }
+            PasskeyError::CeremonyExpired => "Passkey request has expired, try again.".to_string(),
+            PasskeyError::Rejected(reason) => format!("Passkey was rejected: {}", reason),
+            PasskeyError::Internal(_) => "Internal server error.".to_string(),
+        }
+    }
+}
+
+impl From<OsuServerError> for PasskeyError {
+    fn from(error: OsuServerError) -> Self {
+        PasskeyError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Passkey query failed: {}", error))
+}
+
+fn state_error(error: serde_json::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Failed to encode passkey state: {}", error))
+}
+
+fn registration_key(user_id: i32) -> String {
+    format!("passkey:{}:registration", user_id)
+}
+
+fn authentication_key(session: &str) -> String {
+    format!("passkey:login:{}", session)
+}
+
+//Binary ids are stored the same way browsers send them, base64url
+fn encode_id<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+pub fn create_webauthn(config: &RunConfiguration) -> Arc<Webauthn> {
+    let rp_id = config
+        .webauthn_rp_id
+        .clone()
+        .unwrap_or(config.server_url.clone());
+    let origin = config
+        .webauthn_origin
+        .clone()
+        .unwrap_or(format!("https://{}", config.server_url));
+    let origin = Url::parse(&origin).expect("Invalid WEBAUTHN_ORIGIN");
+
+    Arc::new(
+        WebauthnBuilder::new(&rp_id, &origin)
+            .expect("Invalid WebAuthn configuration")
+            .rp_name("dub")
+            .build()
+            .expect("Failed to build WebAuthn"),
+    )
+}
+
+pub async fn get_passkeys(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<PassKeyEntry>, OsuServerError> {
+    sqlx::query_as(
+        r#"SELECT * FROM "PassKeys" WHERE "userId" = $1 AND credential IS NOT NULL ORDER BY id"#,
+    )
+    .bind(user_id)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+async fn get_credentials(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<StoredPassKey>, OsuServerError> {
+    sqlx::query_as(
+        r#"SELECT id, credential FROM "PassKeys" WHERE "userId" = $1 AND credential IS NOT NULL"#,
+    )
+    .bind(user_id)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn remove_passkey(ctx: &Context, user: &User, id: i32) -> Result<bool, OsuServerError> {
+    let removed = sqlx::query(r#"DELETE FROM "PassKeys" WHERE id = $1 AND "userId" = $2"#)
+        .bind(id)
+        .bind(user.id)
+        .execute(&*ctx.pool)
+        .await
+        .map_err(database_error)?;
+
+    if removed.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "passkey.removed",
+            target_id: Some(user.id),
+            before: Some(json!({ "id": id })),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(true)
+}
+
+pub async fn start_registration(
+    ctx: &Context,
+    user: &User,
+) -> Result<CreationChallengeResponse, OsuServerError> {
+    //Every passkey of a user shares one user handle
+    let handle: Option<String> =
+        sqlx::query_scalar(r#"SELECT "userIdHexed" FROM "PassKeys" WHERE "userId" = $1 LIMIT 1"#)
+            .bind(user.id)
+            .fetch_optional(&*ctx.pool)
+            .await
+            .map_err(database_error)?;
+
+    let handle = handle
+        .and_then(|handle| Uuid::parse_str(&handle).ok())
+        .unwrap_or_else(Uuid::new_v4);
+
+    let existing = get_credentials(&ctx.pool, user.id)
+        .await?
+        .into_iter()
+        .map(|stored| stored.credential.cred_id().clone())
+        .collect::<Vec<_>>();
+
+    let (challenge, state) = ctx
+        .webauthn
+        .start_passkey_registration(handle, &user.username, &user.username, Some(existing))
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to start registration: {}", error))
+        })?;
+
+    let pending = PendingRegistration {
+        handle: handle.simple().to_string(),
+        challenge: encode_id(&challenge.public_key.challenge),
+        state,
+    };
+
+    let _: () = ctx
+        .redis
+        .clone()
+        .set_ex(
+            registration_key(user.id),
+            serde_json::to_string(&pending).map_err(state_error)?,
+            CEREMONY_TTL,
+        )
+        .await
+        .map_err(redis_error)?;
+
+    Ok(challenge)
+}
+
+pub async fn finish_registration(
+    ctx: &Context,
+    user: &User,
+    credential: &RegisterPublicKeyCredential,
+    name: Option<String>,
+    authenticator_attachment: Option<String>,
+) -> Result<PassKeyEntry, PasskeyError> {
+    let pending: Option<String> = ctx
+        .redis
+        .clone()
+        .get_del(registration_key(user.id))
+        .await
+        .map_err(redis_error)?;
+
+    let pending: PendingRegistration = pending
+        .and_then(|pending| serde_json::from_str(&pending).ok())
+        .ok_or(PasskeyError::CeremonyExpired)?;
+
+    let passkey = ctx
+        .webauthn
+        .finish_passkey_registration(credential, &pending.state)
+        .map_err(|error| PasskeyError::Rejected(error.to_string()))?;
+
+    let entry: PassKeyEntry = sqlx::query_as(
+        r#"
+INSERT INTO "PassKeys" ("userIdHexed", "userId", challange, "credId", "rawId", "Type", "authenticatorAttachment", credential, name)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+RETURNING *
+"#,
+    )
+    .bind(&pending.handle)
+    .bind(user.id)
+    .bind(&pending.challenge)
+    .bind(encode_id(passkey.cred_id()))
+    .bind(encode_id(&credential.raw_id))
+    .bind(&credential.type_)
+    .bind(authenticator_attachment)
+    .bind(Json(&passkey))
+    .bind(name.unwrap_or("Passkey".to_string()))
+    .fetch_one(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    info!("{} ({}) registered a passkey", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "passkey.registered",
+            target_id: Some(user.id),
+            after: Some(json!(entry)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(entry)
+}
+
+//Returns session id client has to send back together with the signed challenge
+pub async fn start_authentication(
+    ctx: &Context,
+    username: String,
+) -> Result<(String, RequestChallengeResponse), PasskeyError> {
+    let user = find_user_by_id_or_username(&ctx.pool, username)
+        .await?
+        .ok_or(PasskeyError::UserNotFound)?;
+
+    let credentials = get_credentials(&ctx.pool, user.id)
+        .await?
+        .into_iter()
+        .map(|stored| stored.credential.0)
+        .collect::<Vec<Passkey>>();
+
+    if credentials.is_empty() {
+        return Err(PasskeyError::NoPasskeys);
+    }
+
+    let (challenge, state) = ctx
+        .webauthn
+        .start_passkey_authentication(&credentials)
+        .map_err(|error| {
+            OsuServerError::Internal(format!("Failed to start authentication: {}", error))
+        })?;
+
+    let session = random_string(32);
+    let pending = PendingAuthentication {
+        user_id: user.id,
+        state,
+    };
+
+    let _: () = ctx
+        .redis
+        .clone()
+        .set_ex(
+            authentication_key(&session),
+            serde_json::to_string(&pending).map_err(state_error)?,
+            CEREMONY_TTL,
+        )
+        .await
+        .map_err(redis_error)?;
+
+    Ok((session, challenge))
+}
+
+pub async fn finish_authentication(
+    ctx: &Context,
+    session: &str,
+    credential: &PublicKeyCredential,
+) -> Result<User, PasskeyError> {
+    let pending: Option<String> = ctx
+        .redis
+        .clone()
+        .get_del(authentication_key(session))
+        .await
+        .map_err(redis_error)?;
+
+    let pending: PendingAuthentication = pending
+        .and_then(|pending| serde_json::from_str(&pending).ok())
+        .ok_or(PasskeyError::CeremonyExpired)?;
+
+    let result = ctx
+        .webauthn
+        .finish_passkey_authentication(credential, &pending.state)
+        .map_err(|error| PasskeyError::Rejected(error.to_string()))?;
+
+    let stored = get_credentials(&ctx.pool, pending.user_id)
+        .await?
+        .into_iter()
+        .find(|stored| stored.credential.cred_id() == result.cred_id());
+
+    //Signature counter is kept up to date to detect cloned authenticators
+    if let Some(mut stored) = stored {
+        stored.credential.update_credential(&result);
+
+        let updated = sqlx::query(
+            r#"UPDATE "PassKeys" SET credential = $2, "lastUsedAt" = NOW() WHERE id = $1"#,
+        )
+        .bind(stored.id)
+        .bind(Json(&stored.credential.0))
+        .execute(&*ctx.pool)
+        .await;
+
+        if let Err(error) = updated {
+            warn!("Failed to update passkey {}: {}", stored.id, error);
+        }
+    }
+
+    get_user_by_id(&ctx.pool, pending.user_id)
+        .await?
+        .ok_or(PasskeyError::UserNotFound)
+}
@@ -0,0 +1,544 @@
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info, warn};
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    general_utils::random_string,
+    lockout_utils::{clear_lockout, LockoutTarget},
+    mail_utils::Mail,
+    score_utils::OsuServerError,
+    user_utils::{invalidate_auth_cache, to_safe},
+};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 32;
+const MIN_UNIQUE_PASSWORD_CHARACTERS: usize = 4;
+const RESET_TOKEN_LENGTH: usize = 48;
+
+#[derive(Debug)]
+pub enum PasswordError {
+    InvalidLength,
+    TooSimple,
+    SameAsUsername,
+    WrongPassword,
+    InvalidToken,
+    Internal(OsuServerError),
+}
+
+impl PasswordError {
+    pub fn message(&self) -> String {
+        match self {
+            PasswordError::InvalidLength => format!(
+                "Password must be between {} and {} characters long.",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ),
+            PasswordError::TooSimple => "Password is too simple.".to_string(),
+            PasswordError::SameAsUsername => "Password can't be the same as username.".to_string(),
+            PasswordError::WrongPassword => "Current password is incorrect.".to_string(),
+            PasswordError::InvalidToken => "Reset link is invalid or has expired.".to_string(),
+            PasswordError::Internal(_) => "Internal server error.".to_string(),
+        }
+    }
+}
+
+impl From<OsuServerError> for PasswordError {
+    fn from(error: OsuServerError) -> Self {
+        PasswordError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Password query failed: {}", error))
+}
+
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn validate_password(username: &str, password: &str) -> Result<(), PasswordError> {
+    let length = password.chars().count();
+
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return Err(PasswordError::InvalidLength);
+    }
+
+    let mut characters = password.chars().collect::<Vec<char>>();
+    characters.sort_unstable();
+    characters.dedup();
+
+    if characters.len() < MIN_UNIQUE_PASSWORD_CHARACTERS {
+        return Err(PasswordError::TooSimple);
+    }
+
+    if to_safe(password) == to_safe(username) {
+        return Err(PasswordError::SameAsUsername);
+    }
+
+    Ok(())
+}
+
+//Client sends md5 hex digest on login, so it is what bcrypt gets as well
+pub fn hash_password(password: &str) -> Result<String, OsuServerError> {
+    let password_md5 = format!("{:x}", md5::compute(password));
+
+    bcrypt::hash(password_md5, bcrypt::DEFAULT_COST)
+        .map_err(|error| OsuServerError::Internal(format!("Failed to hash password: {}", error)))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let password_md5 = format!("{:x}", md5::compute(password));
+
+    bcrypt::verify(password_md5, hash).unwrap_or(false)
+}
+
+pub async fn change_password(
+    ctx: &Context,
+    user: &User,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), PasswordError> {
+    if !verify_password(current_password, &user.password) {
+        return Err(PasswordError::WrongPassword);
+    }
+
+    validate_password(&user.username, new_password)?;
+
+    //Issued tokens carry hmac of the password hash, so they stop working with the new hash
+    ctx.passwords
+        .update_password(user.id, &hash_password(new_password)?)
+        .await?;
+
+    invalidate_auth_cache(&ctx.redis, user.id).await;
+
+    info!("{} ({}) changed password", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.password_changed",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+//Always succeeds for unknown addresses, so it can't be used to look up accounts
+pub async fn request_password_reset(ctx: &Context, email: &str) -> Result<(), OsuServerError> {
+    let user: Option<User> =
+        sqlx::query_as(r#"SELECT * FROM "User" WHERE lower(email) = lower($1)"#)
+            .bind(email.trim())
+            .fetch_optional(&*ctx.pool)
+            .await
+            .map_err(database_error)?;
+
+    if user.is_none() {
+        return Ok(());
+    }
+
+    let user = user.unwrap();
+
+    //One mail per minute, repeated clicks don't flood the inbox
+    if ctx.passwords.has_recent_reset(user.id).await? {
+        return Ok(());
+    }
+
+    let token = random_string(RESET_TOKEN_LENGTH);
+
+    ctx.passwords
+        .create_reset(
+            user.id,
+            &token_hash(&token),
+            ctx.config.password_reset_ttl_minutes,
+        )
+        .await?;
+
+    let sent = ctx
+        .mailer
+        .send(Mail {
+            to: email.trim().to_string(),
+            subject: "Password reset".to_string(),
+            body: format!(
+                "Hello {},\n\nsomeone requested a password reset for your account. \
+                 If it was you, open the link below within {} minutes:\n\n\
+                 https://{}/reset-password?token={}\n\n\
+                 Otherwise you can ignore this mail.",
+                user.username, ctx.config.password_reset_ttl_minutes, ctx.config.server_url, token
+            ),
+        })
+        .await;
+
+    if let Err(error) = sent {
+        error!(
+            "Failed to send password reset to {} via {}: {}",
+            user.username,
+            ctx.mailer.name(),
+            error
+        );
+        //Same answer as for unknown addresses, error is only visible in logs
+        return Ok(());
+    }
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: None,
+            action: "user.password_reset_requested",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn reset_password(
+    ctx: &Context,
+    token: &str,
+    new_password: &str,
+) -> Result<(), PasswordError> {
+    let hash = token_hash(token);
+    let user = ctx.passwords.get_reset_user(&hash).await?;
+
+    if user.is_none() {
+        return Err(PasswordError::InvalidToken);
+    }
+
+    let user = user.unwrap();
+
+    //Rejected password keeps the token usable for another try
+    validate_password(&user.username, new_password)?;
+
+    //Token is only used together with the password update, parallel requests can't use it twice
+    let user = ctx
+        .passwords
+        .redeem_reset(&hash, &hash_password(new_password)?)
+        .await?;
+
+    if user.is_none() {
+        return Err(PasswordError::InvalidToken);
+    }
+
+    let user = user.unwrap();
+
+    invalidate_auth_cache(&ctx.redis, user.id).await;
+    //Owner proved access to the mailbox, lockouts from every ip are lifted
+    if let Err(error) = clear_lockout(&ctx.redis, LockoutTarget::User, &user.username).await {
+        warn!("Failed to clear lockout of {}: {:?}", user.id, error);
+    }
+
+    info!("{} ({}) reset password", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.password_reset",
+            target_id: Some(user.id),
+            after: Some(json!({ "via": "mail" })),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn update_user_password(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    password: &str,
+) -> Result<(), OsuServerError> {
+    sqlx::query(r#"UPDATE "User" SET password = $2 WHERE id = $1"#)
+        .bind(user_id)
+        .bind(password)
+        .execute(connection)
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+pub async fn has_recent_reset_token(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    sqlx::query_scalar(
+        r#"SELECT EXISTS (SELECT 1 FROM "PasswordResetToken" WHERE "userId" = $1 AND "createdAt" > NOW() - make_interval(mins => 1))"#,
+    )
+    .bind(user_id)
+    .fetch_one(connection)
+    .await
+    .map_err(database_error)
+}
+
+//Earlier unused tokens of the user stop working
+pub async fn insert_reset_token(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    token_hash: &str,
+    ttl_minutes: i32,
+) -> Result<(), OsuServerError> {
+    let mut transaction = connection.begin().await.map_err(database_error)?;
+
+    sqlx::query(
+        r#"UPDATE "PasswordResetToken" SET "usedAt" = NOW() WHERE "userId" = $1 AND "usedAt" IS NULL"#,
+    )
+    .bind(user_id)
+    .execute(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    sqlx::query(
+        r#"
+INSERT INTO "PasswordResetToken" ("userId", "tokenHash", "expiresAt")
+VALUES ($1, $2, NOW() + make_interval(mins => $3))
+"#,
+    )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(ttl_minutes)
+    .execute(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)
+}
+
+pub async fn get_reset_token_user(
+    connection: &Pool<Postgres>,
+    token_hash: &str,
+) -> Result<Option<User>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT "User".* FROM "User"
+JOIN "PasswordResetToken" token ON token."userId" = "User".id
+WHERE token."tokenHash" = $1 AND token."usedAt" IS NULL AND token."expiresAt" > NOW()
+"#,
+    )
+    .bind(token_hash)
+    .fetch_optional(connection)
+    .await
+    .map_err(database_error)
+}
+
+//Single statement, token row is locked until the password is stored
+pub async fn redeem_reset_token(
+    connection: &Pool<Postgres>,
+    token_hash: &str,
+    password: &str,
+) -> Result<Option<User>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+WITH claimed AS (
+    UPDATE "PasswordResetToken"
+    SET "usedAt" = NOW()
+    WHERE "tokenHash" = $1 AND "usedAt" IS NULL AND "expiresAt" > NOW()
+    RETURNING "userId"
+)
+UPDATE "User" SET password = $2, "forgotPassword" = false
+FROM claimed
+WHERE "User".id = claimed."userId"
+RETURNING "User".*
+"#,
+    )
+    .bind(token_hash)
+    .bind(password)
+    .fetch_optional(connection)
+    .await
+    .map_err(database_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Request},
+        middleware,
+        routing::get,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    use crate::{
+        api::auth::{
+            auth::{issue_tokens, TokenGrant},
+            middleware::auth,
+        },
+        repositories::{memory::MemoryStorage, PasswordRepository, UserRepository},
+        testing::{test_context, test_user},
+    };
+
+    use super::*;
+
+    //Cheap bcrypt cost, DEFAULT_COST is slow in debug builds
+    fn with_password(mut user: User, password: &str) -> User {
+        user.password = bcrypt::hash(format!("{:x}", md5::compute(password)), 4).unwrap();
+        user
+    }
+
+    async fn reset_context() -> (Arc<MemoryStorage>, Context) {
+        let storage = Arc::new(MemoryStorage::new());
+        storage
+            .add_user(with_password(test_user(2, "Player"), "old password"))
+            .await;
+
+        let ctx = test_context(storage.clone()).await;
+
+        (storage, ctx)
+    }
+
+    async fn stored_user(storage: &MemoryStorage) -> User {
+        UserRepository::get_by_id(storage, 2)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn is_authenticated(ctx: Arc<Context>, token: &str) -> bool {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|user: Option<Extension<Option<User>>>| async move {
+                    matches!(user, Some(Extension(Some(_)))).to_string()
+                }),
+            )
+            .layer(middleware::from_fn(auth))
+            .layer(Extension(ctx));
+
+        let request = Request::builder()
+            .uri("/")
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        body.as_ref() == b"true"
+    }
+
+    #[test]
+    fn validates_password_rules() {
+        assert!(matches!(
+            validate_password("Player", "short"),
+            Err(PasswordError::InvalidLength)
+        ));
+        assert!(matches!(
+            validate_password("Player", &"a".repeat(33)),
+            Err(PasswordError::InvalidLength)
+        ));
+        assert!(matches!(
+            validate_password("Player", "abababab"),
+            Err(PasswordError::TooSimple)
+        ));
+        assert!(matches!(
+            validate_password("Some Player", "some_player"),
+            Err(PasswordError::SameAsUsername)
+        ));
+        assert!(validate_password("Player", "correct horse").is_ok());
+    }
+
+    #[tokio::test]
+    async fn reset_token_works_once() {
+        let (storage, ctx) = reset_context().await;
+        storage
+            .create_reset(2, &token_hash("token"), 30)
+            .await
+            .unwrap();
+
+        //Rejected password doesn't use the token up
+        assert!(matches!(
+            reset_password(&ctx, "token", "short").await,
+            Err(PasswordError::InvalidLength)
+        ));
+
+        reset_password(&ctx, "token", "new password").await.unwrap();
+
+        let user = stored_user(&storage).await;
+        assert!(verify_password("new password", &user.password));
+
+        assert!(matches!(
+            reset_password(&ctx, "token", "another password").await,
+            Err(PasswordError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_or_replaced_reset_token_is_rejected() {
+        let (storage, ctx) = reset_context().await;
+        storage
+            .create_reset(2, &token_hash("expired"), -1)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            reset_password(&ctx, "expired", "new password").await,
+            Err(PasswordError::InvalidToken)
+        ));
+
+        storage
+            .create_reset(2, &token_hash("first"), 30)
+            .await
+            .unwrap();
+        storage
+            .create_reset(2, &token_hash("second"), 30)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            reset_password(&ctx, "first", "new password").await,
+            Err(PasswordError::InvalidToken)
+        ));
+
+        let user = stored_user(&storage).await;
+        assert!(verify_password("old password", &user.password));
+    }
+
+    #[tokio::test]
+    async fn password_change_revokes_issued_tokens() {
+        let (storage, ctx) = reset_context().await;
+        let ctx = Arc::new(ctx);
+        let user = stored_user(&storage).await;
+
+        let (_, response) = issue_tokens(
+            &ctx,
+            &user,
+            None,
+            TokenGrant {
+                client_id: None,
+                grant_type: "password",
+                amr: vec!["pwd".to_string()],
+                scope: None,
+            },
+        )
+        .await;
+        let token = response.0.access_token.clone().unwrap();
+
+        assert!(is_authenticated(ctx.clone(), &token).await);
+
+        assert!(matches!(
+            change_password(&ctx, &user, "wrong password", "new password").await,
+            Err(PasswordError::WrongPassword)
+        ));
+        assert!(is_authenticated(ctx.clone(), &token).await);
+
+        change_password(&ctx, &user, "old password", "new password")
+            .await
+            .unwrap();
+
+        assert!(!is_authenticated(ctx, &token).await);
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use chrono::NaiveDateTime;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{config::RunConfiguration, context::Context};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    badge_utils::{grant_automatic_badge, DONOR_BADGE},
+    notification_utils::{notify, NewNotification, NotificationKind},
+    score_utils::OsuServerError,
+    user_utils::send_bancho_message,
+};
+
+//Every paid month is worth this many days of donor perks
+pub const DAYS_PER_MONTH: i32 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedPayment {
+    //Id of payment in provider's system
+    pub payment_id: i32,
+    pub user_id: i32,
+    //In minor units of currency
+    pub sum: i32,
+    pub currency: String,
+    pub months: i32,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct PaymentEntry {
+    pub id: String,
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    #[sqlx(rename = "paymentId")]
+    pub payment_id: i32,
+    #[sqlx(rename = "paymentSystem")]
+    pub payment_system: String,
+    pub sum: i32,
+    pub currency: String,
+    #[sqlx(rename = "finishedAt")]
+    pub finished_at: NaiveDateTime,
+    pub until: NaiveDateTime,
+}
+
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    //Err when request can't be trusted, Ok(None) for events other than completed payments
+    async fn parse_webhook(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Option<CompletedPayment>, String>;
+}
+
+#[derive(Debug, Deserialize)]
+struct DummyWebhook {
+    payment_id: i32,
+    user_id: i32,
+    status: String,
+    amount: i32,
+    currency: String,
+    months: i32,
+}
+
+//Local provider for development, body has to be signed with
+//hex encoded HMAC-SHA256 of DUMMY_PAYMENT_SECRET in X-Signature header
+pub struct DummyPaymentProvider {
+    secret: String,
+}
+
+impl DummyPaymentProvider {
+    pub fn new(secret: String) -> Self {
+        Self { secret }
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+#[async_trait]
+impl PaymentProvider for DummyPaymentProvider {
+    fn name(&self) -> &'static str {
+        "dummy"
+    }
+
+    async fn parse_webhook(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Option<CompletedPayment>, String> {
+        let signature = headers
+            .get("X-Signature")
+            .and_then(|signature| signature.to_str().ok())
+            .and_then(decode_hex)
+            .ok_or("Missing signature")?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .map_err(|error| error.to_string())?;
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| "Invalid signature".to_string())?;
+
+        let webhook: DummyWebhook =
+            serde_json::from_slice(body).map_err(|error| error.to_string())?;
+
+        if webhook.status != "completed" {
+            return Ok(None);
+        }
+
+        if webhook.months < 1 || webhook.amount < 0 {
+            return Err("Invalid payment".to_string());
+        }
+
+        Ok(Some(CompletedPayment {
+            payment_id: webhook.payment_id,
+            user_id: webhook.user_id,
+            sum: webhook.amount,
+            currency: webhook.currency,
+            months: webhook.months,
+        }))
+    }
+}
+
+pub struct PaymentService {
+    providers: HashMap<&'static str, Box<dyn PaymentProvider>>,
+}
+
+impl PaymentService {
+    pub fn new(config: &RunConfiguration) -> Self {
+        let mut providers: HashMap<&'static str, Box<dyn PaymentProvider>> = HashMap::new();
+
+        for name in config.payment_providers.split(',').map(|name| name.trim()) {
+            match name {
+                "dummy" => match config.dummy_payment_secret.clone() {
+                    Some(secret) => {
+                        let provider = DummyPaymentProvider::new(secret);
+                        providers.insert(provider.name(), Box::new(provider));
+                    }
+                    None => {
+                        warn!("dummy payments are enabled, but DUMMY_PAYMENT_SECRET is not set")
+                    }
+                },
+                "" => {}
+                name => warn!("Unknown payment provider: {}", name),
+            }
+        }
+
+        Self { providers }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn PaymentProvider> {
+        self.providers.get(name).map(|provider| provider.as_ref())
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Failed to process payment: {}", error))
+}
+
+//Extends donor time and stores the entry in one transaction, None when this payment
+//was already recorded
+pub async fn record_payment(
+    connection: &Pool<Postgres>,
+    provider: &str,
+    payment: &CompletedPayment,
+) -> Result<Option<PaymentEntry>, OsuServerError> {
+    let mut transaction = connection.begin().await.map_err(database_error)?;
+
+    let donor_until: Option<NaiveDateTime> = sqlx::query_scalar(
+        r#"
+UPDATE "User"
+SET "donorUntil" = GREATEST(COALESCE("donorUntil", NOW()), NOW()) + make_interval(days => $2)
+WHERE id = $1
+RETURNING "donorUntil"
+"#,
+    )
+    .bind(payment.user_id)
+    .bind(payment.months * DAYS_PER_MONTH)
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    if donor_until.is_none() {
+        return Err(OsuServerError::Internal(format!(
+            "Payment {} of {} is for unknown user {}",
+            payment.payment_id, provider, payment.user_id
+        )));
+    }
+
+    let entry: Option<PaymentEntry> = sqlx::query_as(
+        r#"
+INSERT INTO "PaymentEntry" (id, "userId", "paymentId", "paymentSystem", sum, currency, until)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+ON CONFLICT ("paymentSystem", "paymentId") DO NOTHING
+RETURNING *
+"#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(payment.user_id)
+    .bind(payment.payment_id)
+    .bind(provider)
+    .bind(payment.sum)
+    .bind(&payment.currency)
+    .bind(donor_until)
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    //Retried webhook, donor time must not be extended twice
+    if entry.is_none() {
+        transaction.rollback().await.map_err(database_error)?;
+        return Ok(None);
+    }
+
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(entry)
+}
+
+//Returns None when this payment was already processed
+pub async fn process_payment(
+    ctx: &Context,
+    provider: &str,
+    payment: &CompletedPayment,
+) -> Result<Option<PaymentEntry>, OsuServerError> {
+    let entry = match ctx.payment_entries.record(provider, payment).await? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    info!(
+        "Payment {} of {}: user {} is donor until {}",
+        entry.payment_id, provider, entry.user_id, entry.until
+    );
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: None,
+            action: "donor.extended",
+            target_id: Some(entry.user_id),
+            after: Some(json!({ "donorUntil": entry.until, "paymentEntryId": entry.id })),
+            note: provider,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    if let Err(error) = grant_automatic_badge(
+        ctx,
+        DONOR_BADGE,
+        entry.user_id,
+        Some(entry.until),
+        "Automatic: donor",
+    )
+    .await
+    {
+        error!("Failed to grant donor badge: {:?}", error);
+    }
+
+    notify(
+        &ctx.pool,
+        NewNotification {
+            user_id: entry.user_id,
+            kind: NotificationKind::Supporter,
+            subject: entry.id.clone(),
+            text: format!(
+                "Thank you for supporting us! Your supporter perks last until {}.",
+                entry.until.format("%Y-%m-%d")
+            ),
+            avatar_of: None,
+        },
+    )
+    .await;
+
+    send_bancho_message(&entry.user_id, "user:privileges".to_string(), None).await;
+
+    Ok(Some(entry))
+}
+
+pub async fn get_user_payments(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Vec<PaymentEntry>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "PaymentEntry" WHERE "userId" = $1 ORDER BY "finishedAt" DESC"#)
+        .bind(user_id)
+        .fetch_all(connection)
+        .await
+        .map_err(database_error)
+}
+
+//Revokes perks of donors whose time ran out, each expiry is handled once
+pub async fn expire_donors(ctx: &Context) -> Result<(), OsuServerError> {
+    let mut expired: Vec<i32> = sqlx::query_scalar(
+        r#"
+UPDATE "PaymentEntry"
+SET "expiredAt" = NOW()
+FROM "User"
+WHERE
+    "User".id = "PaymentEntry"."userId" AND
+    "PaymentEntry"."expiredAt" IS NULL AND
+    ("User"."donorUntil" IS NULL OR "User"."donorUntil" <= NOW())
+RETURNING "PaymentEntry"."userId"
+"#,
+    )
+    .fetch_all(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    expired.sort_unstable();
+    expired.dedup();
+
+    for user_id in expired {
+        info!("supporter perks of {} expired", user_id);
+
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: None,
+                action: "donor.expired",
+                target_id: Some(user_id),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        notify(
+            &ctx.pool,
+            NewNotification {
+                user_id,
+                kind: NotificationKind::Supporter,
+                subject: user_id.to_string(),
+                text: "Your supporter perks have expired. Thank you for your support!".to_string(),
+                avatar_of: None,
+            },
+        )
+        .await;
+
+        send_bancho_message(&user_id, "user:privileges".to_string(), None).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repositories::{memory::MemoryStorage, PaymentRepository, UserRepository},
+        testing::test_user,
+    };
+
+    fn sign(secret: &str, body: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Signature", signature.parse().unwrap());
+        headers
+    }
+
+    fn webhook(status: &str) -> Vec<u8> {
+        json!({
+            "payment_id": 7,
+            "user_id": 2,
+            "status": status,
+            "amount": 500,
+            "currency": "EUR",
+            "months": 1
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn payment(payment_id: i32) -> CompletedPayment {
+        CompletedPayment {
+            payment_id,
+            user_id: 2,
+            sum: 500,
+            currency: "EUR".to_string(),
+            months: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn dummy_provider_checks_signature() {
+        let provider = DummyPaymentProvider::new("secret".to_string());
+        let body = webhook("completed");
+
+        let payment = provider
+            .parse_webhook(&sign("secret", &body), &body)
+            .await
+            .unwrap();
+        assert_eq!(payment.map(|payment| payment.payment_id), Some(7));
+
+        assert!(provider
+            .parse_webhook(&sign("other", &body), &body)
+            .await
+            .is_err());
+        assert!(provider
+            .parse_webhook(&HeaderMap::new(), &body)
+            .await
+            .is_err());
+
+        let pending = webhook("pending");
+        assert_eq!(
+            provider
+                .parse_webhook(&sign("secret", &pending), &pending)
+                .await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn retried_payment_extends_donor_time_once() {
+        let storage = MemoryStorage::new();
+        storage.add_user(test_user(2, "donor")).await;
+
+        let first = storage
+            .record("dummy", &payment(7))
+            .await
+            .unwrap()
+            .expect("first delivery is recorded");
+        assert!(storage
+            .record("dummy", &payment(7))
+            .await
+            .unwrap()
+            .is_none());
+
+        let user = UserRepository::get_by_id(&storage, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.donor_until, Some(first.until));
+
+        //Same id from another provider is a different payment
+        let other = storage
+            .record("other", &payment(7))
+            .await
+            .unwrap()
+            .expect("other provider is recorded");
+        assert_eq!(
+            other.until - first.until,
+            chrono::Duration::days(DAYS_PER_MONTH.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn payment_for_unknown_user_fails() {
+        let storage = MemoryStorage::new();
+
+        assert!(storage.record("dummy", &payment(7)).await.is_err());
+    }
+}
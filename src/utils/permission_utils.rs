@@ -0,0 +1,179 @@
+use serde::Serialize;
+use tracing::error;
+
+use crate::{context::Context, db::user::User};
+
+use super::user_utils::{is_donator, RESTRICTED_PERMISSION};
+
+//Capabilities granted by groups, stored as bits in "Group".permissions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Capabilities(pub i32);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    //Beatmap status changes with !map
+    pub const NOMINATE: Capabilities = Capabilities(1);
+    //Restrictions, reports, hwid, sessions, audit log, restricted profiles
+    pub const MODERATE: Capabilities = Capabilities(2);
+    pub const MANAGE_GROUPS: Capabilities = Capabilities(4);
+    //Packet capture and other debugging tools
+    pub const DEVELOPER: Capabilities = Capabilities(8);
+    pub const SUPPORTER: Capabilities = Capabilities(16);
+
+    const NAMES: [(Capabilities, &'static str); 5] = [
+        (Capabilities::NOMINATE, "nominate"),
+        (Capabilities::MODERATE, "moderate"),
+        (Capabilities::MANAGE_GROUPS, "manage_groups"),
+        (Capabilities::DEVELOPER, "developer"),
+        (Capabilities::SUPPORTER, "supporter"),
+    ];
+
+    pub fn contains(&self, capability: Capabilities) -> bool {
+        self.0 & capability.0 == capability.0
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    //Client side privileges: 1 player, 2 moderator, 4 supporter, 8 owner, 16 developer
+    pub fn bancho_privileges(&self) -> i32 {
+        let mut privileges = 1;
+
+        if self.contains(Capabilities::SUPPORTER) {
+            privileges |= 4;
+        }
+
+        if self.contains(Capabilities::MODERATE) {
+            privileges |= 2;
+        }
+
+        if self.contains(Capabilities::MANAGE_GROUPS) {
+            privileges |= 8;
+        }
+
+        if self.contains(Capabilities::DEVELOPER) {
+            privileges |= 16;
+        }
+
+        privileges
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+//Single place every permission check goes through, bancho, bot commands and api
+pub async fn get_capabilities(ctx: &Context, user: &User) -> Capabilities {
+    //Restricted and pending accounts keep their groups but can't use them
+    if user.permissions & RESTRICTED_PERMISSION > 0 {
+        return Capabilities::NONE;
+    }
+
+    let groups = ctx.groups.get_user_groups(user.id).await;
+
+    if let Err(error) = groups {
+        error!("Failed to fetch groups of {}: {:?}", user.id, error);
+        return Capabilities::NONE;
+    }
+
+    let capabilities = groups
+        .unwrap()
+        .iter()
+        .fold(Capabilities::NONE, |capabilities, group| {
+            capabilities | Capabilities(group.permissions)
+        });
+
+    match is_donator(user) {
+        true => capabilities | Capabilities::SUPPORTER,
+        false => capabilities,
+    }
+}
+
+pub async fn has_capability(ctx: &Context, user: &User, capability: Capabilities) -> bool {
+    get_capabilities(ctx, user).await.contains(capability)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        repositories::memory::MemoryStorage,
+        testing::{test_context, test_user},
+        utils::group_utils::Group,
+    };
+
+    fn group(name: &str, capabilities: Capabilities) -> Group {
+        Group {
+            id: name.to_string(),
+            name: name.to_string(),
+            permissions: capabilities.0,
+            badge_id: None,
+            require_two_factor: false,
+        }
+    }
+
+    #[test]
+    fn maps_capabilities_to_client_privileges() {
+        assert_eq!(Capabilities::NONE.bancho_privileges(), 1);
+        assert_eq!(
+            (Capabilities::MODERATE | Capabilities::SUPPORTER).bancho_privileges(),
+            1 | 2 | 4
+        );
+        assert_eq!(
+            (Capabilities::MANAGE_GROUPS | Capabilities::DEVELOPER).bancho_privileges(),
+            1 | 8 | 16
+        );
+    }
+
+    #[tokio::test]
+    async fn combines_groups_and_donor_status() {
+        let storage = Arc::new(MemoryStorage::new());
+        storage
+            .add_user_group(2, group("nominator", Capabilities::NOMINATE))
+            .await;
+        storage
+            .add_user_group(2, group("moderator", Capabilities::MODERATE))
+            .await;
+        let ctx = test_context(storage).await;
+
+        let mut user = test_user(2, "player");
+        let capabilities = get_capabilities(&ctx, &user).await;
+        assert_eq!(
+            capabilities,
+            Capabilities::NOMINATE | Capabilities::MODERATE
+        );
+        assert_eq!(capabilities.names(), vec!["nominate", "moderate"]);
+
+        user.donor_until = Some(Utc::now().naive_utc() + Duration::days(1));
+        assert!(has_capability(&ctx, &user, Capabilities::SUPPORTER).await);
+        assert!(!has_capability(&ctx, &user, Capabilities::MANAGE_GROUPS).await);
+    }
+
+    #[tokio::test]
+    async fn restricted_users_have_no_capabilities() {
+        let storage = Arc::new(MemoryStorage::new());
+        storage
+            .add_user_group(2, group("admin", Capabilities::MANAGE_GROUPS))
+            .await;
+        let ctx = test_context(storage).await;
+
+        let mut user = test_user(2, "player");
+        user.permissions = RESTRICTED_PERMISSION;
+
+        assert_eq!(get_capabilities(&ctx, &user).await, Capabilities::NONE);
+    }
+}
@@ -0,0 +1,56 @@
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+
+use super::score_utils::OsuServerError;
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserPreferences {
+    #[sqlx(rename = "notifyFirstPlaceLost")]
+    pub notify_first_place_lost: bool,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self {
+            notify_first_place_lost: true,
+        }
+    }
+}
+
+//Users without a row use defaults
+pub async fn get_user_preferences(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<UserPreferences, OsuServerError> {
+    let preferences: Option<UserPreferences> =
+        sqlx::query_as(r#"SELECT * FROM "UserPreference" WHERE "userId" = $1"#)
+            .bind(user_id)
+            .fetch_optional(connection)
+            .await
+            .map_err(|error| {
+                OsuServerError::Internal(format!("Failed to fetch preferences: {}", error))
+            })?;
+
+    Ok(preferences.unwrap_or_default())
+}
+
+pub async fn update_user_preferences(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    notify_first_place_lost: Option<bool>,
+) -> Result<UserPreferences, OsuServerError> {
+    sqlx::query_as(
+        r#"
+INSERT INTO "UserPreference" ("userId", "notifyFirstPlaceLost")
+VALUES ($1, COALESCE($2, true))
+ON CONFLICT ("userId") DO UPDATE SET
+    "notifyFirstPlaceLost" = COALESCE($2, "UserPreference"."notifyFirstPlaceLost")
+RETURNING *
+"#,
+    )
+    .bind(user_id)
+    .bind(notify_first_place_lost)
+    .fetch_one(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to update preferences: {}", error)))
+}
@@ -0,0 +1,159 @@
+use regex::Regex;
+use serde::Serialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::context::Context;
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    password_utils::{hash_password, validate_password},
+    score_utils::OsuServerError,
+    user_utils::{to_safe, PENDING_VERIFICATION_FLAG, RESTRICTED_PERMISSION},
+    username_utils::{is_username_available, validate_username_format},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Registration {
+    pub username: String,
+    pub email: String,
+    //Plain password, client sends it only on registration
+    pub password: String,
+}
+
+//Messages per field, shown by the client under matching input
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RegistrationErrors {
+    pub username: Vec<String>,
+    pub user_email: Vec<String>,
+    pub password: Vec<String>,
+}
+
+impl RegistrationErrors {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_empty() && self.user_email.is_empty() && self.password.is_empty()
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Registration query failed: {}", error))
+}
+
+pub async fn validate_registration(
+    ctx: &Context,
+    registration: &Registration,
+) -> Result<RegistrationErrors, OsuServerError> {
+    let mut errors = RegistrationErrors::default();
+
+    match validate_username_format(&registration.username, &ctx.config.reserved_usernames) {
+        Err(error) => errors.username.push(error.message().to_string()),
+        Ok(()) => {
+            if !is_username_available(
+                &ctx.pool,
+                &registration.username,
+                None,
+                ctx.config.username_hold_days,
+            )
+            .await?
+            {
+                errors
+                    .username
+                    .push("This username is already taken.".to_string());
+            }
+        }
+    }
+
+    let email_pattern = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").expect("Failed to parse regex");
+
+    if !email_pattern.is_match(&registration.email) {
+        errors
+            .user_email
+            .push("Email address is not valid.".to_string());
+    } else {
+        let taken: bool = sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM "User" WHERE lower(email) = lower($1))"#,
+        )
+        .bind(&registration.email)
+        .fetch_one(&*ctx.pool)
+        .await
+        .map_err(database_error)?;
+
+        if taken {
+            errors
+                .user_email
+                .push("This email address is already in use.".to_string());
+        }
+    }
+
+    if let Err(error) = validate_password(&registration.username, &registration.password) {
+        errors.password.push(error.message());
+    }
+
+    Ok(errors)
+}
+
+//Accounts start pending verification, first bancho login runs the policy checks
+pub async fn create_account(
+    ctx: &Context,
+    registration: &Registration,
+    ip: Option<&str>,
+) -> Result<Option<i32>, OsuServerError> {
+    let password = hash_password(&registration.password)?;
+
+    let country = ctx
+        .geoip
+        .lookup(ip.map(str::to_string))
+        .await
+        .map(|country| country.code)
+        .unwrap_or("XX".to_string());
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+
+    let id = sqlx::query_scalar::<_, i32>(
+        r#"
+INSERT INTO "User" (username, "usernameSafe", password, email, country, permissions, flags)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+RETURNING id
+"#,
+    )
+    .bind(&registration.username)
+    .bind(to_safe(&registration.username))
+    .bind(password)
+    .bind(&registration.email)
+    .bind(&country)
+    .bind(RESTRICTED_PERMISSION)
+    .bind(PENDING_VERIFICATION_FLAG)
+    .fetch_one(&mut *transaction)
+    .await;
+
+    //Same name or email registered between validation and insert
+    let id = match id {
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => return Ok(None),
+        id => id.map_err(database_error)?,
+    };
+
+    sqlx::query(r#"INSERT INTO "UserStats" ("userId") VALUES ($1)"#)
+        .bind(id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    info!("New account {} ({}) registered", registration.username, id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(id),
+            action: "user.registered",
+            target_id: Some(id),
+            after: Some(json!({ "username": registration.username, "country": country, "ip": ip })),
+            note: "In-game registration",
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(Some(id))
+}
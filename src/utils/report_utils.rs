@@ -0,0 +1,411 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::error;
+use webhook::client::WebhookClient;
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    notification_utils::{notify, NewNotification, NotificationKind},
+    score_utils::OsuServerError,
+    user_utils::find_user_by_id_or_username,
+};
+
+const REPORT_COLUMNS: &str = r#"
+    "Report".*,
+    reported.username AS "reportedUsername",
+    reporter.username AS "reporterUsername"
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportReason {
+    Other,
+    Cheating,
+    Multiaccounting,
+    Harassment,
+    InappropriateName,
+    Spam,
+}
+
+impl ReportReason {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "other" => Some(ReportReason::Other),
+            "cheating" | "cheat" | "hacks" => Some(ReportReason::Cheating),
+            "multiaccounting" | "multi" => Some(ReportReason::Multiaccounting),
+            "harassment" | "toxic" => Some(ReportReason::Harassment),
+            "name" | "username" => Some(ReportReason::InappropriateName),
+            "spam" => Some(ReportReason::Spam),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            ReportReason::Other => 0,
+            ReportReason::Cheating => 1,
+            ReportReason::Multiaccounting => 2,
+            ReportReason::Harassment => 3,
+            ReportReason::InappropriateName => 4,
+            ReportReason::Spam => 5,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Other => "other",
+            ReportReason::Cheating => "cheating",
+            ReportReason::Multiaccounting => "multiaccounting",
+            ReportReason::Harassment => "harassment",
+            ReportReason::InappropriateName => "name",
+            ReportReason::Spam => "spam",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReportError {
+    UserNotFound,
+    SelfReport,
+    AlreadyReported,
+    InvalidScore,
+    EmptyReason,
+    Internal(OsuServerError),
+}
+
+impl ReportError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ReportError::UserNotFound => "Could not find user.",
+            ReportError::SelfReport => "You can't report yourself.",
+            ReportError::AlreadyReported => "You already have an open report against this user.",
+            ReportError::InvalidScore => "Score doesn't belong to reported user.",
+            ReportError::EmptyReason => "Reason can't be empty.",
+            ReportError::Internal(_) => "Internal server error.",
+        }
+    }
+}
+
+impl From<OsuServerError> for ReportError {
+    fn from(error: OsuServerError) -> Self {
+        ReportError::Internal(error)
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Report {
+    pub id: i32,
+    #[sqlx(rename = "reportedUser")]
+    pub reported_user: i32,
+    #[sqlx(rename = "reportedUsername")]
+    pub reported_username: String,
+    #[sqlx(rename = "repporterId")]
+    pub reporter_id: i32,
+    #[sqlx(rename = "reporterUsername")]
+    pub reporter_username: String,
+    #[sqlx(rename = "scoreId")]
+    pub score_id: Option<i32>,
+    #[sqlx(rename = "reasonId")]
+    pub reason_id: i32,
+    pub reason: String,
+    pub status: String,
+    #[sqlx(rename = "claimedBy")]
+    pub claimed_by: Option<i32>,
+    pub resolution: Option<String>,
+    #[sqlx(rename = "punishmentId")]
+    pub punishment_id: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+    #[sqlx(rename = "resolvedAt")]
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Report query failed: {}", error))
+}
+
+pub async fn get_report(
+    connection: &Pool<Postgres>,
+    id: i32,
+) -> Result<Option<Report>, OsuServerError> {
+    sqlx::query_as(&format!(
+        r#"
+SELECT {}
+FROM "Report"
+JOIN "User" reported ON reported.id = "Report"."reportedUser"
+JOIN "User" reporter ON reporter.id = "Report"."repporterId"
+WHERE "Report".id = $1
+"#,
+        REPORT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn get_reports(
+    connection: &Pool<Postgres>,
+    status: Option<String>,
+    reported_user: Option<i32>,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<Report>, OsuServerError> {
+    sqlx::query_as(&format!(
+        r#"
+SELECT {}
+FROM "Report"
+JOIN "User" reported ON reported.id = "Report"."reportedUser"
+JOIN "User" reporter ON reporter.id = "Report"."repporterId"
+WHERE
+    ($1::text IS NULL OR "Report".status = $1) AND
+    ($2::integer IS NULL OR "Report"."reportedUser" = $2)
+ORDER BY "Report"."createdAt" ASC
+OFFSET $3
+LIMIT $4
+"#,
+        REPORT_COLUMNS
+    ))
+    .bind(status)
+    .bind(reported_user)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn submit_report(
+    ctx: &Context,
+    reporter: &User,
+    target: String,
+    reason: ReportReason,
+    text: String,
+    score_id: Option<i32>,
+) -> Result<Report, ReportError> {
+    let text = text.trim().to_string();
+
+    if text.is_empty() {
+        return Err(ReportError::EmptyReason);
+    }
+
+    let reported = find_user_by_id_or_username(&ctx.pool, target)
+        .await?
+        .ok_or(ReportError::UserNotFound)?;
+
+    if reported.id == reporter.id {
+        return Err(ReportError::SelfReport);
+    }
+
+    let open: bool = sqlx::query_scalar(
+        r#"SELECT EXISTS (SELECT 1 FROM "Report" WHERE "repporterId" = $1 AND "reportedUser" = $2 AND status IN ('open', 'claimed'))"#,
+    )
+    .bind(reporter.id)
+    .bind(reported.id)
+    .fetch_one(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    if open {
+        return Err(ReportError::AlreadyReported);
+    }
+
+    if let Some(score_id) = score_id {
+        let owner: Option<i32> =
+            sqlx::query_scalar(r#"SELECT "userId" FROM "Score" WHERE id = $1"#)
+                .bind(score_id)
+                .fetch_optional(&*ctx.pool)
+                .await
+                .map_err(database_error)?;
+
+        if owner != Some(reported.id) {
+            return Err(ReportError::InvalidScore);
+        }
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        r#"
+INSERT INTO "Report" ("reportedUser", "scoreId", "repporterId", "reasonId", reason)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING id
+"#,
+    )
+    .bind(reported.id)
+    .bind(score_id)
+    .bind(reporter.id)
+    .bind(reason.id())
+    .bind(&text)
+    .fetch_one(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    let report = get_report(&ctx.pool, id)
+        .await?
+        .ok_or_else(|| OsuServerError::Internal("Inserted report is missing".to_string()))?;
+
+    report_alert(ctx, &report, reason).await;
+
+    Ok(report)
+}
+
+//Returns None when report was already claimed or closed
+pub async fn claim_report(
+    connection: &Pool<Postgres>,
+    id: i32,
+    staff_id: i32,
+) -> Result<Option<Report>, OsuServerError> {
+    let claimed = sqlx::query(
+        r#"UPDATE "Report" SET status = 'claimed', "claimedBy" = $2 WHERE id = $1 AND status = 'open'"#,
+    )
+    .bind(id)
+    .bind(staff_id)
+    .execute(connection)
+    .await
+    .map_err(database_error)?;
+
+    if claimed.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let report = get_report(connection, id).await?;
+
+    if let Some(report) = &report {
+        insert_audit_log(
+            connection,
+            AuditRecord {
+                actor_id: Some(staff_id),
+                action: "report.claimed",
+                target_id: Some(report.reported_user),
+                after: Some(json!({ "reportId": report.id })),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    Ok(report)
+}
+
+//Reports without punishment are dismissed, reporter is notified either way
+pub async fn resolve_report(
+    ctx: &Context,
+    id: i32,
+    staff_id: i32,
+    punishment_id: Option<String>,
+    resolution: Option<String>,
+) -> Result<Option<Report>, OsuServerError> {
+    let status = match punishment_id {
+        Some(_) => "resolved",
+        None => "dismissed",
+    };
+
+    let updated = sqlx::query(
+        r#"
+UPDATE "Report"
+SET
+    status = $2,
+    "claimedBy" = COALESCE("claimedBy", $3),
+    "punishmentId" = $4,
+    resolution = $5,
+    "resolvedAt" = NOW()
+WHERE id = $1 AND status IN ('open', 'claimed')
+"#,
+    )
+    .bind(id)
+    .bind(status)
+    .bind(staff_id)
+    .bind(&punishment_id)
+    .bind(resolution)
+    .execute(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let report = get_report(&ctx.pool, id).await?;
+
+    if let Some(report) = &report {
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: Some(staff_id),
+                action: "report.resolved",
+                target_id: Some(report.reported_user),
+                after: Some(json!({
+                    "reportId": report.id,
+                    "status": status,
+                    "punishmentId": punishment_id,
+                })),
+                note: report.resolution.as_deref().unwrap_or_default(),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let outcome = match punishment_id {
+            Some(_) => "action has been taken",
+            None => "no action was needed",
+        };
+
+        notify(
+            &ctx.pool,
+            NewNotification {
+                user_id: report.reporter_id,
+                kind: NotificationKind::ReportResolved,
+                subject: report.id.to_string(),
+                text: format!(
+                    "Your report against {} has been reviewed, {}. Thank you!",
+                    report.reported_username, outcome
+                ),
+                avatar_of: None,
+            },
+        )
+        .await;
+    }
+
+    Ok(report)
+}
+
+async fn report_alert(ctx: &Context, report: &Report, reason: ReportReason) {
+    if let Some(webhook) = ctx.config.alert_discord_webhook.clone() {
+        let client = WebhookClient::new(webhook.as_str());
+
+        let formatted_report = format!(
+            r#"
+Reported: [{}](https://{}/users/{})
+Reporter: [{}](https://{}/users/{})
+Category: `{}`
+Score: `{}`
+Reason: {}
+"#,
+            report.reported_username,
+            ctx.config.server_url,
+            report.reported_user,
+            report.reporter_username,
+            ctx.config.server_url,
+            report.reporter_id,
+            reason.as_str(),
+            report
+                .score_id
+                .map(|score_id| score_id.to_string())
+                .unwrap_or("-".to_string()),
+            report.reason
+        );
+
+        if let Err(error) = client
+            .send(|message| {
+                message
+                    .content(format!("New player report #{}!", report.id).as_str())
+                    .embed(|embed| embed.description(formatted_report.as_str()))
+            })
+            .await
+        {
+            error!("Failed to send report alert: {}", error);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, ops::BitAnd};
 
-use redis::{Client, Commands};
+use redis::{aio::ConnectionManager, AsyncCommands};
 use serde::Deserialize;
 use sqlx::{prelude::FromRow, Pool, Postgres, Row};
 use tracing::{debug, error, info};
@@ -708,29 +708,22 @@ WHERE
 
 pub async fn get_user_grades_count(
     connection: &Pool<Postgres>,
-    client: &Client,
+    client: &ConnectionManager,
     user: &User,
     mode: &OsuMode,
     recalculate: Option<bool>,
     grade: String,
 ) -> Result<i32, OsuServerError> {
-    let redis_connection = client.get_connection();
-
-    if let Err(error) = redis_connection {
-        error!("Failed to get connection: {}", error);
-        return Err(OsuServerError::Internal(
-            "Failed to get connection.".to_string(),
-        ));
-    }
-
-    let mut redis_connection = redis_connection.unwrap();
-
-    let cached_value: Result<Option<i32>, redis::RedisError> = redis_connection.get(format!(
-        "user:{}:grades:{}:{}",
-        user.id,
-        mode.to_osu(),
-        grade
-    ));
+    let mut redis_connection = client.clone();
+
+    let cached_value: Result<Option<i32>, redis::RedisError> = redis_connection
+        .get(format!(
+            "user:{}:grades:{}:{}",
+            user.id,
+            mode.to_osu(),
+            grade
+        ))
+        .await;
     if let Err(error) = cached_value {
         error!("Failed to get cached value: {}", error);
         return Err(OsuServerError::Internal(
@@ -768,12 +761,16 @@ pub async fn get_user_grades_count(
         }
 
         //Setting it in redis
-        let _: () = redis_connection
+        let cached: Result<(), redis::RedisError> = redis_connection
             .set(
                 format!("user:{}:grades:{}:{}", user.id, mode.to_osu(), grade),
                 count,
             )
-            .expect("Failed to update cache.");
+            .await;
+
+        if let Err(error) = cached {
+            error!("Failed to update cache: {}", error);
+        }
 
         return Ok(count);
     }
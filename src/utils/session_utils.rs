@@ -0,0 +1,116 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::error;
+
+use crate::bancho::client::HWID;
+
+use super::score_utils::OsuServerError;
+
+#[derive(Debug, Default)]
+pub struct LoginRecord<'a> {
+    pub user_id: i32,
+    pub source: &'a str,
+    pub ip: Option<&'a str>,
+    pub country: Option<&'a str>,
+    pub client_version: Option<&'a str>,
+    pub hwid: Option<&'a HWID>,
+    pub client_id: Option<i32>,
+    pub grant_type: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct LoginHistoryEntry {
+    pub id: i32,
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    pub username: String,
+    pub ip: Option<String>,
+    pub source: String,
+    pub country: Option<String>,
+    #[sqlx(rename = "clientVersion")]
+    pub client_version: Option<String>,
+    pub mac: Option<String>,
+    #[sqlx(rename = "uniqueId")]
+    pub unique_id: Option<String>,
+    #[sqlx(rename = "diskId")]
+    pub disk_id: Option<String>,
+    #[sqlx(rename = "clientId")]
+    pub client_id: Option<i32>,
+    #[sqlx(rename = "grantType")]
+    pub grant_type: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct LoginHistoryFilter {
+    pub user_id: Option<i32>,
+    pub ip: Option<String>,
+    //Matches mac, unique id or disk id
+    pub hwid: Option<String>,
+    pub source: Option<String>,
+}
+
+pub async fn insert_login_history(connection: &Pool<Postgres>, record: &LoginRecord<'_>) {
+    let result = sqlx::query(
+        r#"
+INSERT INTO "LoginHistory" (
+    "userId", source, ip, country, "clientVersion", mac, "uniqueId", "diskId", "clientId", "grantType"
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+"#,
+    )
+    .bind(record.user_id)
+    .bind(record.source)
+    .bind(record.ip)
+    .bind(record.country)
+    .bind(record.client_version)
+    .bind(record.hwid.map(|hwid| hwid.mac.as_str()))
+    .bind(record.hwid.map(|hwid| hwid.uid.as_str()))
+    .bind(record.hwid.map(|hwid| hwid.disk.as_str()))
+    .bind(record.client_id)
+    .bind(record.grant_type)
+    .execute(connection)
+    .await;
+
+    if let Err(error) = result {
+        error!("Failed to store login of {}: {}", record.user_id, error);
+    }
+}
+
+pub async fn get_login_history(
+    connection: &Pool<Postgres>,
+    filter: &LoginHistoryFilter,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<LoginHistoryEntry>, OsuServerError> {
+    sqlx::query_as(
+        r#"
+SELECT
+    "LoginHistory".*, "User".username
+FROM
+    "LoginHistory"
+JOIN
+    "User" ON "User".id = "LoginHistory"."userId"
+WHERE
+    ($1::integer IS NULL OR "LoginHistory"."userId" = $1) AND
+    ($2::text IS NULL OR "LoginHistory".ip = $2) AND
+    ($3::text IS NULL OR $3 IN ("LoginHistory".mac, "LoginHistory"."uniqueId", "LoginHistory"."diskId")) AND
+    ($4::text IS NULL OR "LoginHistory".source = $4)
+ORDER BY
+    "LoginHistory"."createdAt" DESC
+OFFSET $5
+LIMIT $6
+"#,
+    )
+    .bind(filter.user_id)
+    .bind(&filter.ip)
+    .bind(&filter.hwid)
+    .bind(&filter.source)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch login history: {}", error)))
+}
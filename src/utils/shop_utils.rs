@@ -0,0 +1,319 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    badge_utils::{grant_automatic_badge, DONOR_BADGE},
+    score_utils::OsuServerError,
+    user_utils::{is_restricted, send_bancho_message},
+};
+
+//Coins awarded for the first vote callback of a day
+pub const VOTE_REWARD: i32 = 50;
+const DONOR_DAYS_PER_PURCHASE: i32 = 7;
+const MAX_BACKGROUND_SLOTS: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShopItem {
+    UsernameChange,
+    DonorDays,
+    BackgroundSlot,
+}
+
+impl ShopItem {
+    pub const ALL: [ShopItem; 3] = [
+        ShopItem::UsernameChange,
+        ShopItem::DonorDays,
+        ShopItem::BackgroundSlot,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "username_change" => Some(ShopItem::UsernameChange),
+            "donor_days" => Some(ShopItem::DonorDays),
+            "background_slot" => Some(ShopItem::BackgroundSlot),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShopItem::UsernameChange => "username_change",
+            ShopItem::DonorDays => "donor_days",
+            ShopItem::BackgroundSlot => "background_slot",
+        }
+    }
+
+    pub fn price(&self) -> i32 {
+        match self {
+            ShopItem::UsernameChange => 500,
+            ShopItem::DonorDays => 300,
+            ShopItem::BackgroundSlot => 200,
+        }
+    }
+
+    pub fn describe(&self) -> ShopItemInfo {
+        let (name, description) = match self {
+            ShopItem::UsernameChange => ("Username change", "One additional username change."),
+            ShopItem::DonorDays => ("Supporter days", "7 days of supporter perks."),
+            ShopItem::BackgroundSlot => ("Background slot", "One more profile background slot."),
+        };
+
+        ShopItemInfo {
+            key: self.as_str(),
+            name,
+            description,
+            price: self.price(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShopItemInfo {
+    pub key: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub price: i32,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ShopPurchase {
+    pub id: i32,
+    #[sqlx(rename = "userId")]
+    pub user_id: i32,
+    pub item: String,
+    pub price: i32,
+    #[sqlx(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub enum ShopError {
+    Restricted,
+    NotEnoughCoins,
+    LimitReached,
+    Internal(OsuServerError),
+}
+
+impl ShopError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ShopError::Restricted => "Restricted accounts can't use the shop.",
+            ShopError::NotEnoughCoins => "You don't have enough coins.",
+            ShopError::LimitReached => "You already own the maximum amount of this item.",
+            ShopError::Internal(_) => "Internal server error.",
+        }
+    }
+}
+
+impl From<OsuServerError> for ShopError {
+    fn from(error: OsuServerError) -> Self {
+        ShopError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Shop query failed: {}", error))
+}
+
+pub fn get_catalogue() -> Vec<ShopItemInfo> {
+    ShopItem::ALL.iter().map(ShopItem::describe).collect()
+}
+
+//Returns false when user was already rewarded today
+pub async fn reward_listing_vote(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    let mut transaction = connection.begin().await.map_err(database_error)?;
+
+    let inserted = sqlx::query(
+        r#"
+INSERT INTO "ListingVote" ("userId", coins)
+VALUES ($1, $2)
+ON CONFLICT ("userId", "votedOn") DO NOTHING
+"#,
+    )
+    .bind(user_id)
+    .bind(VOTE_REWARD)
+    .execute(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    if inserted.rows_affected() == 0 {
+        transaction.rollback().await.map_err(database_error)?;
+        return Ok(false);
+    }
+
+    sqlx::query(r#"UPDATE "User" SET coins = coins + $1 WHERE id = $2"#)
+        .bind(VOTE_REWARD)
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    Ok(true)
+}
+
+//Coins are deducted and item is applied in one transaction, nothing is charged on failure
+pub async fn purchase_item(
+    ctx: &Context,
+    user: &User,
+    item: ShopItem,
+) -> Result<ShopPurchase, ShopError> {
+    if is_restricted(user).await {
+        return Err(ShopError::Restricted);
+    }
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+
+    let balance: Option<i32> = sqlx::query_scalar(
+        r#"UPDATE "User" SET coins = coins - $2 WHERE id = $1 AND coins >= $2 RETURNING coins"#,
+    )
+    .bind(user.id)
+    .bind(item.price())
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    if balance.is_none() {
+        transaction.rollback().await.map_err(database_error)?;
+        return Err(ShopError::NotEnoughCoins);
+    }
+
+    let purchase: ShopPurchase = sqlx::query_as(
+        r#"INSERT INTO "ShopPurchase" ("userId", item, price) VALUES ($1, $2, $3) RETURNING *"#,
+    )
+    .bind(user.id)
+    .bind(item.as_str())
+    .bind(item.price())
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    let mut donor_until: Option<NaiveDateTime> = None;
+
+    match item {
+        ShopItem::UsernameChange => {
+            sqlx::query(
+                r#"UPDATE "User" SET "usernameChangesRemaining" = "usernameChangesRemaining" + 1 WHERE id = $1"#,
+            )
+            .bind(user.id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+        }
+        ShopItem::BackgroundSlot => {
+            let slots: Option<i32> = sqlx::query_scalar(
+                r#"
+UPDATE "User"
+SET "backgroundSlots" = "backgroundSlots" + 1
+WHERE id = $1 AND "backgroundSlots" < $2
+RETURNING "backgroundSlots"
+"#,
+            )
+            .bind(user.id)
+            .bind(MAX_BACKGROUND_SLOTS)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+
+            if slots.is_none() {
+                transaction.rollback().await.map_err(database_error)?;
+                return Err(ShopError::LimitReached);
+            }
+        }
+        ShopItem::DonorDays => {
+            let until: NaiveDateTime = sqlx::query_scalar(
+                r#"
+UPDATE "User"
+SET "donorUntil" = GREATEST(COALESCE("donorUntil", NOW()), NOW()) + make_interval(days => $2)
+WHERE id = $1
+RETURNING "donorUntil"
+"#,
+            )
+            .bind(user.id)
+            .bind(DONOR_DAYS_PER_PURCHASE)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+
+            //Stored as payment so expiry is handled the same way as for paid perks
+            sqlx::query(
+                r#"
+INSERT INTO "PaymentEntry" (id, "userId", "paymentId", "paymentSystem", sum, currency, until)
+VALUES ($1, $2, $3, 'coins', $4, 'COIN', $5)
+"#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(user.id)
+            .bind(purchase.id)
+            .bind(item.price())
+            .bind(until)
+            .execute(&mut *transaction)
+            .await
+            .map_err(database_error)?;
+
+            donor_until = Some(until);
+        }
+    }
+
+    transaction.commit().await.map_err(database_error)?;
+
+    info!(
+        "{} bought {} for {} coins",
+        user.username,
+        item.as_str(),
+        item.price()
+    );
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "shop.purchase",
+            target_id: Some(user.id),
+            before: Some(json!({ "coins": user.coins })),
+            after: Some(json!({ "coins": balance, "item": item.as_str() })),
+            note: item.as_str(),
+        },
+    )
+    .await;
+
+    if let Some(until) = donor_until {
+        if let Err(error) =
+            grant_automatic_badge(ctx, DONOR_BADGE, user.id, Some(until), "Automatic: donor").await
+        {
+            error!("Failed to grant donor badge: {:?}", error);
+        }
+
+        send_bancho_message(&user.id, "user:privileges".to_string(), None).await;
+    }
+
+    Ok(purchase)
+}
+
+pub async fn get_purchases(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<ShopPurchase>, OsuServerError> {
+    sqlx::query_as(
+        r#"SELECT * FROM "ShopPurchase" WHERE "userId" = $1 ORDER BY id DESC OFFSET $2 LIMIT $3"#,
+    )
+    .bind(user_id)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(connection)
+    .await
+    .map_err(database_error)
+}
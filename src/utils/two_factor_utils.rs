@@ -0,0 +1,561 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{prelude::FromRow, Pool, Postgres, Transaction};
+use totp_rs::{Algorithm, Secret, TOTP};
+use tracing::info;
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    general_utils::random_string,
+    score_utils::OsuServerError,
+};
+
+const TOTP_STEP: u64 = 30;
+const TOTP_DIGITS: usize = 6;
+//Steps accepted before and after the current one, covers clock drift of phones
+const TOTP_SKEW: i64 = 1;
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+#[derive(Debug, FromRow)]
+struct TwoFactorRow {
+    secret: String,
+    #[sqlx(rename = "lastUsedStep")]
+    last_used_step: Option<i64>,
+    #[sqlx(rename = "enabledAt")]
+    enabled_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    //Set when one of user's groups requires it
+    pub required: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    //otpauth:// url, rendered as QR code by the website
+    pub url: String,
+}
+
+#[derive(Debug)]
+pub enum TwoFactorError {
+    AlreadyEnabled,
+    NotEnabled,
+    NotStarted,
+    InvalidCode,
+    RequiredByGroup,
+    Internal(OsuServerError),
+}
+
+impl TwoFactorError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            TwoFactorError::AlreadyEnabled => "Two-factor authentication is already enabled.",
+            TwoFactorError::NotEnabled => "Two-factor authentication is not enabled.",
+            TwoFactorError::NotStarted => "Start two-factor enrollment first.",
+            TwoFactorError::InvalidCode => "Invalid two-factor code.",
+            TwoFactorError::RequiredByGroup => {
+                "One of your groups requires two-factor authentication."
+            }
+            TwoFactorError::Internal(_) => "Internal server error.",
+        }
+    }
+}
+
+impl From<OsuServerError> for TwoFactorError {
+    fn from(error: OsuServerError) -> Self {
+        TwoFactorError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Two-factor query failed: {}", error))
+}
+
+fn totp_error<E: std::fmt::Display>(error: E) -> OsuServerError {
+    OsuServerError::Internal(format!("Failed to set up totp: {}", error))
+}
+
+//Recovery codes are accepted with or without dash and in any case
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|character| character.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn code_hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize_code(code).as_bytes()))
+}
+
+fn build_totp(ctx: &Context, user: &User, secret: &str) -> Result<TOTP, OsuServerError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(totp_error)?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        TOTP_SKEW as u8,
+        TOTP_STEP,
+        secret,
+        //Colon separates issuer and account in otpauth urls, totp-rs rejects it in both
+        Some(ctx.config.server_url.replace(':', "")),
+        user.username.replace(':', ""),
+    )
+    .map_err(totp_error)
+}
+
+fn current_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+//Returns step the code belongs to, so it can be marked as used. Steps up to the last
+//used one are skipped, a code can't be replayed and older codes don't work after a newer one
+fn match_step(totp: &TOTP, code: &str, time: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+
+    if code.len() != TOTP_DIGITS || !code.chars().all(|character| character.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = time / TOTP_STEP as i64;
+
+    (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|step| last_used_step.is_none_or(|last_used| *step > last_used))
+        .find(|step| totp.generate(*step as u64 * TOTP_STEP) == code)
+}
+
+async fn get_row(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<Option<TwoFactorRow>, OsuServerError> {
+    sqlx::query_as(r#"SELECT * FROM "UserTwoFactor" WHERE "userId" = $1"#)
+        .bind(user_id)
+        .fetch_optional(connection)
+        .await
+        .map_err(database_error)
+}
+
+async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+) -> Result<Vec<String>, OsuServerError> {
+    let codes = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = random_string(RECOVERY_CODE_LENGTH).to_lowercase();
+            format!(
+                "{}-{}",
+                &code[..RECOVERY_CODE_LENGTH / 2],
+                &code[RECOVERY_CODE_LENGTH / 2..]
+            )
+        })
+        .collect::<Vec<String>>();
+
+    sqlx::query(r#"DELETE FROM "TwoFactorRecoveryCode" WHERE "userId" = $1"#)
+        .bind(user_id)
+        .execute(&mut **transaction)
+        .await
+        .map_err(database_error)?;
+
+    sqlx::query(
+        r#"INSERT INTO "TwoFactorRecoveryCode" ("userId", "codeHash") SELECT $1, unnest($2::text[])"#,
+    )
+    .bind(user_id)
+    .bind(codes.iter().map(|code| code_hash(code)).collect::<Vec<String>>())
+    .execute(&mut **transaction)
+    .await
+    .map_err(database_error)?;
+
+    Ok(codes)
+}
+
+pub async fn is_two_factor_enabled(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    sqlx::query_scalar(
+        r#"SELECT EXISTS (SELECT 1 FROM "UserTwoFactor" WHERE "userId" = $1 AND "enabledAt" IS NOT NULL)"#,
+    )
+    .bind(user_id)
+    .fetch_one(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn is_two_factor_required(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+) -> Result<bool, OsuServerError> {
+    sqlx::query_scalar(
+        r#"
+SELECT EXISTS (
+    SELECT 1 FROM "UserGroup"
+    JOIN "Group" ON "Group".id = "UserGroup"."groupId"
+    WHERE "UserGroup"."userId" = $1 AND "Group"."requireTwoFactor"
+)
+"#,
+    )
+    .bind(user_id)
+    .fetch_one(connection)
+    .await
+    .map_err(database_error)
+}
+
+pub async fn get_two_factor_status(
+    ctx: &Context,
+    user: &User,
+) -> Result<TwoFactorStatus, OsuServerError> {
+    let recovery_codes_left: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM "TwoFactorRecoveryCode" WHERE "userId" = $1 AND "usedAt" IS NULL"#,
+    )
+    .bind(user.id)
+    .fetch_one(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    Ok(TwoFactorStatus {
+        enabled: is_two_factor_enabled(&ctx.pool, user.id).await?,
+        required: is_two_factor_required(&ctx.pool, user.id).await?,
+        recovery_codes_left,
+    })
+}
+
+//Starting again before confirming replaces the pending secret
+pub async fn start_enrollment(
+    ctx: &Context,
+    user: &User,
+) -> Result<TwoFactorEnrollment, TwoFactorError> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+
+    let totp = build_totp(ctx, user, &secret)?;
+
+    let stored = sqlx::query(
+        r#"
+INSERT INTO "UserTwoFactor" ("userId", secret) VALUES ($1, $2)
+ON CONFLICT ("userId") DO UPDATE SET secret = $2, "createdAt" = NOW()
+WHERE "UserTwoFactor"."enabledAt" IS NULL
+"#,
+    )
+    .bind(user.id)
+    .bind(&secret)
+    .execute(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    if stored.rows_affected() == 0 {
+        return Err(TwoFactorError::AlreadyEnabled);
+    }
+
+    Ok(TwoFactorEnrollment {
+        secret,
+        url: totp.get_url(),
+    })
+}
+
+//Returns recovery codes, this is the only time they are shown
+pub async fn confirm_enrollment(
+    ctx: &Context,
+    user: &User,
+    code: &str,
+) -> Result<Vec<String>, TwoFactorError> {
+    let row = get_row(&ctx.pool, user.id)
+        .await?
+        .ok_or(TwoFactorError::NotStarted)?;
+
+    if row.enabled_at.is_some() {
+        return Err(TwoFactorError::AlreadyEnabled);
+    }
+
+    let step = match_step(
+        &build_totp(ctx, user, &row.secret)?,
+        code,
+        current_time(),
+        None,
+    )
+    .ok_or(TwoFactorError::InvalidCode)?;
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+
+    let enabled = sqlx::query(
+        r#"UPDATE "UserTwoFactor" SET "enabledAt" = NOW(), "lastUsedStep" = $2 WHERE "userId" = $1 AND "enabledAt" IS NULL AND secret = $3"#,
+    )
+    .bind(user.id)
+    .bind(step)
+    .bind(&row.secret)
+    .execute(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    if enabled.rows_affected() == 0 {
+        transaction.rollback().await.map_err(database_error)?;
+        return Err(TwoFactorError::NotStarted);
+    }
+
+    //Tokens issued before were signed in with password alone, they stop working
+    sqlx::query(r#"UPDATE "User" SET "tokenGeneration" = "tokenGeneration" + 1 WHERE id = $1"#)
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+    let codes = replace_recovery_codes(&mut transaction, user.id).await?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    info!("{} ({}) enabled two-factor", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.two_factor_enabled",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(codes)
+}
+
+//Accepts current totp code or one of recovery codes, either works only once
+pub async fn verify_two_factor(
+    ctx: &Context,
+    user: &User,
+    code: &str,
+) -> Result<bool, OsuServerError> {
+    let row = get_row(&ctx.pool, user.id).await?;
+
+    let row = match row {
+        Some(row) if row.enabled_at.is_some() => row,
+        _ => return Ok(false),
+    };
+
+    let totp = build_totp(ctx, user, &row.secret)?;
+
+    //Stored step is checked again by the update, two requests racing with one code can't both pass
+    if let Some(step) = match_step(&totp, code, current_time(), row.last_used_step) {
+        let accepted = sqlx::query(
+            r#"UPDATE "UserTwoFactor" SET "lastUsedStep" = $2 WHERE "userId" = $1 AND ("lastUsedStep" IS NULL OR "lastUsedStep" < $2)"#,
+        )
+        .bind(user.id)
+        .bind(step)
+        .execute(&*ctx.pool)
+        .await
+        .map_err(database_error)?;
+
+        return Ok(accepted.rows_affected() > 0);
+    }
+
+    let recovered = sqlx::query(
+        r#"UPDATE "TwoFactorRecoveryCode" SET "usedAt" = NOW() WHERE "userId" = $1 AND "codeHash" = $2 AND "usedAt" IS NULL"#,
+    )
+    .bind(user.id)
+    .bind(code_hash(code))
+    .execute(&*ctx.pool)
+    .await
+    .map_err(database_error)?;
+
+    if recovered.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.two_factor_recovery_used",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(true)
+}
+
+pub async fn disable_two_factor(
+    ctx: &Context,
+    user: &User,
+    code: &str,
+) -> Result<(), TwoFactorError> {
+    if !is_two_factor_enabled(&ctx.pool, user.id).await? {
+        return Err(TwoFactorError::NotEnabled);
+    }
+
+    if is_two_factor_required(&ctx.pool, user.id).await? {
+        return Err(TwoFactorError::RequiredByGroup);
+    }
+
+    if !verify_two_factor(ctx, user, code).await? {
+        return Err(TwoFactorError::InvalidCode);
+    }
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+
+    sqlx::query(r#"DELETE FROM "UserTwoFactor" WHERE "userId" = $1"#)
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+    sqlx::query(r#"DELETE FROM "TwoFactorRecoveryCode" WHERE "userId" = $1"#)
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    info!("{} ({}) disabled two-factor", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.two_factor_disabled",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn regenerate_recovery_codes(
+    ctx: &Context,
+    user: &User,
+    code: &str,
+) -> Result<Vec<String>, TwoFactorError> {
+    if !is_two_factor_enabled(&ctx.pool, user.id).await? {
+        return Err(TwoFactorError::NotEnabled);
+    }
+
+    if !verify_two_factor(ctx, user, code).await? {
+        return Err(TwoFactorError::InvalidCode);
+    }
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+    let codes = replace_recovery_codes(&mut transaction, user.id).await?;
+    transaction.commit().await.map_err(database_error)?;
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.two_factor_recovery_regenerated",
+            target_id: Some(user.id),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        repositories::memory::MemoryStorage,
+        testing::{test_context, test_user},
+    };
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+    //Start of step 56666666
+    const TIME: i64 = 1_699_999_980;
+    const STEP: i64 = TIME / TOTP_STEP as i64;
+
+    async fn totp() -> TOTP {
+        let ctx = test_context(Arc::new(MemoryStorage::new())).await;
+
+        build_totp(&ctx, &test_user(2, "player"), SECRET).unwrap()
+    }
+
+    fn code_at(totp: &TOTP, step: i64) -> String {
+        totp.generate(step as u64 * TOTP_STEP)
+    }
+
+    #[tokio::test]
+    async fn accepts_codes_within_skew() {
+        let totp = totp().await;
+
+        for step in [STEP - 1, STEP, STEP + 1] {
+            assert_eq!(
+                match_step(&totp, &code_at(&totp, step), TIME, None),
+                Some(step)
+            );
+        }
+
+        assert_eq!(
+            match_step(&totp, &code_at(&totp, STEP - 2), TIME, None),
+            None
+        );
+        assert_eq!(
+            match_step(&totp, &code_at(&totp, STEP + 2), TIME, None),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn used_steps_cant_be_replayed() {
+        let totp = totp().await;
+
+        assert_eq!(
+            match_step(&totp, &code_at(&totp, STEP), TIME, Some(STEP)),
+            None
+        );
+        //Previous code is still within skew, but a newer one was already used
+        assert_eq!(
+            match_step(&totp, &code_at(&totp, STEP - 1), TIME, Some(STEP)),
+            None
+        );
+        assert_eq!(
+            match_step(&totp, &code_at(&totp, STEP + 1), TIME, Some(STEP)),
+            Some(STEP + 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_codes() {
+        let totp = totp().await;
+        let code = code_at(&totp, STEP);
+
+        assert_eq!(
+            match_step(&totp, &format!(" {} ", code), TIME, None),
+            Some(STEP)
+        );
+        assert_eq!(match_step(&totp, &code[1..], TIME, None), None);
+        assert_eq!(match_step(&totp, "abcdef", TIME, None), None);
+    }
+
+    #[tokio::test]
+    async fn colon_in_username_is_stripped() {
+        let ctx = test_context(Arc::new(MemoryStorage::new())).await;
+
+        let totp = build_totp(&ctx, &test_user(2, "player:one"), SECRET).unwrap();
+        assert_eq!(totp.account_name, "playerone");
+    }
+
+    #[test]
+    fn recovery_codes_ignore_dash_and_case() {
+        assert_eq!(code_hash("abcde-12345"), code_hash("ABCDE12345"));
+        assert_ne!(code_hash("abcde-12345"), code_hash("abcde-12346"));
+    }
+}
@@ -1,10 +1,11 @@
-use std::time::Duration;
-
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
 use bcrypt::verify;
 use chrono::{NaiveDateTime, Utc};
 use clap::Parser;
-use redis::Commands;
+use hmac::{Hmac, Mac};
+use redis::{aio::ConnectionManager, AsyncCommands};
 use serde_json::json;
+use sha2::Sha256;
 use sqlx::{prelude::FromRow, Pool, Postgres, Row};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
@@ -24,34 +25,72 @@ pub fn to_safe(name: impl ToString) -> String {
     name.to_string().to_lowercase().replace(' ', "_")
 }
 
+//Successful logins are remembered for an hour
+const AUTH_CACHE_TTL: u64 = 60 * 60;
+
+fn auth_cache_key(user_id: i32) -> String {
+    format!("user:{}:auth", user_id)
+}
+
+//Keyed with server secret and bound to the current password hash,
+//so changed password never matches an old entry
+fn auth_cache_digest(secret: &str, password_hash: &str, password: &str) -> Hmac<Sha256> {
+    let mut hmac: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes()).unwrap();
+    hmac.update(password_hash.as_bytes());
+    hmac.update(b":");
+    hmac.update(password.as_bytes());
+    hmac
+}
+
+pub async fn invalidate_auth_cache(redis: &ConnectionManager, user_id: i32) {
+    let result: Result<i32, redis::RedisError> = redis.clone().del(auth_cache_key(user_id)).await;
+
+    if let Err(error) = result {
+        error!(
+            "Failed to invalidate auth cache of user {}: {}",
+            user_id, error
+        );
+    }
+}
+
+//Older versions stored verified logins as `user:{name}:password:{password}`
+pub async fn purge_legacy_password_cache(
+    redis: &ConnectionManager,
+) -> Result<usize, OsuServerError> {
+    let mut connection = redis.clone();
+    let mut keys: Vec<String> = Vec::new();
+
+    {
+        let mut iter = connection
+            .scan_match::<_, String>("user:*:password:*")
+            .await
+            .map_err(redis_error)?;
+
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+    }
+
+    for chunk in keys.chunks(500) {
+        let _: i32 = connection.del(chunk).await.map_err(redis_error)?;
+    }
+
+    Ok(keys.len())
+}
+
 //Ignoring dead code and unsued variables
 #[allow(unused_variables)]
 #[allow(unreachable_code)]
 pub async fn validate_auth(
-    redis: &redis::Client,
+    redis: &ConnectionManager,
     connection: &Pool<Postgres>,
+    secret: &str,
     user: impl ToString,
     password: impl ToString,
 ) -> bool {
     #[cfg(debug_assertions)]
     return true;
 
-    let redis_connection = redis.get_connection();
-
-    if let Err(error) = redis_connection {
-        error!("Failed to get redis connection: {}", error);
-        return false;
-    }
-
-    let mut redis_connection = redis_connection.unwrap();
-    let cached_password: Option<String> = redis_connection
-        .get(format!(
-            "user:{}:password:{}",
-            to_safe(user.to_string()),
-            password.to_string()
-        ))
-        .unwrap();
-
     let user_id = get_user_id(redis, connection, user.to_string()).await;
 
     if user_id.is_none() {
@@ -74,27 +113,49 @@ pub async fn validate_auth(
     }
     let user = user.unwrap();
 
-    if let Some(cached_pass) = cached_password {
-        return user.password == cached_pass;
+    let password = password.to_string();
+    let mut redis_connection = redis.clone();
+
+    //Cache is optional, if redis is unavailable we are going straight to bcrypt
+    let cached_digest: Option<String> = redis_connection
+        .get(auth_cache_key(user.id))
+        .await
+        .unwrap_or_else(|error| {
+            warn!("Failed to read auth cache: {}", error);
+            None
+        });
+
+    if let Some(cached_digest) = cached_digest {
+        let cached_digest = STANDARD_NO_PAD.decode(cached_digest).unwrap_or_default();
+
+        if auth_cache_digest(secret, &user.password, &password)
+            .verify_slice(&cached_digest)
+            .is_ok()
+        {
+            return true;
+        }
     }
 
-    let verification_result = verify(password.to_string(), &user.password);
+    let verification_result = verify(&password, &user.password);
 
     match verification_result {
         Ok(result) => {
             if result {
-                let _: () = redis_connection
-                    .set(
-                        format!(
-                            "user:{}:password:{}",
-                            to_safe(user.username_safe),
-                            password.to_string()
-                        ),
-                        user.password.to_string(),
+                let digest = auth_cache_digest(secret, &user.password, &password)
+                    .finalize()
+                    .into_bytes();
+
+                let cached: Result<(), redis::RedisError> = redis_connection
+                    .set_ex(
+                        auth_cache_key(user.id),
+                        STANDARD_NO_PAD.encode(digest),
+                        AUTH_CACHE_TTL,
                     )
-                    .expect("Failed to save password to cache.");
+                    .await;
 
-                info!("cached: {}, real: {} [2]", password.to_string(), 0);
+                if let Err(error) = cached {
+                    warn!("Failed to save auth cache of user {}: {}", user.id, error);
+                }
             }
             result
         }
@@ -103,21 +164,18 @@ pub async fn validate_auth(
 }
 
 pub async fn get_user_id(
-    redis: &redis::Client,
+    redis: &ConnectionManager,
     connection: &Pool<Postgres>,
     username: impl ToString,
 ) -> Option<i32> {
-    let redis_connection = redis.get_connection();
-
-    if let Err(error) = redis_connection {
-        error!("Failed to get redis connection: {}", error);
-        return None;
-    }
-
-    let mut redis_connection = redis_connection.unwrap();
+    let mut redis_connection = redis.clone();
     let cached_id: Option<i32> = redis_connection
         .get(format!("user:{}:id", to_safe(username.to_string())))
-        .unwrap_or_default();
+        .await
+        .unwrap_or_else(|error| {
+            warn!("Failed to read cached user id: {}", error);
+            None
+        });
 
     if let Some(cached_id) = cached_id {
         return Some(cached_id);
@@ -135,8 +193,9 @@ pub async fn get_user_id(
         }
         Ok(user) => {
             let user = User::from_row(&user).unwrap();
-            let _: Result<i32, redis::RedisError> =
-                redis_connection.set(format!("user:{}:id", to_safe(user.username_safe)), user.id);
+            let _: Result<(), redis::RedisError> = redis_connection
+                .set(format!("user:{}:id", to_safe(user.username_safe)), user.id)
+                .await;
 
             Some(user.id)
         }
@@ -197,6 +256,7 @@ WHERE
     }
 }
 
+//Every component is matched on its own, empty values from the client never match
 pub async fn find_hwids(
     connection: &Pool<Postgres>,
     hwid: &HWID,
@@ -204,21 +264,24 @@ pub async fn find_hwids(
     let rows = sqlx::query(
         r#"
 SELECT
-    "Hwid".*, "User".*
+    "User".*,
+    "Hwid".id AS "hwidId",
+    COALESCE("Hwid"."mac", '') AS "hwidMac",
+    COALESCE("Hwid"."uniqueId", '') AS "hwidUniqueId",
+    COALESCE("Hwid"."diskId", '') AS "hwidDiskId"
 FROM
     "Hwid"
 JOIN
     "User" ON "Hwid"."userId" = "User"."id"
 WHERE
-    "Hwid"."mac" = $1 OR "Hwid"."mac" = $2 OR
-    "Hwid"."uniqueId" = $3 OR "Hwid"."uniqueId" = $1 OR
-    "Hwid"."diskId" = $4 OR "Hwid"."diskId" = $3
+    ($1 <> '' AND "Hwid"."mac" = $1) OR
+    ($2 <> '' AND "Hwid"."uniqueId" = $2) OR
+    ($3 <> '' AND "Hwid"."diskId" = $3)
 "#,
     )
-    .bind(&hwid.uid)
     .bind(&hwid.mac)
+    .bind(&hwid.uid)
     .bind(&hwid.disk)
-    .bind(&hwid.plain)
     .fetch_all(connection)
     .await;
 
@@ -234,7 +297,6 @@ WHERE
             let mut result = Vec::new();
             for row in rows {
                 let user = User::from_row(&row);
-                let hwid = DatabaseHwid::from_row(&row);
 
                 if let Err(error) = user {
                     return Err(OsuServerError::Internal(format!(
@@ -245,14 +307,13 @@ WHERE
 
                 let user = user.unwrap();
 
-                if let Err(error) = hwid {
-                    return Err(OsuServerError::Internal(format!(
-                        "Failed to fetch hwid: {}",
-                        error
-                    )));
-                }
-
-                let hwid = hwid.unwrap();
+                let hwid = DatabaseHwid {
+                    id: row.get("hwidId"),
+                    user_id: user.id,
+                    mac: row.get("hwidMac"),
+                    unique_id: row.get("hwidUniqueId"),
+                    disk_id: row.get("hwidDiskId"),
+                };
 
                 result.push(UserHwid { hwid, user });
             }
@@ -309,72 +370,59 @@ WHERE
     }
 }
 
+//Account state bits, roles are resolved through groups in permission_utils
+pub const RESTRICTED_PERMISSION: i32 = 8;
+pub const PENDING_VERIFICATION_FLAG: i32 = 32;
+
 pub async fn is_restricted(user: &User) -> bool {
-    (user.permissions & 8) > 0 && (user.flags & 32) == 0
+    user.permissions & RESTRICTED_PERMISSION > 0 && user.flags & PENDING_VERIFICATION_FLAG == 0
 }
 
 pub fn is_pending_verification(user: &User) -> bool {
-    user.permissions & 8 > 0 && user.flags & 32 > 0
+    user.permissions & RESTRICTED_PERMISSION > 0 && user.flags & PENDING_VERIFICATION_FLAG > 0
 }
 
 pub fn is_verified(user: &User) -> bool {
     user.flags & 2 > 0
 }
 
-pub async fn get_country_rank(redis: &redis::Client, user: &User, mode: &OsuMode) -> Option<i32> {
-    let redis = redis.get_connection();
-
-    if let Err(error) = redis {
-        error!("Failed to get redis connection: {}", error);
-        return None;
-    }
-
-    let mut redis = redis.unwrap();
-    let response: Result<Option<i32>, redis::RedisError> = redis.zrevrank(
-        format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
-        user.id,
-    );
-
-    if let Ok(rank) = response {
-        match rank {
-            Some(rank) => {
-                return Some(rank + 1);
-            }
-            None => {
-                return None;
-            }
-        }
-    }
-
-    None
+pub fn redis_error(error: redis::RedisError) -> OsuServerError {
+    error!("Redis request failed: {}", error);
+    OsuServerError::Internal(format!("Redis request failed: {}", error))
 }
 
-pub async fn get_rank(redis: &redis::Client, user: &User, mode: &OsuMode) -> Option<i32> {
-    let redis = redis.get_connection();
-
-    if let Err(error) = redis {
-        error!("Failed to get redis connection: {}", error);
-        return None;
-    }
+pub async fn get_country_rank(
+    redis: &ConnectionManager,
+    user: &User,
+    mode: &OsuMode,
+) -> Result<Option<i32>, OsuServerError> {
+    let rank: Option<i32> = redis
+        .clone()
+        .zrevrank(
+            format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
+            user.id,
+        )
+        .await
+        .map_err(redis_error)?;
 
-    let mut redis = redis.unwrap();
-    let response: Result<Option<i32>, redis::RedisError> = redis.zrevrank(
-        format!("leaderboard:{}:performance", mode.to_osu()),
-        user.id,
-    );
+    Ok(rank.map(|rank| rank + 1))
+}
 
-    if let Ok(rank) = response {
-        match rank {
-            Some(rank) => {
-                return Some(rank + 1);
-            }
-            None => {
-                return None;
-            }
-        }
-    }
+pub async fn get_rank(
+    redis: &ConnectionManager,
+    user: &User,
+    mode: &OsuMode,
+) -> Result<Option<i32>, OsuServerError> {
+    let rank: Option<i32> = redis
+        .clone()
+        .zrevrank(
+            format!("leaderboard:{}:performance", mode.to_osu()),
+            user.id,
+        )
+        .await
+        .map_err(redis_error)?;
 
-    None
+    Ok(rank.map(|rank| rank + 1))
 }
 
 pub async fn get_inactive_users(connection: &Pool<Postgres>) -> Result<Vec<User>, OsuServerError> {
@@ -416,9 +464,10 @@ SELECT
 FROM
     "User"
 WHERE
-    permissions & 8 > 0
+    permissions & $1 > 0
     "#,
     )
+    .bind(RESTRICTED_PERMISSION)
     .fetch_all(connection)
     .await;
 
@@ -434,15 +483,8 @@ WHERE
     }
 }
 
-pub async fn remove_ranking(redis: &redis::Client, user: &User) {
-    let redis = redis.get_connection_with_timeout(Duration::from_secs(60));
-
-    if let Err(error) = redis {
-        error!("Failed to get redis connection: {}", error);
-        return;
-    }
-
-    let mut redis = redis.unwrap();
+pub async fn remove_ranking(redis: &ConnectionManager, user: &User) -> Result<(), OsuServerError> {
+    let mut redis = redis.clone();
 
     for mode in [
         OsuMode::Osu,
@@ -451,47 +493,39 @@ pub async fn remove_ranking(redis: &redis::Client, user: &User) {
         OsuMode::Mania,
         OsuMode::Relax,
     ] {
-        let result: Result<Option<i32>, redis::RedisError> = redis.zrem(
-            format!("leaderboard:{}:performance", mode.to_osu()),
-            user.id,
-        );
+        let _: Option<i32> = redis
+            .zrem(
+                format!("leaderboard:{}:performance", mode.to_osu()),
+                user.id,
+            )
+            .await
+            .map_err(redis_error)?;
 
-        match result {
-            Err(error) => {
-                error!("Failed to remove ranking: {}", error);
-            }
-            Ok(_) => {
-                info!(
-                    "Removed ranking for user {} in mode {}",
-                    user.id,
-                    mode.to_osu()
-                );
-            }
-        }
+        let _: Option<i32> = redis
+            .zrem(
+                format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
+                user.id,
+            )
+            .await
+            .map_err(redis_error)?;
 
-        let result: Result<Option<i32>, redis::RedisError> = redis.zrem(
-            format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
+        info!(
+            "Removed ranking for user {} in mode {}",
             user.id,
+            mode.to_osu()
         );
-
-        match result {
-            Err(error) => {
-                error!("Failed to remove ranking: {}", error);
-            }
-            Ok(_) => {
-                info!(
-                    "Removed ranking for user {} in mode {}",
-                    user.id,
-                    mode.to_osu()
-                );
-            }
-        }
     }
+
+    Ok(())
 }
 
 pub async fn update_user_hwid(connection: &Pool<Postgres>, user: &User, hwid: &HWID) {
     sqlx::query(
-        "UPDATE \"Hwid\" SET \"mac\"=$1, \"uniqueId\"=$2, \"diskId\"=$3 WHERE \"userId\"=$4",
+        r#"
+INSERT INTO "Hwid" ("userId", "mac", "uniqueId", "diskId")
+VALUES ($4, $1, $2, $3)
+ON CONFLICT ("userId") DO UPDATE SET "mac" = $1, "uniqueId" = $2, "diskId" = $3
+"#,
     )
     .bind(&hwid.mac)
     .bind(&hwid.uid)
@@ -513,7 +547,9 @@ SELECT
 FROM
     "UserBadge"
 WHERE
-    "userId" = $1
+    "userId" = $1 AND
+    ("expiresAt" IS NULL OR "expiresAt" > NOW())
+ORDER BY "createdAt"
 "#,
     )
     .bind(user.id)
@@ -823,60 +859,42 @@ LIMIT
     }
 }
 
-pub async fn increment_user_coins(connection: &Pool<Postgres>, user_id: &i32, coins: i32) {
-    sqlx::query!(
-        r#"
-UPDATE
-    "User"
-SET
-    "coins" = "coins" + $1
-WHERE id = $2
-"#,
-        coins,
-        user_id
-    )
-    .execute(connection)
-    .await
-    .unwrap_or_default();
-}
-
 pub async fn get_leaderboard(
-    redis: &redis::Client,
+    redis: &ConnectionManager,
     mode: Option<OsuMode>,
     offset: Option<i32>,
     limit: Option<i32>,
-) -> Vec<i32> {
-    let mut connection = redis
-        .get_connection()
-        .expect("Failed to open redis connection.");
-
-    let resp: Result<Vec<i32>, redis::RedisError> = connection.zrevrange(
-        format!(
-            "leaderboard:{}:performance",
-            mode.unwrap_or(OsuMode::Osu).to_osu()
-        ),
-        offset.unwrap_or(0) as isize,
-        (limit.unwrap_or(50) - 1) as isize,
-    );
-
-    resp.unwrap_or_default()
+) -> Result<Vec<i32>, OsuServerError> {
+    redis
+        .clone()
+        .zrevrange(
+            format!(
+                "leaderboard:{}:performance",
+                mode.unwrap_or(OsuMode::Osu).to_osu()
+            ),
+            offset.unwrap_or(0) as isize,
+            (limit.unwrap_or(50) - 1) as isize,
+        )
+        .await
+        .map_err(redis_error)
 }
 
-pub async fn get_leaderboard_count(redis: &redis::Client, mode: Option<OsuMode>) -> i32 {
-    let mut connection = redis
-        .get_connection()
-        .expect("Failed to open redis connection.");
-
-    let resp: Result<i32, redis::RedisError> = connection.zcount(
-        format!(
-            "leaderboard:{}:performance",
-            mode.unwrap_or(OsuMode::Osu).to_osu()
-        ),
-        "-inf",
-        "+inf",
-    );
-
-    resp.unwrap_or(0)
+pub async fn get_leaderboard_count(
+    redis: &ConnectionManager,
+    mode: Option<OsuMode>,
+) -> Result<i32, OsuServerError> {
+    redis
+        .clone()
+        .zcount(
+            format!(
+                "leaderboard:{}:performance",
+                mode.unwrap_or(OsuMode::Osu).to_osu()
+            ),
+            "-inf",
+            "+inf",
+        )
+        .await
+        .map_err(redis_error)
 }
 
 pub async fn get_usersats_many(
@@ -980,10 +998,6 @@ WHERE
     }
 }
 
-pub fn is_user_manager(user: &User) -> bool {
-    user.permissions & 1 > 0
-}
-
 pub async fn get_punishment_by_id(connection: &Pool<Postgres>, id: String) -> Option<Punishment> {
     sqlx::query_as(r#"SELECT * FROM "Punishment" WHERE id = $1 "#)
         .bind(id)
@@ -993,12 +1007,14 @@ pub async fn get_punishment_by_id(connection: &Pool<Postgres>, id: String) -> Op
 }
 
 pub async fn restrict_user(connection: &Pool<Postgres>, user_id: i32) {
-    let e = sqlx::query!(
-        r#"UPDATE "User" SET "permissions" = 8 WHERE id = $1"#,
-        user_id
-    )
-    .execute(connection)
-    .await;
+    //Dropping pending verification flag, otherwise restricted new accounts look unverified
+    let e =
+        sqlx::query(r#"UPDATE "User" SET "permissions" = $2, flags = flags & ~$3 WHERE id = $1"#)
+            .bind(user_id)
+            .bind(RESTRICTED_PERMISSION)
+            .bind(PENDING_VERIFICATION_FLAG)
+            .execute(connection)
+            .await;
 
     match e {
         Ok(_) => {}
@@ -1009,13 +1025,58 @@ pub async fn restrict_user(connection: &Pool<Postgres>, user_id: i32) {
 }
 
 pub async fn unrestrict_user(connection: &Pool<Postgres>, user_id: i32) {
-    sqlx::query!(
-        r#"UPDATE "User" SET "permissions" = 0 WHERE id = $1"#,
-        user_id
+    sqlx::query(r#"UPDATE "User" SET "permissions" = "permissions" & ~$2 WHERE id = $1"#)
+        .bind(user_id)
+        .bind(RESTRICTED_PERMISSION)
+        .execute(connection)
+        .await
+        .unwrap_or_default();
+}
+
+pub async fn get_expired_punishments(
+    connection: &Pool<Postgres>,
+) -> Result<Vec<Punishment>, OsuServerError> {
+    sqlx::query_as(
+        r#"SELECT * FROM "Punishment" WHERE expires AND "expiresAt" <= NOW() AND "liftedAt" IS NULL ORDER BY "expiresAt""#,
     )
-    .execute(connection)
+    .fetch_all(connection)
     .await
-    .unwrap_or_default();
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch punishments: {}", error)))
+}
+
+pub async fn has_active_punishment(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    punishment_type: &str,
+) -> Result<bool, OsuServerError> {
+    sqlx::query_scalar(
+        r#"
+SELECT EXISTS (
+    SELECT 1 FROM "Punishment"
+    WHERE
+        "appliedTo" = $1 AND
+        "punishmentType" = $2 AND
+        "liftedAt" IS NULL AND
+        (NOT expires OR "expiresAt" > NOW())
+)
+"#,
+    )
+    .bind(user_id)
+    .bind(punishment_type)
+    .fetch_one(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to fetch punishments: {}", error)))
+}
+
+pub async fn mark_punishment_lifted(connection: &Pool<Postgres>, id: &str) {
+    let result = sqlx::query(r#"UPDATE "Punishment" SET "liftedAt" = NOW() WHERE id = $1"#)
+        .bind(id)
+        .execute(connection)
+        .await;
+
+    if let Err(error) = result {
+        error!("Failed to mark punishment {} as lifted: {}", id, error);
+    }
 }
 
 pub async fn get_silenced_until(connection: &Pool<Postgres>, user_id: i32) -> i64 {
@@ -1221,10 +1282,10 @@ WHERE
 
 pub async fn recalculate_user_stats(
     connection: &Pool<Postgres>,
-    redis: &redis::Client,
+    redis: &ConnectionManager,
     user: &User,
     mode: &OsuMode,
-) {
+) -> Result<(), OsuServerError> {
     let scores: Result<Vec<Score>, sqlx::Error> = sqlx::query_as(
         r#"
     SELECT
@@ -1250,7 +1311,7 @@ ORDER BY "performance" DESC
 
     if let Err(error) = scores {
         error!("Failed to get scores: {}", error);
-        return;
+        return Err(OsuServerError::Internal(error.to_string()));
     }
 
     let scores = scores.unwrap();
@@ -1289,36 +1350,46 @@ ORDER BY "performance" DESC
     .unwrap_or_default();
 
     //Updating leaderboard in redis
+    let mut redis = redis.clone();
 
-    match redis.get_connection() {
-        Ok(mut connection) => match is_restricted(user).await {
-            true => {
-                let _: Result<i32, redis::RedisError> = connection.zrem(
+    match is_restricted(user).await {
+        true => {
+            let _: i32 = redis
+                .zrem(
                     format!("leaderboard:{}:performance", mode.to_osu()),
                     user.id.to_string(),
-                );
-                let _: Result<i32, redis::RedisError> = connection.zrem(
+                )
+                .await
+                .map_err(redis_error)?;
+            let _: i32 = redis
+                .zrem(
                     format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
                     user.id.to_string(),
-                );
-            }
-            false => {
-                let _: Result<i32, redis::RedisError> = connection.zadd(
+                )
+                .await
+                .map_err(redis_error)?;
+        }
+        false => {
+            let _: i32 = redis
+                .zadd(
                     format!("leaderboard:{}:performance", mode.to_osu()),
                     user.id.to_string(),
                     pp as i64,
-                );
-                let _: Result<i32, redis::RedisError> = connection.zadd(
+                )
+                .await
+                .map_err(redis_error)?;
+            let _: i32 = redis
+                .zadd(
                     format!("leaderboard:{}:performance:{}", mode.to_osu(), user.country),
                     user.id.to_string(),
                     pp as i64,
-                );
-            }
-        },
-        Err(err) => {
-            error!("Failed to update rankings in redis: {}", err);
+                )
+                .await
+                .map_err(redis_error)?;
         }
     }
+
+    Ok(())
 }
 
 pub async fn send_message_announcement(
@@ -1344,6 +1415,31 @@ pub async fn send_message_announcement(
     }
 }
 
+//Mio private message, returns false when target is offline
+pub async fn send_bancho_pm(config: &RunConfiguration, target: &str, message: String) -> bool {
+    let response = reqwest::Client::new()
+        .post(format!(
+            "https://c.{}/api/v2/bancho/notification",
+            config.server_url
+        ))
+        .json(&json!({
+            "message": message,
+            "message_type": "pm",
+            "target": target,
+            "key": config.token_hmac_secret
+        }))
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => response.status().is_success(),
+        Err(error) => {
+            error!("Failed to send request: {}", error);
+            false
+        }
+    }
+}
+
 pub async fn remove_friend(connection: &Pool<Postgres>, user_id: &i32, friend_id: &i32) {
     sqlx::query("DELETE FROM \"RelationShips\" WHERE \"userId\" = $1 AND \"friendId\" = $2")
         .bind(user_id)
@@ -0,0 +1,311 @@
+use redis::AsyncCommands;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use tracing::info;
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    permission_utils::{has_capability, Capabilities},
+    score_utils::OsuServerError,
+    user_utils::{invalidate_auth_cache, is_restricted, send_bancho_message, to_safe},
+};
+
+const MIN_LENGTH: usize = 2;
+//Longest name the client is able to display
+const MAX_LENGTH: usize = 15;
+
+#[derive(Debug)]
+pub enum UsernameError {
+    TooShort,
+    TooLong,
+    InvalidCharacters,
+    Reserved,
+    Taken,
+    Unchanged,
+    NoChangesRemaining,
+    Restricted,
+    Internal(OsuServerError),
+}
+
+impl UsernameError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            UsernameError::TooShort => "Username is too short.",
+            UsernameError::TooLong => "Username is too long.",
+            UsernameError::InvalidCharacters => {
+                "Username may only contain letters, numbers, spaces and - _ [ ]."
+            }
+            UsernameError::Reserved => "This username is reserved.",
+            UsernameError::Taken => "This username is already taken.",
+            UsernameError::Unchanged => "This is already your username.",
+            UsernameError::NoChangesRemaining => "You don't have any username changes left.",
+            UsernameError::Restricted => "Restricted accounts can't change their username.",
+            UsernameError::Internal(_) => "Internal server error.",
+        }
+    }
+}
+
+impl From<OsuServerError> for UsernameError {
+    fn from(error: OsuServerError) -> Self {
+        UsernameError::Internal(error)
+    }
+}
+
+fn database_error(error: sqlx::Error) -> OsuServerError {
+    OsuServerError::Internal(format!("Username query failed: {}", error))
+}
+
+//Shape of the name only, availability is checked by is_username_available.
+//Reserved names are comma separated, like RESERVED_USERNAMES
+pub fn validate_username_format(username: &str, reserved: &str) -> Result<(), UsernameError> {
+    let length = username.chars().count();
+
+    if length < MIN_LENGTH {
+        return Err(UsernameError::TooShort);
+    }
+
+    if length > MAX_LENGTH {
+        return Err(UsernameError::TooLong);
+    }
+
+    let valid_characters = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '[' | ']'));
+
+    //Space and underscore are the same in safe name, mixing them only confuses people
+    if !valid_characters
+        || username.starts_with(' ')
+        || username.ends_with(' ')
+        || username.contains("  ")
+        || (username.contains(' ') && username.contains('_'))
+    {
+        return Err(UsernameError::InvalidCharacters);
+    }
+
+    let safe = to_safe(username);
+
+    if reserved
+        .split(',')
+        .any(|reserved| to_safe(reserved.trim()) == safe)
+    {
+        return Err(UsernameError::Reserved);
+    }
+
+    Ok(())
+}
+
+//Names released by other users stay on hold for USERNAME_HOLD_DAYS
+pub async fn is_username_available(
+    connection: &Pool<Postgres>,
+    username: &str,
+    user_id: Option<i32>,
+    hold_days: i32,
+) -> Result<bool, OsuServerError> {
+    let taken: bool = sqlx::query_scalar(
+        r#"
+SELECT
+    EXISTS (SELECT 1 FROM "User" WHERE "usernameSafe" = $1 AND id IS DISTINCT FROM $2) OR
+    EXISTS (
+        SELECT 1 FROM "UsernameHistory"
+        WHERE
+            "usernameSafe" = $1 AND
+            "userId" IS DISTINCT FROM $2 AND
+            "changedAt" > NOW() - make_interval(days => $3)
+    )
+"#,
+    )
+    .bind(to_safe(username))
+    .bind(user_id)
+    .bind(hold_days)
+    .fetch_one(connection)
+    .await
+    .map_err(database_error)?;
+
+    Ok(!taken)
+}
+
+pub async fn change_username(
+    ctx: &Context,
+    user: &User,
+    username: &str,
+) -> Result<User, UsernameError> {
+    let username = username.trim();
+
+    if is_restricted(user).await {
+        return Err(UsernameError::Restricted);
+    }
+
+    validate_username_format(username, &ctx.config.reserved_usernames)?;
+
+    let safe = to_safe(username);
+
+    //Changing only the case keeps the same safe name
+    if username == user.username {
+        return Err(UsernameError::Unchanged);
+    }
+
+    if safe != user.username_safe
+        && !is_username_available(
+            &ctx.pool,
+            username,
+            Some(user.id),
+            ctx.config.username_hold_days,
+        )
+        .await?
+    {
+        return Err(UsernameError::Taken);
+    }
+
+    //Supporters change names freely, everyone else spends a change bought in the shop
+    let free = has_capability(ctx, user, Capabilities::SUPPORTER).await;
+
+    let mut transaction = ctx.pool.begin().await.map_err(database_error)?;
+
+    let remaining: i32 = sqlx::query_scalar(
+        r#"SELECT "usernameChangesRemaining" FROM "User" WHERE id = $1 FOR UPDATE"#,
+    )
+    .bind(user.id)
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    if !free && remaining < 1 {
+        transaction.rollback().await.map_err(database_error)?;
+        return Err(UsernameError::NoChangesRemaining);
+    }
+
+    let updated = sqlx::query_as::<_, User>(
+        r#"
+UPDATE "User"
+SET
+    username = $2,
+    "usernameSafe" = $3,
+    "oldUsernames" = array_append(COALESCE("oldUsernames", '{}'), username),
+    "usernameChangesRemaining" = "usernameChangesRemaining" - $4
+WHERE id = $1
+RETURNING *
+"#,
+    )
+    .bind(user.id)
+    .bind(username)
+    .bind(&safe)
+    .bind(if free { 0 } else { 1 })
+    .fetch_one(&mut *transaction)
+    .await;
+
+    //Unique index on "usernameSafe" catches a name taken in the meantime
+    let updated = match updated {
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+            return Err(UsernameError::Taken)
+        }
+        updated => updated.map_err(database_error)?,
+    };
+
+    sqlx::query(
+        r#"INSERT INTO "UsernameHistory" ("userId", username, "usernameSafe") VALUES ($1, $2, $3)"#,
+    )
+    .bind(user.id)
+    .bind(&user.username)
+    .bind(&user.username_safe)
+    .execute(&mut *transaction)
+    .await
+    .map_err(database_error)?;
+
+    transaction.commit().await.map_err(database_error)?;
+
+    let _: Result<i32, redis::RedisError> = ctx
+        .redis
+        .clone()
+        .del(&[
+            format!("user:{}:id", user.username_safe),
+            format!("user:{}:id", safe),
+        ])
+        .await;
+    invalidate_auth_cache(&ctx.redis, user.id).await;
+
+    info!(
+        "{} ({}) is now known as {}",
+        user.username, user.id, username
+    );
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id: Some(user.id),
+            action: "user.username_changed",
+            target_id: Some(user.id),
+            before: Some(json!({ "username": user.username })),
+            after: Some(json!({ "username": updated.username })),
+            note: "",
+        },
+    )
+    .await;
+
+    send_bancho_message(&user.id, "user:renamed".to_string(), None).await;
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESERVED: &str = "mio,peppy,bancho,banchobot,admin,moderator,system";
+
+    fn validate(username: &str) -> Result<(), UsernameError> {
+        validate_username_format(username, RESERVED)
+    }
+
+    #[test]
+    fn accepts_regular_names() {
+        for username in [
+            "ab",
+            "Cookiezi",
+            "Player One",
+            "[Mio]_fan-2",
+            "a23456789012345",
+        ] {
+            assert!(validate(username).is_ok(), "{} was rejected", username);
+        }
+    }
+
+    #[test]
+    fn rejects_names_by_length() {
+        assert!(matches!(validate("a"), Err(UsernameError::TooShort)));
+        assert!(matches!(
+            validate("a234567890123456"),
+            Err(UsernameError::TooLong)
+        ));
+    }
+
+    #[test]
+    fn rejects_confusing_names() {
+        for username in [
+            " leading",
+            "trailing ",
+            "double  space",
+            "mixed a_b c",
+            "emoji🙂",
+            "dot.name",
+        ] {
+            assert!(
+                matches!(validate(username), Err(UsernameError::InvalidCharacters)),
+                "{} was accepted",
+                username
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_names_in_any_form() {
+        for username in ["Mio", "BanchoBot", "ADMIN"] {
+            assert!(
+                matches!(validate(username), Err(UsernameError::Reserved)),
+                "{} was accepted",
+                username
+            );
+        }
+    }
+}
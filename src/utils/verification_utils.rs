@@ -0,0 +1,289 @@
+use rand::Rng;
+use redis::AsyncCommands;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+use webhook::client::WebhookClient;
+
+use crate::{context::Context, db::user::User};
+
+use super::{
+    audit_utils::{insert_audit_log, AuditRecord},
+    hwid_utils::{HwidAction, HwidHit},
+    lockout_utils::{clear_failed_attempts, register_failed_attempt},
+    score_utils::OsuServerError,
+    user_utils::redis_error,
+};
+
+const CODE_TTL: u64 = 60 * 60 * 24;
+//Wrong guesses before the code is thrown away, next login issues a new one
+const CODE_ATTEMPT_LIMIT: i64 = 5;
+//Other accounts logged in from the same address within this window hold verification
+const IP_LOOKBACK_DAYS: i32 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationOutcome {
+    Verified,
+    CodeRequired(String),
+    //Left for staff review, holds reason
+    Held(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeConfirmation {
+    Confirmed,
+    Invalid,
+    //No code to check against, it expired or was thrown away after too many guesses
+    Expired,
+}
+
+fn code_key(user_id: i32) -> String {
+    format!("verification:{}:code", user_id)
+}
+
+fn held_key(user_id: i32) -> String {
+    format!("verification:{}:held", user_id)
+}
+
+pub async fn find_ip_conflicts(
+    connection: &Pool<Postgres>,
+    user_id: i32,
+    ip: &str,
+) -> Result<Vec<i32>, OsuServerError> {
+    sqlx::query_scalar(
+        r#"
+SELECT DISTINCT
+    "userId"
+FROM
+    "LoginHistory"
+WHERE
+    ip = $1 AND
+    "userId" != $2 AND
+    "createdAt" > NOW() - make_interval(days => $3)
+"#,
+    )
+    .bind(ip)
+    .bind(user_id)
+    .bind(IP_LOOKBACK_DAYS)
+    .fetch_all(connection)
+    .await
+    .map_err(|error| OsuServerError::Internal(format!("Failed to find ip conflicts: {}", error)))
+}
+
+//Runs on bancho login of accounts still pending verification
+pub async fn verify_first_login(
+    ctx: &Context,
+    user: &User,
+    ip: Option<&str>,
+    hwid_hits: &[HwidHit],
+) -> Result<VerificationOutcome, OsuServerError> {
+    let hwid_users = hwid_hits
+        .iter()
+        .filter(|hit| matches!(hit.action, HwidAction::Flag | HwidAction::Restrict))
+        .flat_map(|hit| hit.users.iter().map(|user| user.id))
+        .collect::<Vec<i32>>();
+
+    if !hwid_users.is_empty() {
+        return hold_verification(ctx, user, "hwid", hwid_users).await;
+    }
+
+    if let Some(ip) = ip {
+        let ip_users = find_ip_conflicts(&ctx.pool, user.id, ip).await?;
+
+        if !ip_users.is_empty() {
+            return hold_verification(ctx, user, "ip", ip_users).await;
+        }
+    }
+
+    if ctx.config.verification_require_code {
+        return Ok(VerificationOutcome::CodeRequired(
+            get_verification_code(ctx, user.id).await?,
+        ));
+    }
+
+    complete_verification(ctx, user, None, "Automatic verification on first login").await;
+
+    Ok(VerificationOutcome::Verified)
+}
+
+async fn get_verification_code(ctx: &Context, user_id: i32) -> Result<String, OsuServerError> {
+    let mut connection = ctx.redis.clone();
+
+    let code: Option<String> = connection
+        .get(code_key(user_id))
+        .await
+        .map_err(redis_error)?;
+
+    if let Some(code) = code {
+        return Ok(code);
+    }
+
+    let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+
+    let _: () = connection
+        .set_ex(code_key(user_id), &code, CODE_TTL)
+        .await
+        .map_err(redis_error)?;
+
+    Ok(code)
+}
+
+pub async fn confirm_verification_code(
+    ctx: &Context,
+    user: &User,
+    code: &str,
+) -> Result<CodeConfirmation, OsuServerError> {
+    let mut connection = ctx.redis.clone();
+
+    //Held accounts wait for staff even if a code was issued earlier
+    let held: bool = connection
+        .exists(held_key(user.id))
+        .await
+        .map_err(redis_error)?;
+
+    let expected: Option<String> = connection
+        .get(code_key(user.id))
+        .await
+        .map_err(redis_error)?;
+
+    let expected = match expected {
+        Some(expected) if !held => expected,
+        _ => return Ok(CodeConfirmation::Expired),
+    };
+
+    if expected != code.trim() {
+        let attempts = register_failed_attempt(
+            &ctx.redis,
+            "verification",
+            &user.id.to_string(),
+            CODE_TTL as i64,
+        )
+        .await?;
+
+        if attempts < CODE_ATTEMPT_LIMIT {
+            return Ok(CodeConfirmation::Invalid);
+        }
+
+        info!(
+            "Verification code of {} ({}) invalidated after {} attempts",
+            user.username, user.id, attempts
+        );
+
+        let _: i32 = connection
+            .del(code_key(user.id))
+            .await
+            .map_err(redis_error)?;
+        clear_failed_attempts(&ctx.redis, "verification", &user.id.to_string()).await;
+
+        return Ok(CodeConfirmation::Expired);
+    }
+
+    complete_verification(ctx, user, Some(user.id), "Confirmed code sent in game").await;
+
+    Ok(CodeConfirmation::Confirmed)
+}
+
+pub async fn complete_verification(ctx: &Context, user: &User, actor_id: Option<i32>, note: &str) {
+    ctx.users.clear_pending_verification(user.id).await;
+
+    let _: Result<i32, redis::RedisError> = ctx
+        .redis
+        .clone()
+        .del(&[code_key(user.id), held_key(user.id)])
+        .await;
+
+    clear_failed_attempts(&ctx.redis, "verification", &user.id.to_string()).await;
+
+    info!("User {} ({}) has been verified", user.username, user.id);
+
+    insert_audit_log(
+        &ctx.pool,
+        AuditRecord {
+            actor_id,
+            action: "verification.completed",
+            target_id: Some(user.id),
+            before: Some(json!({ "permissions": user.permissions, "flags": user.flags })),
+            after: Some(json!({ "permissions": 0, "flags": 0 })),
+            note,
+        },
+    )
+    .await;
+}
+
+async fn hold_verification(
+    ctx: &Context,
+    user: &User,
+    reason: &str,
+    linked_users: Vec<i32>,
+) -> Result<VerificationOutcome, OsuServerError> {
+    //Reporting held account only once a day, not on every relog
+    let first: bool = ctx
+        .redis
+        .clone()
+        .set_nx(held_key(user.id), reason)
+        .await
+        .map_err(redis_error)?;
+
+    if first {
+        let _: Result<bool, redis::RedisError> = ctx
+            .redis
+            .clone()
+            .expire(held_key(user.id), CODE_TTL as i64)
+            .await;
+
+        insert_audit_log(
+            &ctx.pool,
+            AuditRecord {
+                actor_id: None,
+                action: "verification.held",
+                target_id: Some(user.id),
+                after: Some(json!({ "reason": reason, "linkedUserIds": linked_users })),
+                note: "Verification left for staff review",
+                ..Default::default()
+            },
+        )
+        .await;
+
+        held_alert(ctx, user, reason, &linked_users).await;
+    }
+
+    Ok(VerificationOutcome::Held(reason.to_string()))
+}
+
+async fn held_alert(ctx: &Context, user: &User, reason: &str, linked_users: &[i32]) {
+    if let Some(webhook) = ctx.config.alert_discord_webhook.clone() {
+        let client = WebhookClient::new(webhook.as_str());
+
+        let formatted_report = format!(
+            "Reason: `{}`\n{}",
+            reason,
+            linked_users
+                .iter()
+                .map(|id| format!("https://{}/users/{}", ctx.config.server_url, id))
+                .collect::<Vec<String>>()
+                .join("\n")
+        );
+
+        if let Err(error) = client
+            .send(|message| {
+                message
+                    .content("New account verification held!")
+                    .embed(|embed| {
+                        embed
+                            .author(
+                                user.username.as_str(),
+                                Some(format!(
+                                    "https://{}/users/{}",
+                                    ctx.config.server_url, user.id
+                                )),
+                                Some(format!("https://a.{}/{}", ctx.config.server_url, user.id)),
+                            )
+                            .description(formatted_report.as_str())
+                    })
+            })
+            .await
+        {
+            error!("Failed to send verification alert: {}", error);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 pub mod other;
 pub mod scores;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{routing::get, Extension, Router};
 use tower::ServiceBuilder;
@@ -36,5 +36,10 @@ pub async fn serve(ctx: Context) {
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
         .unwrap();
-    axum::serve(listener, router).await.unwrap();
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -12,7 +12,12 @@ use url_builder::URLBuilder;
 
 use crate::{
     context::Context,
-    utils::{beatmap_utils::OnlineBeatmap, http_utils::OsuMode, user_utils::validate_auth},
+    utils::{
+        beatmap_utils::OnlineBeatmap,
+        http_utils::OsuMode,
+        ip_utils::ClientIp,
+        lockout_utils::{check_login, LoginCheck},
+    },
     web::scores::submission::BeatmapStatus,
 };
 
@@ -90,9 +95,10 @@ fn ranked_status_to_string(status: u8) -> &'static str {
 
 pub async fn search_beatmaps(
     Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<BeatmapsQuery>,
 ) -> Response {
-    if !validate_auth(&ctx.redis, &ctx.pool, query.u, query.h).await {
+    if check_login(&ctx, &query.u, &query.h, ip.as_deref(), "direct").await != LoginCheck::Ok {
         return Response::builder().body(Body::from("error: pass")).unwrap();
     }
 
@@ -177,9 +183,10 @@ pub async fn search_beatmaps(
 
 pub async fn search_beatmap_set(
     Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<BeatmapSetQuery>,
 ) -> Response {
-    if !validate_auth(&ctx.redis, &ctx.pool, query.u, query.h).await {
+    if check_login(&ctx, &query.u, &query.h, ip.as_deref(), "direct").await != LoginCheck::Ok {
         return Response::builder().body(Body::from("error: pass")).unwrap();
     }
 
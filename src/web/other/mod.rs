@@ -10,27 +10,38 @@ use axum::{
 use tokio_util::io::ReaderStream;
 
 use crate::{
-    context::Context, utils::general_utils::random_string, utils::user_utils::validate_auth,
+    context::Context,
+    utils::{
+        general_utils::random_string,
+        ip_utils::ClientIp,
+        lockout_utils::{check_login, LoginCheck},
+    },
     web::scores::submission::ParsedMultipart,
 };
 
-use self::direct::{download_osz, search_beatmap_set, search_beatmaps};
+use self::{
+    direct::{download_osz, search_beatmap_set, search_beatmaps},
+    register::register,
+};
 
 mod direct;
+mod register;
 
 async fn upload_screenshot(
     Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     multipart: Multipart,
 ) -> Response {
     let form_data: ParsedMultipart = ParsedMultipart::from_multipart(multipart).await;
-
-    if !validate_auth(
-        &ctx.redis,
-        &ctx.pool,
-        form_data.get_field::<String>("u").unwrap(),
-        form_data.get_field::<String>("p").unwrap(),
+    if check_login(
+        &ctx,
+        &form_data.get_field::<String>("u").unwrap(),
+        &form_data.get_field::<String>("p").unwrap(),
+        ip.as_deref(),
+        "screenshot",
     )
     .await
+        != LoginCheck::Ok
     {
         return Response::builder()
             .status(400)
@@ -77,4 +88,5 @@ pub fn serve() -> Router {
         .route("/web/osu-screenshot.php", post(upload_screenshot))
         .route("/ss/:file", get(view_screenshot))
         .route("/d/:id", get(download_osz))
+        .route("/users", post(register))
 }
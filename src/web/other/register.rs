@@ -0,0 +1,138 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Multipart, Request},
+    http::header::CONTENT_TYPE,
+    response::Response,
+    Extension, Form,
+};
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    context::Context,
+    utils::{
+        ip_utils::ClientIp,
+        registration_utils::{
+            create_account, validate_registration, Registration, RegistrationErrors,
+        },
+    },
+    web::scores::submission::ParsedMultipart,
+};
+
+//Client renders every message listed under its input field
+fn form_error(errors: RegistrationErrors) -> Response {
+    Response::builder()
+        .status(400)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "form_error": { "user": errors } }).to_string(),
+        ))
+        .unwrap()
+}
+
+fn internal_error() -> Response {
+    Response::builder()
+        .status(500)
+        .body(Body::from("Internal server error"))
+        .unwrap()
+}
+
+//Older clients post multipart, newer ones url encoded form
+async fn read_form(request: Request) -> Option<HashMap<String, String>> {
+    let is_multipart = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    if is_multipart {
+        let multipart = Multipart::from_request(request, &()).await.ok()?;
+        let form_data = ParsedMultipart::from_multipart(multipart).await;
+
+        return Some(
+            [
+                "user[username]",
+                "user[user_email]",
+                "user[password]",
+                "check",
+            ]
+            .into_iter()
+            .filter_map(|name| Some((name.to_string(), form_data.get_field(name)?)))
+            .collect(),
+        );
+    }
+
+    Form::<HashMap<String, String>>::from_request(request, &())
+        .await
+        .ok()
+        .map(|Form(form)| form)
+}
+
+pub async fn register(
+    Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+) -> Response {
+    let form = read_form(request).await;
+
+    if form.is_none() {
+        return Response::builder()
+            .status(400)
+            .body(Body::from("Invalid form"))
+            .unwrap();
+    }
+
+    let mut form = form.unwrap();
+
+    let registration = Registration {
+        username: form
+            .remove("user[username]")
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        email: form
+            .remove("user[user_email]")
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        password: form.remove("user[password]").unwrap_or_default(),
+    };
+
+    let errors = validate_registration(&ctx, &registration).await;
+
+    if let Err(error) = errors {
+        error!("Failed to validate registration: {:?}", error);
+        return internal_error();
+    }
+
+    let errors = errors.unwrap();
+
+    if !errors.is_empty() {
+        return form_error(errors);
+    }
+
+    //Client validates the form with check=1 first and only then submits it
+    if form.get("check").map(String::as_str) != Some("0") {
+        return Response::builder()
+            .status(200)
+            .body(Body::from("ok"))
+            .unwrap();
+    }
+
+    match create_account(&ctx, &registration, ip.as_deref()).await {
+        Ok(Some(_)) => Response::builder()
+            .status(200)
+            .body(Body::from("ok"))
+            .unwrap(),
+        Ok(None) => form_error(RegistrationErrors {
+            username: vec!["This username or email is already taken.".to_string()],
+            ..Default::default()
+        }),
+        Err(error) => {
+            error!("Failed to create account: {:?}", error);
+            internal_error()
+        }
+    }
+}
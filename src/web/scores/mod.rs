@@ -16,10 +16,10 @@ use tracing::{debug, error};
 use crate::{
     context::Context,
     utils::{
-        beatmap_utils::{get_beatmap_by_hash, get_online_beatmap_by_checksum},
+        beatmap_utils::get_online_beatmap_by_checksum,
         http_utils::{OsuMode, ScoreRequestQuery},
-        score_utils::{get_beatmap_leaderboard, get_user_best},
-        user_utils::{get_user_id, validate_auth},
+        ip_utils::ClientIp,
+        lockout_utils::{check_login, LoginCheck},
     },
     web::scores::submission::ScoreStatus,
 };
@@ -36,9 +36,10 @@ pub struct ReplayRequestQuery {
 
 async fn get_score_replay(
     Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<ReplayRequestQuery>,
 ) -> Vec<u8> {
-    if !validate_auth(&ctx.redis, &ctx.pool, query.u.clone(), query.h.to_string()).await {
+    if check_login(&ctx, &query.u, &query.h, ip.as_deref(), "replay").await != LoginCheck::Ok {
         return "error: pass".to_string().into();
     }
 
@@ -86,6 +87,7 @@ async fn update_beatmap(Path(file): Path<String>) -> Response {
 
 async fn get_scores(
     Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<ScoreRequestQuery>,
 ) -> Response {
     let ScoreRequestQuery {
@@ -95,12 +97,12 @@ async fn get_scores(
         beatmap_hash,
         mode,
         mods,
-        leaderboard_type,
+        leaderboard_type: _,
         set_id: _,
         filename,
     } = query;
 
-    if !validate_auth(&ctx.redis, &ctx.pool, username.clone(), password).await {
+    if check_login(&ctx, &username, &password, ip.as_deref(), "getscores").await != LoginCheck::Ok {
         return Response::builder().body(Body::from("error: pass")).unwrap();
     }
 
@@ -108,7 +110,7 @@ async fn get_scores(
         return Response::builder().body(Body::from("error: pass")).unwrap();
     }
 
-    let user_id = get_user_id(&ctx.redis, &ctx.pool, username.clone()).await;
+    let user_id = ctx.users.get_id(&username).await;
 
     if user_id.is_none() {
         return Response::builder().body(Body::from("error: pass")).unwrap();
@@ -122,7 +124,7 @@ async fn get_scores(
         mode.clone() as u8
     });
 
-    let mut beatmap = get_beatmap_by_hash(&ctx.pool, beatmap_hash.clone()).await;
+    let mut beatmap = ctx.beatmaps.get_by_hash(beatmap_hash.clone()).await;
 
     //Ensuring maybe there is update for this beatmap
 
@@ -154,7 +156,7 @@ async fn get_scores(
 
         let online_beatmap = online_beatmap.unwrap();
 
-        online_beatmap.clone().insert_in_db(&ctx.pool).await;
+        ctx.beatmaps.insert(online_beatmap.clone()).await;
 
         beatmap = Ok(Some(online_beatmap.clone()));
     }
@@ -162,16 +164,16 @@ async fn get_scores(
     let beatmap = beatmap.clone().unwrap().unwrap();
 
     let status = ScoreStatus::find_suitable_best_status_for_beatmap(beatmap.clone().status.into());
-    let user_best = get_user_best(
-        &ctx.pool,
-        beatmap_hash.clone(),
-        user_id,
-        playmode.clone(),
-        mods,
-        leaderboard_type,
-        Some(status.to_db()),
-    )
-    .await;
+    let user_best = ctx
+        .scores
+        .get_user_best(
+            beatmap_hash.clone(),
+            user_id,
+            playmode.clone(),
+            mods,
+            Some(status.to_db()),
+        )
+        .await;
 
     if let Err(error) = user_best {
         error!("User best error: {:#?}", error);
@@ -183,17 +185,18 @@ async fn get_scores(
     debug!("{:#?}", user_best);
 
     let mut response = Builder::default();
-    let leaderboard = get_beatmap_leaderboard(
-        &ctx.pool,
-        beatmap_hash.clone(),
-        playmode.clone(),
-        mods,
-        Some(
-            ScoreStatus::find_suitable_best_status_for_beatmap(beatmap.clone().status.into())
-                .to_db(),
-        ),
-    )
-    .await;
+    let leaderboard = ctx
+        .scores
+        .get_beatmap_leaderboard(
+            beatmap_hash.clone(),
+            playmode.clone(),
+            mods,
+            Some(
+                ScoreStatus::find_suitable_best_status_for_beatmap(beatmap.clone().status.into())
+                    .to_db(),
+            ),
+        )
+        .await;
 
     if let Err(error) = leaderboard {
         error!("Leaderboard error: {:#?}", error);
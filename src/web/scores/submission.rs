@@ -4,24 +4,29 @@ use axum::{body::Bytes, extract::Multipart, Extension};
 
 use base64::prelude::*;
 use chrono::{NaiveDateTime, Utc};
+use serde_json::json;
 use simple_rijndael::{impls::RijndaelCbc, paddings::ZeroPadding};
-use sqlx::{Pool, Postgres};
 use tokio::fs;
 use tracing::{debug, error, info, warn};
 
 use crate::{
     context::Context,
+    db::user::User,
     utils::{
-        beatmap_utils::{announce_insane_score, get_beatmap_by_hash},
+        activity_utils::{insert_activity, ActivityType},
+        audit_utils::{insert_audit_log, AuditRecord},
+        beatmap_utils::announce_insane_score,
+        beatmap_utils::Beatmap,
         chart::Chart,
         http_utils::OsuMode,
+        ip_utils::ClientIp,
+        lockout_utils::{check_login, LoginCheck},
+        notification_utils::{insert_notification, NewNotification, NotificationKind},
         performance_utils::{calculate_performance_safe, get_pp_cap, is_cap_reached},
-        score_utils::{get_first_place_on_beatmap, get_score_by_id, get_user_best, UserScore},
+        preference_utils::get_user_preferences,
+        score_utils::{UserScore, UserScoreWithBeatmap},
         user_utils::{
-            find_user_by_id_or_username, get_rank, get_user_stats, increase_user_playcount,
-            increase_user_score, insert_user_punishment, is_restricted, recalculate_user_stats,
-            restrict_user, send_bancho_message, send_message_announcement, update_user_max_combo,
-            validate_auth,
+            is_restricted, send_bancho_message, send_bancho_pm, send_message_announcement,
         },
     },
 };
@@ -282,81 +287,13 @@ impl PlayerScore {
 
         None
     }
-
-    pub async fn update_status(&mut self, pool: &Pool<Postgres>, status: ScoreStatus) {
-        if let Some(id) = self.id {
-            sqlx::query(
-                r#"
-                UPDATE "Score" SET "status" = $1 WHERE "id" = $2
-            "#,
-            )
-            .bind(status.to_db())
-            .bind(id)
-            .execute(pool)
-            .await
-            .unwrap_or_default();
-        }
-    }
-
-    pub async fn insert_score_in_db(&self, pool: &Pool<Postgres>, user_id: i32) -> Option<i32> {
-        let id = sqlx::query!(
-            r#"
-            INSERT INTO "Score" (
-                "beatmapChecksum",
-                "playMode",
-                "totalScore",
-                "maxCombo",
-                "count300",
-                "count100",
-                "count50",
-                "countGeKi",
-                "countKatu",
-                "countMiss",
-                "mods",
-                "perfect",
-                "status",
-                "submittedAt",
-                "userId",
-                "performance"
-            )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
-            RETURNING "id"
-        "#,
-            self.beatmap_md5.clone(),
-            128.bitand(self.mods)
-                .eq(&128)
-                .then(|| 4)
-                .unwrap_or(self.playmode as i32),
-            self.total_score,
-            self.max_combo,
-            self.count_300,
-            self.count_100,
-            self.count_50,
-            self.count_geki,
-            self.count_katu,
-            self.count_miss,
-            self.mods,
-            self.perfect,
-            self.status.to_db(),
-            self.submitted_at,
-            user_id,
-            self.performance.unwrap_or(0.0)
-        )
-        .fetch_one(pool)
-        .await;
-
-        if let Err(error) = id {
-            warn!("{:#?}", error);
-            return None;
-        }
-
-        let id = id.unwrap();
-
-        Some(id.id)
-    }
 }
 
-pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipart) -> String {
+pub async fn submit_score(
+    Extension(ctx): Extension<Arc<Context>>,
+    ClientIp(ip): ClientIp,
+    data: Multipart,
+) -> String {
     let form_data = ParsedMultipart::from_multipart(data).await;
 
     let version = form_data.get_field::<String>("osuver");
@@ -400,19 +337,24 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
         .get_field::<String>("pass")
         .unwrap_or("".to_string());
 
-    if !validate_auth(
-        &ctx.redis,
-        &ctx.pool,
+    if check_login(
+        &ctx,
         decrypted_score.player_name.trim_end(),
-        user_password,
+        &user_password,
+        ip.as_deref(),
+        "submission",
     )
     .await
+        != LoginCheck::Ok
     {
         warn!("invalid auth");
         return "error: pass".to_string();
     }
 
-    let beatmap = get_beatmap_by_hash(&ctx.pool, decrypted_score.beatmap_md5.clone()).await;
+    let beatmap = ctx
+        .beatmaps
+        .get_by_hash(decrypted_score.beatmap_md5.clone())
+        .await;
 
     if let Err(error) = beatmap {
         warn!("{:#?}", error);
@@ -432,9 +374,10 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
         .get_field::<String>("x")
         .unwrap_or("0".to_string())
         .eq("1");
-    let user =
-        find_user_by_id_or_username(&ctx.pool, decrypted_score.player_name.trim().to_string())
-            .await;
+    let user = ctx
+        .users
+        .find_by_id_or_username(decrypted_score.player_name.trim().to_string())
+        .await;
 
     if let Err(error) = user {
         warn!("{:#?}", error);
@@ -474,19 +417,19 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
     )
     .await;
 
-    let best_score = get_user_best(
-        &ctx.pool,
-        beatmap.clone().checksum,
-        user.id,
-        osu_mode.clone(),
-        decrypted_score.mods,
-        0,
-        Some(
-            ScoreStatus::find_suitable_best_status_for_beatmap(beatmap.clone().status.into())
-                .to_db(),
-        ),
-    )
-    .await;
+    let best_score = ctx
+        .scores
+        .get_user_best(
+            beatmap.clone().checksum,
+            user.id,
+            osu_mode.clone(),
+            decrypted_score.mods,
+            Some(
+                ScoreStatus::find_suitable_best_status_for_beatmap(beatmap.clone().status.into())
+                    .to_db(),
+            ),
+        )
+        .await;
 
     if let Err(error) = best_score {
         warn!("{:#?}", error);
@@ -524,16 +467,18 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
     }
 
     if current_score.status != ScoreStatus::Failed {
-        if let Some(mut old_score) = old_score {
+        if let Some(old_score) = old_score {
             if performance > old_score.performance.unwrap_or(0.0) {
-                old_score
-                    .update_status(
-                        &ctx.pool,
-                        ScoreStatus::find_suitable_ranked_status_for_beatmap(
-                            beatmap.clone().status.into(),
-                        ),
-                    )
-                    .await;
+                if let Some(old_score_id) = old_score.id {
+                    ctx.scores
+                        .update_status(
+                            old_score_id,
+                            ScoreStatus::find_suitable_ranked_status_for_beatmap(
+                                beatmap.clone().status.into(),
+                            ),
+                        )
+                        .await;
+                }
 
                 current_score.status = ScoreStatus::find_suitable_best_status_for_beatmap(
                     beatmap.clone().status.into(),
@@ -547,13 +492,11 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
         }
     }
 
-    let _old_first_place_score = get_first_place_on_beatmap(
-        &ctx.pool,
-        decrypted_score.beatmap_md5.clone(),
-        osu_mode.clone(),
-    )
-    .await;
-    let score_id = current_score.insert_score_in_db(&ctx.pool, user.id).await;
+    let old_first_place_score = ctx
+        .scores
+        .get_first_place(decrypted_score.beatmap_md5.clone(), osu_mode.clone())
+        .await;
+    let score_id = ctx.scores.insert(&current_score, user.id).await;
 
     if score_id.is_none() {
         warn!("no score id");
@@ -562,14 +505,15 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
 
     let score_id = score_id.unwrap();
 
-    let new_score = get_score_by_id(
-        &ctx.pool,
-        score_id,
-        decrypted_score.beatmap_md5.clone(),
-        &osu_mode,
-        Some(current_score.status.to_db()),
-    )
-    .await;
+    let new_score = ctx
+        .scores
+        .get_by_id(
+            score_id,
+            decrypted_score.beatmap_md5.clone(),
+            &osu_mode,
+            Some(current_score.status.to_db()),
+        )
+        .await;
 
     if let Err(error) = new_score {
         warn!("Score insertion failed {:#?}", error);
@@ -612,18 +556,30 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
         }
         None => {
             if !decrypted_score.failed && !quit {
-                insert_user_punishment(
+                ctx.punishments
+                    .insert(
+                        "CRITICAL".to_string(),
+                        1,
+                        user.id,
+                        "RESTRICTION".to_string(),
+                        false,
+                        None,
+                        "Lia: Hasn't sent a replay file.".to_string(),
+                    )
+                    .await;
+                ctx.users.restrict(user.id).await;
+                insert_audit_log(
                     &ctx.pool,
-                    "CRITICAL".to_string(),
-                    1,
-                    user.id,
-                    "RESTRICTION".to_string(),
-                    false,
-                    None,
-                    "Lia: Hasn't sent a replay file.".to_string(),
+                    AuditRecord {
+                        actor_id: None,
+                        action: "punishment.auto_restrict",
+                        target_id: Some(user.id),
+                        before: Some(json!({ "permissions": user.permissions })),
+                        after: Some(json!({ "permissions": 8 })),
+                        note: "Lia: Hasn't sent a replay file.",
+                    },
                 )
                 .await;
-                restrict_user(&ctx.pool, user.id).await;
                 send_bancho_message(&user.id, "user:restricted".to_string(), None).await;
             }
         }
@@ -631,37 +587,37 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
 
     //Everything works fine, prcessing everything
     current_score.id = Some(score_id);
-    let stats_before = get_user_stats(&ctx.pool, &user.id, &osu_mode)
-        .await
-        .unwrap();
+    let stats_before = ctx.users.get_stats(user.id, &osu_mode).await.unwrap();
 
-    increase_user_score(
-        &ctx.pool,
-        osu_mode.clone(),
-        decrypted_score.total_score as i64,
-        &user.id,
-    )
-    .await;
-    increase_user_playcount(&ctx.pool, osu_mode.clone(), &user.id).await;
+    ctx.users
+        .increase_score(user.id, &osu_mode, decrypted_score.total_score as i64)
+        .await;
+    ctx.users.increase_playcount(user.id, &osu_mode).await;
 
     if decrypted_score.max_combo > (stats_before.max_combo as u16) {
-        update_user_max_combo(
-            &ctx.pool,
-            &osu_mode.clone(),
-            &user.id,
-            decrypted_score.max_combo as i16,
-        )
-        .await;
+        ctx.users
+            .update_max_combo(user.id, &osu_mode, decrypted_score.max_combo as i16)
+            .await;
     }
 
-    let rank_before = get_rank(&ctx.redis, &user, &osu_mode).await.unwrap_or(0);
+    let rank_before = ctx
+        .rankings
+        .get_rank(&user, &osu_mode)
+        .await
+        .unwrap_or_default()
+        .unwrap_or(0);
 
-    recalculate_user_stats(&ctx.pool, &ctx.redis, &user, &osu_mode).await;
+    if let Err(error) = ctx.rankings.recalculate(&user, &osu_mode).await {
+        warn!("Failed to recalculate user stats: {:#?}", error);
+    }
 
-    let rank_after = get_rank(&ctx.redis, &user, &osu_mode).await.unwrap_or(0);
-    let stats_after = get_user_stats(&ctx.pool, &user.id, &osu_mode)
+    let rank_after = ctx
+        .rankings
+        .get_rank(&user, &osu_mode)
         .await
-        .unwrap();
+        .unwrap_or_default()
+        .unwrap_or(0);
+    let stats_after = ctx.users.get_stats(user.id, &osu_mode).await.unwrap();
 
     let beatmap_chart = Chart {
         chart_id: "beatmap".to_string(),
@@ -712,10 +668,7 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
     info!("new rank: {}", new_score.rank);
     info!("Score status: {}", new_score.score.status);
 
-    if let Ok(_redis) = ctx.redis.get_connection() {
-        // let _: Result<i32, redis::RedisError> = redis.publish("users:refresh", user.id.to_string());
-        send_bancho_message(&user.id, "user:refresh".to_string(), None).await;
-    }
+    send_bancho_message(&user.id, "user:refresh".to_string(), None).await;
 
     if new_score.rank == 1
         && !is_restricted(&user).await
@@ -723,23 +676,40 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
     {
         debug!("Sending announcement and checking for pp cap");
 
-        if is_cap_reached(&new_score) {
+        let cap_reached = is_cap_reached(&new_score);
+
+        if cap_reached {
             //Restricting user due to cap
-            insert_user_punishment(
+            let note = format!(
+                "Lia: user has reached pp cap (score_id: {}).",
+                new_score.score.id
+            );
+
+            ctx.punishments
+                .insert(
+                    "CRITICAL".to_string(),
+                    1,
+                    user.id,
+                    "RESTRICTION".to_string(),
+                    false,
+                    None,
+                    note.clone(),
+                )
+                .await;
+            ctx.users.restrict(user.id).await;
+
+            insert_audit_log(
                 &ctx.pool,
-                "CRITICAL".to_string(),
-                1,
-                user.id,
-                "RESTRICTION".to_string(),
-                false,
-                None,
-                format!(
-                    "Lia: user has reached pp cap (score_id: {}).",
-                    new_score.score.id
-                ),
+                AuditRecord {
+                    actor_id: None,
+                    action: "punishment.auto_restrict",
+                    target_id: Some(user.id),
+                    before: Some(json!({ "permissions": user.permissions })),
+                    after: Some(json!({ "permissions": 8, "performance": performance })),
+                    note: &note,
+                },
             )
             .await;
-            restrict_user(&ctx.pool, user.id).await;
         }
 
         let performance_string = beatmap
@@ -765,6 +735,12 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
             ctx.config.token_hmac_secret.clone(),
         )
         .await;
+
+        if let Some(old_first_place) = old_first_place_score.filter(|_| !cap_reached) {
+            if old_first_place.user.id != user.id {
+                notify_first_place_lost(&ctx, &old_first_place, &user, &beatmap, &osu_mode).await;
+            }
+        }
     }
 
     if new_score.score.status == 2 && new_score.score.playmode == 4 {
@@ -787,3 +763,70 @@ pub async fn submit_score(Extension(ctx): Extension<Arc<Context>>, data: Multipa
 
     Chart::build(&beatmap, beatmap_chart, overall_chart)
 }
+
+async fn notify_first_place_lost(
+    ctx: &Context,
+    old_first_place: &UserScoreWithBeatmap,
+    sniper: &User,
+    beatmap: &Beatmap,
+    mode: &OsuMode,
+) {
+    let holder = &old_first_place.user;
+
+    insert_activity(
+        &ctx.pool,
+        holder.id,
+        ActivityType::FirstPlaceLost,
+        Some(&beatmap.checksum),
+        Some(mode.to_osu()),
+        Some(sniper.id),
+    )
+    .await;
+
+    let preferences = get_user_preferences(&ctx.pool, holder.id).await;
+
+    if let Err(error) = preferences {
+        error!("{:#?}", error);
+        return;
+    }
+
+    if !preferences.unwrap().notify_first_place_lost {
+        return;
+    }
+
+    let message = format!(
+        "You lost #1 on [https://{}/b/{} {} - {} [{}]] ({}) to [https://{}/users/{} {}]!",
+        ctx.config.server_url,
+        beatmap.beatmap_id,
+        beatmap.artist,
+        beatmap.title,
+        beatmap.version,
+        mode.to_string(),
+        ctx.config.server_url,
+        sniper.id,
+        sniper.username
+    );
+
+    if send_bancho_pm(&ctx.config, &holder.username, message).await {
+        return;
+    }
+
+    let stored = insert_notification(
+        &ctx.pool,
+        &NewNotification {
+            user_id: holder.id,
+            kind: NotificationKind::FirstPlaceLost,
+            subject: beatmap.beatmap_id.to_string(),
+            text: format!(
+                "{} took your #1 on {} - {} [{}]",
+                sniper.username, beatmap.artist, beatmap.title, beatmap.version
+            ),
+            avatar_of: Some(sniper.id),
+        },
+    )
+    .await;
+
+    if let Err(error) = stored {
+        error!("{:#?}", error);
+    }
+}